@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayCoordinate;
+use uuid::Uuid;
+
+use crate::production::{self, ShotID};
+use crate::reports;
+use crate::shotliner_document::{
+    AnnotationMap, Group, GroupID, Tag, TagID, TaggedElement, TaggedElementID,
+};
+
+/// Anything in the AnnotationMap that sits at a position in the screenplay.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AnnotationKey {
+    Shot(ShotID),
+    TaggedElement(TaggedElementID),
+}
+
+/// An inclusive range of (page, line) positions.
+type LineRange = ((usize, usize), (usize, usize));
+
+/// A position-to-annotation index over the lines of the screenplay.
+///
+/// Every range is filed under each page it touches, so a query only looks at the annotations
+/// on the pages it covers, then checks their lines exactly.
+/// Ranges are compared by page and line; the element within a line is ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnnotationIndex {
+    ranges: HashMap<AnnotationKey, Vec<LineRange>>,
+    pages: BTreeMap<usize, HashSet<AnnotationKey>>,
+}
+impl AnnotationIndex {
+    pub fn new() -> Self {
+        AnnotationIndex::default()
+    }
+
+    pub fn from_annotation_map(annotation_map: &AnnotationMap) -> Self {
+        let mut index = AnnotationIndex::new();
+        for (id, shot) in &annotation_map.shotlines {
+            index.insert_shot(id, shot);
+        }
+        for (id, tagged_element) in &annotation_map.tagged_elements {
+            index.insert_tagged_element(id, tagged_element);
+        }
+        index
+    }
+
+    /// Files `key` under `ranges`, replacing wherever it was before.
+    fn insert(&mut self, key: AnnotationKey, ranges: Vec<LineRange>) {
+        self.remove(&key);
+        if ranges.is_empty() {
+            return;
+        }
+        for (start, end) in &ranges {
+            for page in start.0..=end.0 {
+                self.pages.entry(page).or_default().insert(key.clone());
+            }
+        }
+        self.ranges.insert(key, ranges);
+    }
+
+    pub fn remove(&mut self, key: &AnnotationKey) {
+        let Some(ranges) = self.ranges.remove(key) else {
+            return;
+        };
+        for (start, end) in ranges {
+            for page in start.0..=end.0 {
+                if let Some(keys) = self.pages.get_mut(&page) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.pages.remove(&page);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Indexes a shot by its ShotLine. Shots without one aren't anywhere, so they're only removed.
+    pub fn insert_shot(&mut self, id: &ShotID, shot: &production::Shot) {
+        let ranges = shot
+            .shotline
+            .iter()
+            .map(|shotline| {
+                (
+                    (shotline.start.page, shotline.start.line),
+                    (shotline.end.page, shotline.end.line),
+                )
+            })
+            .collect();
+        self.insert(AnnotationKey::Shot(id.clone()), ranges);
+    }
+
+    pub fn insert_tagged_element(&mut self, id: &TaggedElementID, tagged_element: &TaggedElement) {
+        let ranges = reports::get_tagged_element_ranges(tagged_element)
+            .into_iter()
+            .map(|(start, end)| ((start.page, start.line), (end.page, end.line)))
+            .collect();
+        self.insert(AnnotationKey::TaggedElement(id.clone()), ranges);
+    }
+
+    /// Every annotation with a range overlapping the lines from `start` to `end` (inclusive).
+    pub fn query(
+        &self,
+        start: &ScreenplayCoordinate,
+        end: &ScreenplayCoordinate,
+    ) -> HashSet<&AnnotationKey> {
+        let (start, end) = ((start.page, start.line), (end.page, end.line));
+        let mut found: HashSet<&AnnotationKey> = HashSet::new();
+        if start > end {
+            return found;
+        }
+        for (_, keys) in self.pages.range(start.0..=end.0) {
+            for key in keys {
+                if found.contains(key) {
+                    continue;
+                }
+                let overlaps = self.ranges[key]
+                    .iter()
+                    .any(|(range_start, range_end)| *range_start <= end && *range_end >= start);
+                if overlaps {
+                    found.insert(key);
+                }
+            }
+        }
+        found
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+/// Everything found at a position in the screenplay, each kind in script order.
+pub struct AnnotationQueryResult<'a> {
+    pub shots: Vec<(&'a ShotID, &'a production::Shot)>,
+    pub tagged_elements: Vec<(&'a TaggedElementID, &'a TaggedElement)>,
+    /// Tags on the tagged elements found, then tags on the shots' compositions, without duplicates.
+    pub tags: Vec<(&'a TagID, &'a Tag)>,
+}
+impl<'a> AnnotationQueryResult<'a> {
+    pub(crate) fn from_keys(
+        annotation_map: &'a AnnotationMap,
+        keys: HashSet<&AnnotationKey>,
+    ) -> Self {
+        let mut shots: Vec<(&ShotID, &production::Shot)> = Vec::new();
+        let mut tagged_elements: Vec<(&TaggedElementID, &TaggedElement)> = Vec::new();
+        for key in keys {
+            match key {
+                AnnotationKey::Shot(id) => {
+                    if let Some((id, shot)) = annotation_map.shotlines.get_key_value(id) {
+                        shots.push((id, shot));
+                    }
+                }
+                AnnotationKey::TaggedElement(id) => {
+                    if let Some((id, element)) = annotation_map.tagged_elements.get_key_value(id) {
+                        tagged_elements.push((id, element));
+                    }
+                }
+            }
+        }
+        shots.sort_by(|(a_id, a), (b_id, b)| {
+            let start = |shot: &production::Shot| {
+                shot.shotline
+                    .as_ref()
+                    .map(|sl| (sl.start.page, sl.start.line, sl.end.page, sl.end.line))
+            };
+            start(a).cmp(&start(b)).then_with(|| a_id.cmp(b_id))
+        });
+        tagged_elements.sort_by(|(a_id, a), (b_id, b)| {
+            (a.origin.page, a.origin.line, a.origin.element)
+                .cmp(&(b.origin.page, b.origin.line, b.origin.element))
+                .then_with(|| a_id.cmp(b_id))
+        });
+
+        let mut tags: Vec<(&TagID, &Tag)> = Vec::new();
+        let element_tags = tagged_elements
+            .iter()
+            .flat_map(|(_, element)| element.tags.iter());
+        let shot_tags = shots.iter().flat_map(|(_, shot)| {
+            shot.primary_composition.tags.iter().chain(
+                shot.sub_compositions
+                    .iter()
+                    .flat_map(|subs| subs.values())
+                    .flat_map(|composition| composition.tags.iter()),
+            )
+        });
+        for tag_id in element_tags.chain(shot_tags) {
+            if tags.iter().any(|(id, _)| *id == tag_id) {
+                continue;
+            }
+            if let Some(entry) = annotation_map.tags.get_key_value(tag_id) {
+                tags.push(entry);
+            }
+        }
+
+        AnnotationQueryResult {
+            shots: shots,
+            tagged_elements: tagged_elements,
+            tags: tags,
+        }
+    }
+}
+
+/// Somewhere a TagID is referenced.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TagUsage {
+    TaggedElement(TaggedElementID),
+    /// A shot's primary composition.
+    Shot(ShotID),
+    /// The sub-composition of a shot that starts at this coordinate.
+    SubComposition(ShotID, ScreenplayCoordinate),
+    Group(GroupID),
+}
+impl TagUsage {
+    /// Orders usages by kind, then by ID, so listings come out the same every time.
+    pub fn sort_key(&self) -> (u8, Uuid, (usize, usize, Option<u64>)) {
+        match self {
+            TagUsage::TaggedElement(id) => (0, **id, (0, 0, None)),
+            TagUsage::Shot(id) => (1, **id, (0, 0, None)),
+            TagUsage::SubComposition(id, coord) => {
+                (1, **id, (coord.page, coord.line, coord.element))
+            }
+            TagUsage::Group(id) => (2, **id, (0, 0, None)),
+        }
+    }
+}
+
+/// The annotations that hold TagIDs, which get re-filed whenever they change.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum TagHolder {
+    Shot(ShotID),
+    TaggedElement(TaggedElementID),
+    Group(GroupID),
+}
+
+/// A reverse index from each TagID to everything that references it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TagUsageIndex {
+    usages: HashMap<TagID, HashSet<TagUsage>>,
+    filed: HashMap<TagHolder, Vec<(TagID, TagUsage)>>,
+}
+impl TagUsageIndex {
+    pub fn new() -> Self {
+        TagUsageIndex::default()
+    }
+
+    pub fn from_annotation_map(annotation_map: &AnnotationMap) -> Self {
+        let mut index = TagUsageIndex::new();
+        for (id, shot) in &annotation_map.shotlines {
+            index.insert_shot(id, shot);
+        }
+        for (id, tagged_element) in &annotation_map.tagged_elements {
+            index.insert_tagged_element(id, tagged_element);
+        }
+        for (id, group) in &annotation_map.groups {
+            index.insert_group(id, group);
+        }
+        index
+    }
+
+    /// Files everything `holder` references, replacing whatever it referenced before.
+    fn file(&mut self, holder: TagHolder, references: Vec<(TagID, TagUsage)>) {
+        self.unfile(&holder);
+        if references.is_empty() {
+            return;
+        }
+        for (tag_id, usage) in &references {
+            self.usages
+                .entry(tag_id.clone())
+                .or_default()
+                .insert(usage.clone());
+        }
+        self.filed.insert(holder, references);
+    }
+
+    fn unfile(&mut self, holder: &TagHolder) {
+        let Some(references) = self.filed.remove(holder) else {
+            return;
+        };
+        for (tag_id, usage) in references {
+            if let Some(usages) = self.usages.get_mut(&tag_id) {
+                usages.remove(&usage);
+                if usages.is_empty() {
+                    self.usages.remove(&tag_id);
+                }
+            }
+        }
+    }
+
+    pub fn insert_shot(&mut self, id: &ShotID, shot: &production::Shot) {
+        let mut references: Vec<(TagID, TagUsage)> = shot
+            .primary_composition
+            .tags
+            .iter()
+            .map(|tag_id| (tag_id.clone(), TagUsage::Shot(id.clone())))
+            .collect();
+        for (coord, composition) in shot.sub_compositions.iter().flatten() {
+            for tag_id in &composition.tags {
+                references.push((
+                    tag_id.clone(),
+                    TagUsage::SubComposition(id.clone(), coord.clone()),
+                ));
+            }
+        }
+        self.file(TagHolder::Shot(id.clone()), references);
+    }
+    pub fn remove_shot(&mut self, id: &ShotID) {
+        self.unfile(&TagHolder::Shot(id.clone()));
+    }
+
+    pub fn insert_tagged_element(&mut self, id: &TaggedElementID, tagged_element: &TaggedElement) {
+        let references = tagged_element
+            .tags
+            .iter()
+            .map(|tag_id| (tag_id.clone(), TagUsage::TaggedElement(id.clone())))
+            .collect();
+        self.file(TagHolder::TaggedElement(id.clone()), references);
+    }
+    pub fn remove_tagged_element(&mut self, id: &TaggedElementID) {
+        self.unfile(&TagHolder::TaggedElement(id.clone()));
+    }
+
+    pub fn insert_group(&mut self, id: &GroupID, group: &Group) {
+        let references = group
+            .tags
+            .iter()
+            .map(|tag_id| (tag_id.clone(), TagUsage::Group(id.clone())))
+            .collect();
+        self.file(TagHolder::Group(id.clone()), references);
+    }
+    pub fn remove_group(&mut self, id: &GroupID) {
+        self.unfile(&TagHolder::Group(id.clone()));
+    }
+
+    /// Everything referencing `tag_id`, in `TagUsage::sort_key` order.
+    pub fn usages(&self, tag_id: &TagID) -> Vec<&TagUsage> {
+        let mut usages: Vec<&TagUsage> = self
+            .usages
+            .get(tag_id)
+            .map(|usages| usages.iter().collect())
+            .unwrap_or_default();
+        usages.sort_by_key(|usage| usage.sort_key());
+        usages
+    }
+}
@@ -1,16 +1,26 @@
-use std::{collections::VecDeque, fmt::Error};
+use std::collections::VecDeque;
 
 use uuid::Uuid;
 
-use crate::shotliner_document::{Tag, TagID};
+use crate::shotliner_document::{Group, GroupID, Tag, TagID, TaggedElement, TaggedElementID};
 use crate::production;
+
+pub const DEFAULT_MAX_HISTORY_SIZE: u64 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CommandHistoryStatus {
     ExecuteSuccess,
     UndoSuccess,
+    RedoSuccess,
     UndoLimitReached,
     RedoLimitReached,
 }
 
+/// Stores every executed `Command` so it can be undone and redone.
+///
+/// `index` always points one past the most recently applied command;
+/// everything from `index` onwards is the redo tail.
+#[derive(Clone, Debug)]
 pub struct CommandHistory {
     pub index: u64,
     pub history: VecDeque<Command>,
@@ -18,34 +28,105 @@ pub struct CommandHistory {
     pub current_history_size: u64,
 }
 impl CommandHistory {
-    fn execute(&mut self) -> Result<CommandHistoryStatus, Error> {
-        if self.index < self.max_history_size {
-            self.index = self.index + 1;
-            return Ok(CommandHistoryStatus::ExecuteSuccess);
-        } else {
-            return Err(Error);
+    pub fn new(max_history_size: u64) -> Self {
+        CommandHistory {
+            index: 0,
+            history: VecDeque::new(),
+            max_history_size: max_history_size,
+            current_history_size: 0,
         }
     }
-    fn undo(&mut self) {
-        if self.index > 0 {
-            self.index = self.index - 1;
-        } else {
-            // TODO:
+
+    pub fn can_undo(&self) -> bool {
+        self.index > 0
+    }
+    pub fn can_redo(&self) -> bool {
+        self.index < self.current_history_size
+    }
+
+    /// Pushes an already-applied command, dropping the redo tail
+    /// and evicting the oldest entries past `max_history_size`.
+    pub(crate) fn push(&mut self, cmd: Command) {
+        self.history.truncate(self.index as usize);
+        self.history.push_back(cmd);
+
+        while self.history.len() as u64 > self.max_history_size {
+            self.history.pop_front();
         }
+        self.current_history_size = self.history.len() as u64;
+        self.index = self.current_history_size;
     }
-    fn redo(&mut self) {
-        if self.index + 1 <= self.current_history_size {
-            self.index = self.index + 1;
+
+    /// Takes the next command to undo out of the history.
+    ///
+    /// Must be handed back with `return_undone` or `return_failed_undo`.
+    pub(crate) fn take_undo(&mut self) -> Option<Command> {
+        if !self.can_undo() {
+            return None;
         }
+        self.index = self.index - 1;
+        self.history.remove(self.index as usize)
+    }
+    pub(crate) fn return_undone(&mut self, cmd: Command) {
+        self.history.insert(self.index as usize, cmd);
+    }
+    pub(crate) fn return_failed_undo(&mut self, cmd: Command) {
+        self.history.insert(self.index as usize, cmd);
+        self.index = self.index + 1;
+    }
+
+    /// Takes the next command to redo out of the history.
+    ///
+    /// Must be handed back with `return_redone` or `return_failed_redo`.
+    pub(crate) fn take_redo(&mut self) -> Option<Command> {
+        if !self.can_redo() {
+            return None;
+        }
+        self.history.remove(self.index as usize)
+    }
+    pub(crate) fn return_redone(&mut self, cmd: Command) {
+        self.history.insert(self.index as usize, cmd);
+        self.index = self.index + 1;
+    }
+    pub(crate) fn return_failed_redo(&mut self, cmd: Command) {
+        self.history.insert(self.index as usize, cmd);
+    }
+}
+impl Default for CommandHistory {
+    fn default() -> Self {
+        CommandHistory::new(DEFAULT_MAX_HISTORY_SIZE)
     }
 }
 
+/// A reversible edit to the `AnnotationMap`.
+///
+/// The `Option` slots of the Modify and Remove variants hold "the other value":
+/// Modify carries the NEW value until it is executed, then the OLD value until it is undone,
+/// and Remove is filled with the removed value when executed.
+#[derive(Clone, Debug)]
 pub enum Command {
     AddShotline(production::ShotID, production::Shot),
-    ModifyShotline(production::ShotID, Option<production::Shot>), // takes ID, old shotline
+    ModifyShotline(production::ShotID, Option<production::Shot>), // takes ID, new shotline (old shotline once executed)
     RemoveShotline(production::ShotID, Option<production::Shot>), // takes ID, old shotline
 
     AddTag(TagID, Tag),
     ModifyTag(TagID, Option<Tag>),
     RemoveTag(TagID, Option<Tag>),
+
+    AddTaggedElement(TaggedElementID, TaggedElement),
+    ModifyTaggedElement(TaggedElementID, Option<TaggedElement>),
+    RemoveTaggedElement(TaggedElementID, Option<TaggedElement>),
+
+    AddGroup(GroupID, Group),
+    ModifyGroup(GroupID, Option<Group>),
+    RemoveGroup(GroupID, Option<Group>),
+
+    AddShotSetup(Uuid, production::ShotSetup),
+    ModifyShotSetup(Uuid, Option<production::ShotSetup>),
+    RemoveShotSetup(Uuid, Option<production::ShotSetup>),
+
+    /// Applies every command in order as one atomic edit, which is also undone and redone as a single history entry.
+    ///
+    /// If any command fails, everything already applied by the batch is rolled back.
+    Batch(Vec<Command>),
 }
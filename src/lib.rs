@@ -24,6 +24,10 @@ pub mod multimedia;
 /// This is where you go to generate Shot Lists and Strip Boards, among other things...
 pub mod reports;
 
+/// Indexes over the AnnotationMap: the shots and tagged elements at a position in the screenplay,
+/// and everything that references a tag.
+pub mod annotation_index;
+
 /// The ShotLiner document itself.
 /// 
 /// This contains the ScreenplayDocument and AnnotationMap.
@@ -35,6 +39,26 @@ pub mod shotliner_document;
 
 pub mod commands;
 
+/// Checks the AnnotationMap for references and coordinates that don't point at anything,
+/// and repairs the ones that can be fixed without a human deciding what was meant.
+pub mod validation;
+
+/// Carries annotations over to a new draft of the screenplay, by lining up the text of the two drafts.
+pub mod merge_forward;
+
+/// Positions in the screenplay remembered by their content, which can be found again after the script reflows.
+pub mod smart_coordinate;
+
+/// Colored revisions of a production draft, its locked page numbers (with A-pages), and which lines each revision changed.
+pub mod revisions;
+
+/// Automatic shot numbering, and finding shot numbers that are duplicated or out of order.
+pub mod shot_numbering;
+
+/// The text command language used by the `shotliner` binary,
+/// for both its interactive shell and batch scripts.
+pub mod shell;
+
 #[cfg(test)]
 mod tests {
     use core::panic;
@@ -46,11 +70,1898 @@ mod tests {
     };
 
     use crate::{
+        annotation_index::{AnnotationIndex, AnnotationKey, TagUsage, TagUsageIndex},
+        commands::{Command, CommandHistory, CommandHistoryStatus},
+        reports,
+        serializables::sl_file::{self, SlFileError},
+        shell::{self, Shell, ShellCommand, ShellError},
+        validation::{self, AnnotationProblem, CoordinatePlace},
+        merge_forward::MergeOutcome,
+        revisions::{ProductionPageNumber, RevisionColor, RevisionSet, Revisions},
+        shot_numbering::{NumberingMode, NumberingProblem, NumberingScheme, ShotNumbering},
+        smart_coordinate::SmartScreenplayCoordinate,
         production::{self, ShotComposition, ShotNumber}, shotliner_document::{
-            AnnotationMap, ShotLine,  ShotlinerDoc, Tag, TagID, TaggedElement, TaggedElementID
+            AnnotationMap, Group, GroupID, ShotLine,  ShotlinerDoc, Tag, TagID, TaggedElement, TaggedElementID
         }
     };
 
+    fn coord(page: usize, line: usize) -> ScreenplayCoordinate {
+        element_coord(page, line, None)
+    }
+
+    fn element_coord(page: usize, line: usize, element: Option<u64>) -> ScreenplayCoordinate {
+        ScreenplayCoordinate {
+            page: page,
+            line: line,
+            element: element,
+        }
+    }
+
+    /// A shot with nothing but a ShotLine.
+    fn shot_at(start: ScreenplayCoordinate, end: ScreenplayCoordinate) -> production::Shot {
+        let mut shot = production::Shot::new();
+        shot.shotline = Some(ShotLine::new(start, end));
+        shot
+    }
+
+    #[test]
+    fn test_undo_redo_commands() {
+        let mut doc = ShotlinerDoc::new(screenplay_document::ScreenplayDocument::new());
+
+        let tag_id = TagID::new();
+        let tag = Tag {
+            string: "Prop1".to_string(),
+            departments: vec![production::Department::Props],
+        };
+        let shot_id = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        shot.shot_number = Some(ShotNumber("1".to_string()));
+
+        doc.execute(Command::AddTag(tag_id.clone(), tag.clone())).unwrap();
+        doc.execute(Command::AddShotline(shot_id.clone(), shot.clone())).unwrap();
+
+        let mut modified_shot = shot.clone();
+        modified_shot.shot_number = Some(ShotNumber("1A".to_string()));
+        doc.execute(Command::ModifyShotline(shot_id.clone(), Some(modified_shot)))
+            .unwrap();
+        doc.execute(Command::RemoveTag(tag_id.clone(), None)).unwrap();
+
+        assert!(doc.annotation_map.tags.is_empty());
+        assert_eq!(
+            doc.annotation_map.shotlines[&shot_id].shot_number.as_ref().unwrap().0,
+            "1A"
+        );
+
+        assert_eq!(doc.undo(), Ok(CommandHistoryStatus::UndoSuccess));
+        assert_eq!(doc.annotation_map.tags.get(&tag_id), Some(&tag));
+
+        assert_eq!(doc.undo(), Ok(CommandHistoryStatus::UndoSuccess));
+        assert_eq!(
+            doc.annotation_map.shotlines[&shot_id].shot_number.as_ref().unwrap().0,
+            "1"
+        );
+
+        assert_eq!(doc.redo(), Ok(CommandHistoryStatus::RedoSuccess));
+        assert_eq!(
+            doc.annotation_map.shotlines[&shot_id].shot_number.as_ref().unwrap().0,
+            "1A"
+        );
+
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert!(doc.annotation_map.shotlines.is_empty());
+        assert!(doc.annotation_map.tags.is_empty());
+        assert_eq!(doc.undo(), Ok(CommandHistoryStatus::UndoLimitReached));
+
+        // executing a new command truncates the redo tail
+        doc.execute(Command::AddTag(tag_id.clone(), tag.clone())).unwrap();
+        assert_eq!(doc.redo(), Ok(CommandHistoryStatus::RedoLimitReached));
+        assert_eq!(doc.command_history.as_ref().unwrap().current_history_size, 1);
+
+        // failed commands are never pushed onto the history
+        assert!(doc.execute(Command::AddTag(tag_id.clone(), tag.clone())).is_err());
+        assert_eq!(doc.command_history.as_ref().unwrap().current_history_size, 1);
+
+        // the oldest entries are evicted once max_history_size is reached
+        doc.command_history = Some(CommandHistory::new(2));
+        for _ in 0..3 {
+            doc.execute(Command::AddTag(TagID::new(), tag.clone())).unwrap();
+        }
+        assert_eq!(doc.command_history.as_ref().unwrap().current_history_size, 2);
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.undo(), Ok(CommandHistoryStatus::UndoLimitReached));
+        assert_eq!(doc.annotation_map.tags.len(), 2);
+    }
+
+    #[test]
+    fn test_undo_redo_element_group_and_setup_commands() {
+        let mut doc = ShotlinerDoc::new(screenplay_document::ScreenplayDocument::new());
+
+        let te_id = TaggedElementID::new();
+        let tagged_element = TaggedElement {
+            ocurrances: HashSet::new(),
+            origin: element_coord(1, 1, Some(0)),
+            endpoint: element_coord(1, 1, Some(2)),
+            tags: Vec::new(),
+            anchors: None,
+        };
+        let mut moved_element = tagged_element.clone();
+        moved_element.endpoint.element = Some(4);
+
+        let group_id = GroupID::new();
+        let setup_id = uuid::Uuid::new_v4();
+        let setup = production::ShotSetup { index: 1, id: setup_id.to_string() };
+
+        doc.execute(Command::AddTaggedElement(te_id.clone(), tagged_element)).unwrap();
+        doc.execute(Command::ModifyTaggedElement(te_id.clone(), Some(moved_element)))
+            .unwrap();
+        doc.execute(Command::AddGroup(group_id.clone(), Group::new("Day 1".to_string())))
+            .unwrap();
+        doc.execute(Command::ModifyGroup(group_id.clone(), Some(Group::new("Day 2".to_string()))))
+            .unwrap();
+        doc.execute(Command::AddShotSetup(setup_id, setup)).unwrap();
+        doc.execute(Command::RemoveShotSetup(setup_id, None)).unwrap();
+
+        assert!(doc.annotation_map.shot_setups.is_empty());
+        assert_eq!(doc.annotation_map.groups[&group_id].string, "Day 2");
+        assert_eq!(doc.annotation_map.tagged_elements[&te_id].endpoint.element, Some(4));
+
+        doc.undo().unwrap();
+        assert_eq!(doc.annotation_map.shot_setups[&setup_id].index, 1);
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.annotation_map.groups[&group_id].string, "Day 1");
+        doc.undo().unwrap();
+        assert!(doc.annotation_map.groups.is_empty());
+        doc.undo().unwrap();
+        assert_eq!(doc.annotation_map.tagged_elements[&te_id].endpoint.element, Some(2));
+        doc.undo().unwrap();
+        assert!(doc.annotation_map.tagged_elements.is_empty());
+
+        for _ in 0..6 {
+            assert_eq!(doc.redo(), Ok(CommandHistoryStatus::RedoSuccess));
+        }
+        assert!(doc.annotation_map.shot_setups.is_empty());
+        assert_eq!(doc.annotation_map.groups[&group_id].string, "Day 2");
+        assert_eq!(doc.annotation_map.tagged_elements[&te_id].endpoint.element, Some(4));
+
+        // modifying or removing something that doesn't exist fails without touching the map
+        assert!(doc
+            .execute(Command::RemoveTaggedElement(TaggedElementID::new(), None))
+            .is_err());
+        assert_eq!(doc.annotation_map.tagged_elements.len(), 1);
+    }
+
+    #[test]
+    fn test_batch_commands() {
+        let mut doc = ShotlinerDoc::new(screenplay_document::ScreenplayDocument::new());
+
+        let tag_id = TagID::new();
+        let other_tag_id = TagID::new();
+        let tag = Tag {
+            string: "Prop1".to_string(),
+            departments: vec![production::Department::Props],
+        };
+        let te_id = TaggedElementID::new();
+        let tagged_element = TaggedElement {
+            ocurrances: HashSet::new(),
+            origin: element_coord(1, 1, Some(0)),
+            endpoint: element_coord(1, 1, Some(2)),
+            tags: vec![tag_id.clone(), other_tag_id.clone()],
+            anchors: None,
+        };
+        let shot_id = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        shot.primary_composition.add_tag(&tag_id).unwrap();
+
+        doc.execute_batch(vec![
+            Command::AddTag(tag_id.clone(), tag.clone()),
+            Command::AddTag(other_tag_id.clone(), tag.clone()),
+            Command::AddTaggedElement(te_id.clone(), tagged_element),
+            Command::AddShotline(shot_id.clone(), shot),
+        ])
+        .unwrap();
+        assert_eq!(doc.command_history.as_ref().unwrap().current_history_size, 1);
+
+        // a failing step rolls back everything before it
+        let result = doc.execute_batch(vec![
+            Command::RemoveTag(other_tag_id.clone(), None),
+            Command::RemoveShotline(shot_id.clone(), None),
+            Command::RemoveShotline(production::ShotID::new(), None),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(doc.annotation_map.tags.len(), 2);
+        assert_eq!(doc.annotation_map.shotlines.len(), 1);
+        assert_eq!(doc.command_history.as_ref().unwrap().current_history_size, 1);
+
+        doc.delete_tag(&tag_id).unwrap();
+        assert!(!doc.annotation_map.tags.contains_key(&tag_id));
+        assert_eq!(doc.annotation_map.tagged_elements[&te_id].tags, vec![other_tag_id.clone()]);
+        assert!(doc.annotation_map.shotlines[&shot_id].primary_composition.tags.is_empty());
+
+        doc.undo().unwrap();
+        assert!(doc.annotation_map.tags.contains_key(&tag_id));
+        assert_eq!(doc.annotation_map.tagged_elements[&te_id].tags.len(), 2);
+        assert_eq!(doc.annotation_map.shotlines[&shot_id].primary_composition.tags, vec![tag_id.clone()]);
+
+        doc.undo().unwrap();
+        assert!(doc.annotation_map.tags.is_empty());
+        assert!(doc.annotation_map.tagged_elements.is_empty());
+        assert!(doc.annotation_map.shotlines.is_empty());
+
+        doc.redo().unwrap();
+        doc.redo().unwrap();
+        assert_eq!(doc.annotation_map.tags.len(), 1);
+        assert_eq!(doc.annotation_map.tagged_elements[&te_id].tags, vec![other_tag_id]);
+    }
+
+    #[test]
+    fn test_sl_file_round_trip() {
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Doc failed to load.");
+        };
+
+        let tag_id = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "Betamax".to_string(),
+                departments: vec![
+                    production::Department::Props,
+                    production::Department::Other("Electronics".to_string()),
+                ],
+            },
+            tag_id.clone(),
+        )
+        .unwrap();
+
+        let mut shot = production::Shot::new();
+        shot.shot_number = Some(ShotNumber("1A".to_string()));
+        shot.primary_composition.add_tag(&tag_id).unwrap();
+        shot.primary_composition.subtype(Some(production::ShotSubType::Dolly));
+        let mut shotline = ShotLine::new(
+            coord(1, 2),
+            coord(2, 4),
+        );
+        shotline.unfilmed_lines = Some(HashSet::from([coord(1, 3)]));
+        shot.shotline = Some(shotline);
+        let mut close_up = ShotComposition::new();
+        close_up.shot_type(production::ShotType::CloseUp);
+        shot.sub_compositions = Some(std::collections::HashMap::from([(
+            coord(1, 5),
+            close_up,
+        )]));
+        doc.add_shotline(shot, production::ShotID::new()).unwrap();
+
+        doc.add_tagged_element(
+            TaggedElementID::new(),
+            TaggedElement {
+                ocurrances: HashSet::from([(
+                    element_coord(1, 1, Some(0)),
+                    element_coord(1, 1, Some(1)),
+                )]),
+                origin: element_coord(1, 1, Some(0)),
+                endpoint: element_coord(1, 1, Some(1)),
+                tags: vec![tag_id.clone()],
+                anchors: None,
+            },
+        )
+        .unwrap();
+        doc.add_group(GroupID::new(), Group::new("Day 1".to_string())).unwrap();
+        doc.add_shot_setup(uuid::Uuid::new_v4(), production::ShotSetup { index: 1, id: "A".to_string() })
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("shotliner_test_{}.sl", uuid::Uuid::new_v4()));
+        doc.save(&path).unwrap();
+        let loaded = ShotlinerDoc::load(&path);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let Ok(loaded) = loaded else {
+            panic!("Failed to load saved document: {:?}", loaded.err());
+        };
+        assert_eq!(loaded.annotation_map, doc.annotation_map);
+        // the screenplay is found through the saved relative path, from the temp directory
+        let canonical = |filepath: &Option<String>| {
+            std::fs::canonicalize(filepath.as_ref().unwrap()).unwrap()
+        };
+        assert_eq!(canonical(&loaded.screenplay_filepath), canonical(&doc.screenplay_filepath));
+        assert_eq!(loaded.screenplay.pages.len(), doc.screenplay.pages.len());
+
+        // a file from the future
+        let future = contents.replacen(
+            &format!("\"format_version\": {}", sl_file::CURRENT_FORMAT_VERSION),
+            "\"format_version\": 9999",
+            1,
+        );
+        assert!(matches!(sl_file::from_str(&future), Err(SlFileError::UnknownVersion(9999))));
+
+        assert!(matches!(sl_file::from_str("{ not json"), Err(SlFileError::Corrupt(_))));
+        assert!(matches!(sl_file::from_str("{}"), Err(SlFileError::NotAShotlinerFile)));
+        let truncated = contents.replacen("\"annotations\"", "\"annotationz\"", 1);
+        assert!(matches!(sl_file::from_str(&truncated), Err(SlFileError::Corrupt(_))));
+
+        // the referenced screenplay must be the one the document was saved with
+        let path = std::env::temp_dir().join(format!("shotliner_test_{}.sl", uuid::Uuid::new_v4()));
+        std::fs::write(&path, &contents).unwrap();
+        let mismatched = sl_file::load_from_file_with(&path, |_| {
+            Ok(screenplay_document::ScreenplayDocument::new())
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(mismatched, Err(SlFileError::ScreenplayMismatch)));
+    }
+
+    #[test]
+    fn test_sl_file_moved_with_screenplay() {
+        // a project folder with the screenplay in a subfolder, saved then moved somewhere else
+        let folder = std::env::temp_dir().join(format!("shotliner_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(folder.join("scripts")).unwrap();
+        let pdf = folder.join("scripts").join("VCR2L.pdf");
+        std::fs::copy("test_data/VCR2L.pdf", &pdf).unwrap();
+
+        let Ok(doc) = ShotlinerDoc::from_pdf(pdf.to_str().unwrap()) else {
+            panic!("Doc failed to load.");
+        };
+        let path = folder.join("project.sl");
+        doc.save(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let Ok((reference, _, _, _)) = sl_file::from_str(&contents) else {
+            panic!("Failed to read saved document.");
+        };
+        assert_eq!(std::path::Path::new(&reference.filepath), std::path::Path::new("scripts/VCR2L.pdf"));
+
+        let moved = std::env::temp_dir().join(format!("shotliner_test_{}", uuid::Uuid::new_v4()));
+        std::fs::rename(&folder, &moved).unwrap();
+        let loaded = ShotlinerDoc::load(moved.join("project.sl"));
+        let expected = std::fs::canonicalize(moved.join("scripts").join("VCR2L.pdf")).unwrap();
+        std::fs::remove_dir_all(&moved).unwrap();
+
+        let Ok(loaded) = loaded else {
+            panic!("Failed to load moved document: {:?}", loaded.err());
+        };
+        assert_eq!(loaded.screenplay_filepath, Some(expected.to_string_lossy().into_owned()));
+        assert_eq!(loaded.screenplay.pages.len(), doc.screenplay.pages.len());
+    }
+
+    #[test]
+    fn test_sl_file_migrations() {
+        // Every historical format version must still load as the current structs
+        let fixtures = [
+            (1, include_str!("../test_data/sl_fixtures/v1.sl")),
+            (2, include_str!("../test_data/sl_fixtures/v2.sl")),
+        ];
+        assert_eq!(fixtures.len() as u64, sl_file::CURRENT_FORMAT_VERSION);
+
+        let mut loaded = Vec::new();
+        for (version, fixture) in fixtures {
+            let Ok((reference, annotation_map, _, _)) = sl_file::from_str(fixture) else {
+                panic!("Failed to load version {} fixture.", version);
+            };
+            assert_eq!(reference.filepath, "test_data/VCR2L.pdf");
+            assert_eq!(annotation_map.shotlines.len(), 1);
+            assert_eq!(annotation_map.tagged_elements.len(), 1);
+            assert_eq!(annotation_map.shot_setups.len(), 1);
+            loaded.push(annotation_map);
+        }
+
+        // v1 -> v2: embedded group tags became TagIDs, reusing existing tags where they match
+        let v1 = &loaded[0];
+        let group = v1.groups.values().next().unwrap();
+        assert_eq!(group.tags.len(), 2);
+        assert_eq!(v1.tags.len(), 3);
+        let mut group_tag_strings: Vec<&String> =
+            group.tags.iter().map(|id| &v1.tags[id].string).collect();
+        group_tag_strings.sort();
+        assert_eq!(group_tag_strings, vec!["Guy's Phone", "Yard Sale Table"]);
+        let v2 = &loaded[1];
+        assert!(v1.tags.iter().any(|(id, tag)| v2.tags.get(id) == Some(tag) && tag.string == "Yard Sale Table"));
+        assert_eq!(v1.shotlines, v2.shotlines);
+
+        assert!(matches!(
+            crate::serializables::migrations::migrate(serde_json::Value::Null, 0),
+            Err(SlFileError::UnknownVersion(0))
+        ));
+    }
+
+    #[test]
+    fn test_shell_commands() {
+        let Ok(mut shell) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf").map(Shell::new) else {
+            panic!("Couldn't open test screenplay.");
+        };
+
+        assert_eq!(
+            shell::parse_line("add shotline 1 2 1 6 # a comment"),
+            Ok(Some(ShellCommand::AddShotline(
+                coord(1, 2),
+                coord(1, 6),
+            )))
+        );
+        assert_eq!(shell::parse_line("   "), Ok(None));
+        assert!(matches!(shell::parse_line("add shotline 1 x"), Err(ShellError::Parse(_))));
+        assert!(matches!(shell::parse_line("add tag \"Betty"), Err(ShellError::Parse(_))));
+
+        shell.run_line("add shotline 2 3 2 9").unwrap();
+        shell.run_line("add shotline 1 2 1 6").unwrap();
+        shell.run_line("add tag \"Yard Sale Table\" props art").unwrap();
+        shell.run_line("modify shotline 0 type cu").unwrap();
+        shell.run_line("modify shotline 0 number 1A").unwrap();
+        shell.run_line("modify shotline 0 tag add 0").unwrap();
+        assert!(shell.run_line("modify shotline 0 tag add 0").is_err());
+        assert!(shell.run_line("modify shotline 5 type cu").is_err());
+        assert!(shell.run_line("add shotline 1 6 1 2").is_err());
+
+        // shotlines are listed in screenplay order, not insertion order
+        let first_id = shell.ordered_shot_ids()[0].clone();
+        let first = &shell.doc.annotation_map.shotlines[&first_id];
+        assert_eq!(first.shotline.as_ref().unwrap().start.page, 1);
+        assert_eq!(first.primary_composition.shot_type, production::ShotType::CloseUp);
+        assert_eq!(first.shot_number, Some(ShotNumber("1A".to_string())));
+        assert_eq!(first.primary_composition.tags.len(), 1);
+
+        let listed = shell.run_line("list shotlines").unwrap().unwrap();
+        assert!(listed.lines().next().unwrap().contains("[1A] 1:2 -> 1:6 CloseUp | tags: Yard Sale Table"));
+
+        shell.run_line("undo").unwrap();
+        let first = &shell.doc.annotation_map.shotlines[&first_id];
+        assert!(first.primary_composition.tags.is_empty());
+        shell.run_line("redo").unwrap();
+
+        // removing a tag also removes it from the shots that use it
+        shell.run_line("remove tag 0").unwrap();
+        assert!(shell.doc.annotation_map.shotlines[&first_id].primary_composition.tags.is_empty());
+
+        let page = shell.run_line("go page 2").unwrap().unwrap();
+        assert!(page.starts_with(&format!("--- page 2 of {} (numbered 2) ---", shell.doc.screenplay.pages.len() - 1)));
+        assert_eq!(shell.current_page, 2);
+        assert!(shell.run_line("go page 400").is_err());
+        assert_eq!(shell.current_page, 2);
+
+        // scripts stop at the first failing line
+        let mut outputs: Vec<String> = Vec::new();
+        let script = "# lining pass\nadd shotline 3 1 3 4\n\nmodify shotline 9 type cu\nadd shotline 4 1 4 2\n";
+        let result = shell.run_script(script, |output| outputs.push(output.to_string()));
+        assert!(matches!(result, Err((4, ShellError::Failed(_)))));
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(shell.doc.annotation_map.shotlines.len(), 3);
+    }
+
+    #[test]
+    fn test_get_shotlist() {
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let tag_id = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "Betty".to_string(),
+                departments: vec![production::Department::Props],
+            },
+            tag_id.clone(),
+        )
+        .unwrap();
+
+        // (shot number, start, end); added out of order on purpose
+        let shots = [
+            (Some("3"), coord(2, 12), coord(2, 16)),
+            (Some("10"), coord(1, 2), coord(1, 4)),
+            (None, coord(1, 0), coord(1, 1)),
+            (Some("1A"), coord(1, 5), coord(1, 9)),
+            (Some("2"), coord(1, 1), coord(1, 3)),
+        ];
+        for (number, start, end) in shots {
+            let mut shot = production::Shot::new();
+            shot.shot_number = number.map(|n| ShotNumber(n.to_string()));
+            shot.shotline = Some(ShotLine::new(start, end));
+            shot.primary_composition.add_tag(&tag_id).unwrap();
+            doc.add_shotline(shot, production::ShotID::new()).unwrap();
+        }
+        doc.add_shotline(production::Shot::new(), production::ShotID::new()).unwrap();
+
+        let Some(shotlist) = reports::get_shotlist(&doc) else {
+            panic!("Couldn't get shotlist.");
+        };
+        let numbers: Vec<Option<&str>> = shotlist
+            .entries
+            .iter()
+            .map(|entry| entry.shot_number.map(|n| n.0.as_str()))
+            .collect();
+        assert_eq!(numbers, vec![Some("1A"), Some("2"), Some("10"), None, Some("3"), None]);
+
+        let first_scene_start = &shotlist.entries[0].scene.unwrap().start;
+        assert_eq!((first_scene_start.page, first_scene_start.line), (1, 0));
+        assert_eq!(shotlist.entries[4].scene.unwrap().start.line, 11);
+        assert!(shotlist.entries[5].scene.is_none());
+
+        assert_eq!(shotlist.entries[0].tags[0].string, "Betty");
+        let characters: Vec<&str> = shotlist.entries[4]
+            .characters
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(characters, vec!["GUY"]);
+        assert!(shotlist.entries[0].characters.is_empty());
+
+        assert!(reports::get_shotlist(&ShotlinerDoc::new(screenplay_document::ScreenplayDocument::new())).is_none());
+    }
+
+    #[test]
+    fn test_get_stripboard() {
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let tag_id = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "Guy's Phone".to_string(),
+                departments: vec![production::Department::Props],
+            },
+            tag_id.clone(),
+        )
+        .unwrap();
+        let phone = element_coord(2, 13, Some(0));
+        doc.add_tagged_element(
+            TaggedElementID::new(),
+            TaggedElement {
+                ocurrances: HashSet::from([(phone.clone(), phone.clone())]),
+                origin: phone.clone(),
+                endpoint: phone,
+                tags: vec![tag_id.clone()],
+                anchors: None,
+            },
+        )
+        .unwrap();
+
+        let Some(stripboard) = reports::get_stripboard_for_document(&doc) else {
+            panic!("Couldn't get stripboard.");
+        };
+        let strips: Vec<&reports::SceneStrip> = stripboard
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                reports::StripBoardEntry::Scene(strip) => Some(strip),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(strips.len(), doc.screenplay.scenes.len());
+
+        // scenes come out in script order, and every scene is at least 1/8 of a page
+        for pair in strips.windows(2) {
+            assert!(
+                (pair[0].scene.start.page, pair[0].scene.start.line)
+                    < (pair[1].scene.start.page, pair[1].scene.start.line)
+            );
+        }
+        assert!(strips.iter().all(|strip| strip.pages_eigths != (0, 0)));
+
+        // the second scene starts and ends on page 2
+        assert_eq!(strips[1].page_span, 2..3);
+        assert!(strips[1].cast_in_scene.iter().any(|c| c.name == "GUY"));
+        assert_eq!(strips[1].tags.len(), 1);
+        assert_eq!(strips[1].tags[0].string, "Guy's Phone");
+        assert!(strips[0].tags.is_empty());
+        assert!(strips[2].tags.is_empty());
+    }
+
+    #[test]
+    fn test_page_eighths() {
+        use reports::page_eighths::{self, EighthsSettings, PageEighths};
+
+        assert_eq!(PageEighths(3).to_string(), "3/8");
+        assert_eq!(PageEighths(8).to_string(), "1");
+        assert_eq!(PageEighths::from_whole_and_eighths(2, 5).to_string(), "2 5/8");
+        assert_eq!(PageEighths::from_pages(0.01), PageEighths(1));
+        assert_eq!(PageEighths::from_pages(0.0), PageEighths(0));
+        assert_eq!(PageEighths::from_pages(1.3), PageEighths(10));
+
+        let settings = EighthsSettings::us_letter_default();
+
+        // lines without positions are measured by counting them; 27 lines is half of a 54 line body
+        let mut screenplay = screenplay_document::ScreenplayDocument::new();
+        let mut page = screenplay_document::Page::default();
+        page.lines = vec![screenplay_document::Line::default(); 40];
+        screenplay.pages.push(page);
+        let start = coord(0, 0);
+        let end = coord(0, 26);
+        let pages = page_eighths::measure_pages(&screenplay, &start, &end, &settings);
+        assert_eq!(PageEighths::from_pages(pages), PageEighths(4));
+
+        let Ok(doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let scenes = page_eighths::get_eighths_for_all_scenes(&doc.screenplay, &settings);
+        assert_eq!(scenes.len(), doc.screenplay.scenes.len());
+        assert!(scenes.iter().all(|(_, eighths)| *eighths >= PageEighths(1)));
+
+        let total = page_eighths::get_eighths_for_document(&doc.screenplay, &settings);
+        assert_eq!(total, scenes.iter().map(|(_, eighths)| *eighths).sum());
+        // 18 full pages of script after the title page, the last one mostly empty
+        assert!(total > PageEighths::from_whole_and_eighths(17, 0));
+        assert!(total < PageEighths::from_whole_and_eighths(19, 0));
+        assert_eq!(
+            page_eighths::get_eighths_for_scene(&doc.screenplay, scenes[3].0, &settings),
+            Some(scenes[3].1)
+        );
+
+        let Some(mut stripboard) = reports::get_stripboard_for_document(&doc) else {
+            panic!("Couldn't get stripboard.");
+        };
+        let day_one = chrono::NaiveDate::from_ymd_opt(2025, 6, 2).unwrap();
+        stripboard.entries.insert(3, reports::StripBoardEntry::DayBreak(day_one));
+        let days = page_eighths::get_eighths_per_shooting_day(&stripboard);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].0, Some(day_one));
+        assert_eq!(days[0].1, scenes[..3].iter().map(|(_, eighths)| *eighths).sum());
+        assert_eq!(days[1].0, None);
+        assert_eq!(days[0].1 + days[1].1, total);
+    }
+
+    #[test]
+    fn test_shot_list_csv() {
+        use crate::serializables::shot_list_csv::{self, ShotListColumn};
+
+        assert_eq!(shot_list_csv::escape_field("Wide"), "Wide");
+        assert_eq!(shot_list_csv::escape_field("Betty, Guy"), "\"Betty, Guy\"");
+        assert_eq!(shot_list_csv::escape_field("\"FOR PARTS\""), "\"\"\"FOR PARTS\"\"\"");
+        assert_eq!(shot_list_csv::escape_field("a\nb"), "\"a\nb\"");
+
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let table_id = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "Yard Sale Table".to_string(),
+                departments: vec![production::Department::Art],
+            },
+            table_id.clone(),
+        )
+        .unwrap();
+        let betty_id = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "Betty".to_string(),
+                departments: vec![production::Department::Props, production::Department::Art],
+            },
+            betty_id.clone(),
+        )
+        .unwrap();
+
+        let mut shot = production::Shot::new();
+        shot.shot_number = Some(ShotNumber("1A".to_string()));
+        shot.shotline = Some(ShotLine::new(
+            coord(1, 9),
+            coord(1, 10),
+        ));
+        shot.primary_composition.shot_type(production::ShotType::Insert);
+        shot.primary_composition
+            .camera_metadata(Some(production::CameraMetadata::new(50)));
+        shot.primary_composition.add_tag(&betty_id).unwrap();
+        shot.primary_composition.add_tag(&table_id).unwrap();
+        shot.completed = true;
+        doc.add_shotline(shot, production::ShotID::new()).unwrap();
+
+        let shotlist = reports::get_shotlist(&doc).unwrap();
+        let columns = vec![
+            ShotListColumn::ShotNumber,
+            ShotListColumn::ShotType,
+            ShotListColumn::Lens,
+            ShotListColumn::Tags,
+            ShotListColumn::DepartmentTags(production::Department::Props),
+            ShotListColumn::PageRange,
+            ShotListColumn::Text,
+            ShotListColumn::Completed,
+        ];
+        let csv = shot_list_csv::to_string(
+            &doc.screenplay,
+            &doc.production_page_labels(),
+            &shotlist,
+            &columns,
+        );
+        let rows: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(rows[0], "Shot,Shot Type,Lens,Tags,Props Tags,Pages,Text,Completed");
+        assert_eq!(
+            rows[1],
+            "1A,Insert,50mm,\"Betty, Yard Sale Table\",Betty,1,\"Betty looks, then peels the sticker off and reads it:\n\"\"FOR PARTS\"\"\",yes"
+        );
+        assert_eq!(rows[2], "");
+
+        // the page range goes by production page number, e.g. once the pages are locked
+        let labels = vec!["title".to_string(), "1A".to_string()];
+        let csv = shot_list_csv::to_string(&doc.screenplay, &labels, &shotlist, &[ShotListColumn::PageRange]);
+        assert_eq!(csv, "Pages\r\n1A\r\n");
+    }
+
+    #[test]
+    fn test_kra_storyboard_template() {
+        use crate::serializables::kra_storyboard::{self, KraTemplateSettings};
+
+        let Ok(mut shell) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf").map(Shell::new) else {
+            panic!("Couldn't open test screenplay.");
+        };
+        shell.run_line("add shotline 2 11 2 20").unwrap();
+        shell.run_line("add shotline 1 0 1 12").unwrap();
+        shell.run_line("modify shotline 1 number 2A").unwrap();
+        shell.run_line("modify shotline 1 type cu").unwrap();
+
+        let shotlist = reports::get_shotlist(&shell.doc).unwrap();
+        assert_eq!(
+            kra_storyboard::panel_label(&shell.doc.screenplay, &shotlist.entries[1]),
+            "Sc 2 / Shot 2A: CloseUp"
+        );
+        let text = kra_storyboard::panel_text(&shell.doc.screenplay, &shotlist.entries[0]);
+        assert_eq!(text[0], "EXT. YARD SALE - DAY");
+
+        let kra = kra_storyboard::storyboard_template(
+            &shell.doc.screenplay,
+            &shotlist,
+            &KraTemplateSettings::default(),
+        )
+        .unwrap();
+        // the archive is stored uncompressed, with the mimetype first
+        assert_eq!(&kra[0..4], b"PK\x03\x04");
+        assert_eq!(&kra[30..38], b"mimetype");
+        assert_eq!(&kra[38..57], b"application/x-krita");
+
+        let contents = String::from_utf8_lossy(&kra);
+        assert_eq!(contents.matches("nodetype=\"grouplayer\"").count(), 2);
+        assert!(contents.contains("name=\"Sc 2 / Shot 2A: CloseUp\""));
+        assert!(contents.contains("&quot;opens&quot;"));
+
+        // without ZIP64 the entry count can't go past u16::MAX
+        let mut zip = crate::serializables::zip_writer::ZipWriter::new();
+        for index in 0..u16::MAX {
+            zip.add_file(&index.to_string(), b"").unwrap();
+        }
+        assert!(zip.add_file("one too many", b"").is_err());
+        assert!(zip.finish().is_ok());
+    }
+
+    #[test]
+    fn test_storyboard_sheets() {
+        use crate::multimedia::{MediaLink, MediaType};
+        use crate::serializables::pdf_writer::PdfImage;
+        use crate::serializables::storyboard_sheets::{self, SheetItem, SheetSettings};
+
+        let png = std::fs::read("test_data/storyboard_frame.png").unwrap();
+        let image = PdfImage::from_png(&png).unwrap();
+        assert_eq!((image.width, image.height), (4, 3));
+        assert!(PdfImage::from_jpeg(&png).is_none());
+
+        let Ok(mut shell) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf").map(Shell::new) else {
+            panic!("Couldn't open test screenplay.");
+        };
+        for page in 1..=8 {
+            shell.run_line(&format!("add shotline {} 3 {} 20", page, page)).unwrap();
+        }
+        let frame = MediaLink::new("test_data/storyboard_frame.png".to_string(), MediaType::Image);
+        for shot_id in &shell.ordered_shot_ids()[0..2] {
+            let mut shot = shell.doc.annotation_map.shotlines[shot_id].clone();
+            shot.primary_composition.add_media(frame.clone());
+            shot.primary_composition
+                .camera_metadata(Some(production::CameraMetadata::new(35)));
+            shell.doc.modify_shotline(shot_id, shot).unwrap();
+        }
+
+        let shotlist = reports::get_shotlist(&shell.doc).unwrap();
+        let settings = SheetSettings::default();
+        let pages = storyboard_sheets::layout_sheets(&shell.doc.screenplay, &shotlist, &settings);
+        // 8 shots, 3x2 to a page
+        assert_eq!(pages.len(), 2);
+        let frames = |page: &storyboard_sheets::SheetPage| {
+            page.items
+                .iter()
+                .filter(|item| matches!(item, SheetItem::Rect { .. }))
+                .count()
+        };
+        assert_eq!(frames(&pages[0]), 6);
+        assert_eq!(frames(&pages[1]), 2);
+        let camera_lines = pages[0]
+            .items
+            .iter()
+            .filter(|item| matches!(item, SheetItem::Text { text, .. } if text == "35mm"))
+            .count();
+        assert_eq!(camera_lines, 2);
+
+        // everything stays inside its page
+        for page in &pages {
+            for item in &page.items {
+                if let SheetItem::Text { x, y, .. } | SheetItem::Rect { x, y, .. } = item {
+                    assert!(*x >= 0.0 && *x <= page.width && *y >= 0.0 && *y <= page.height);
+                }
+            }
+        }
+
+        let svg = storyboard_sheets::page_to_svg(&pages[0]);
+        assert_eq!(svg.matches("data:image/png;base64,").count(), 2);
+
+        let pdf = storyboard_sheets::sheets_to_pdf(&pages);
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with("%PDF-1.4"));
+        assert!(pdf.contains("/Type /Pages /Kids [5 0 R 7 0 R] /Count 2"));
+        // the image is embedded once, and drawn twice
+        assert_eq!(pdf.matches("/Subtype /Image").count(), 1);
+        assert_eq!(pdf.matches("/Im3 Do").count(), 2);
+    }
+
+    #[test]
+    fn test_lined_script() {
+        use crate::serializables::lined_script::{self, LinedScriptSettings};
+
+        let Ok(mut shell) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf").map(Shell::new) else {
+            panic!("Couldn't open test screenplay.");
+        };
+        // the first shot runs onto the next page; the other two fit beside it
+        for line in [
+            "add shotline 1 3 2 5",
+            "add shotline 1 5 1 8",
+            "add shotline 1 12 1 15",
+            "modify shotline 0 number 1A",
+            "modify shotline 1 number 1B",
+            "modify shotline 2 number 1C",
+        ] {
+            shell.run_line(line).unwrap();
+        }
+        let ids = shell.ordered_shot_ids();
+        let lanes = lined_script::assign_lanes(&shell.doc);
+        assert_eq!(lanes[&ids[0]], 0);
+        assert_eq!(lanes[&ids[1]], 1);
+        assert_eq!(lanes[&ids[2]], 1);
+
+        let mut shot = shell.doc.annotation_map.shotlines[&ids[1]].clone();
+        shot.shotline.as_mut().unwrap().unfilmed_lines = Some(HashSet::from([coord(1, 6)]));
+        shell.doc.modify_shotline(&ids[1], shot).unwrap();
+
+        let settings = LinedScriptSettings::default();
+        assert_eq!(settings.lane_x(1, 3), settings.first_lane_x + settings.lane_spacing);
+        // more lanes than fit squeeze together rather than overlapping
+        let last_x =
+            settings.first_lane_x + (settings.lane_count - 1) as f64 * settings.lane_spacing;
+        let lanes_used = settings.lane_count * 2;
+        assert!(settings.lane_x(lanes_used - 1, lanes_used) <= last_x);
+        assert!(settings.lane_x(settings.lane_count, lanes_used) > settings.lane_x(0, lanes_used));
+        let pdf = lined_script::lined_script_pdf(&shell.doc, &settings);
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with("%PDF-1.4"));
+        assert_eq!(pdf.matches("/Type /Page ").count(), shell.doc.screenplay.pages.len());
+        assert!(pdf.contains("(YARD) Tj"));
+        assert!(pdf.contains("(1A) Tj"));
+        // shot 1A carries on to the next page, where its number is repeated in brackets
+        assert!(pdf.contains("(\\(1A\\)) Tj"));
+        assert!(pdf.contains("h f"));
+    }
+
+    #[test]
+    fn test_breakdown_script() {
+        use crate::serializables::breakdown_script::{self, BreakdownScriptSettings, HighlightStyle};
+
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let yard_sale = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "Yard Sale".to_string(),
+                departments: vec![production::Department::Props, production::Department::Vehicles],
+            },
+            yard_sale.clone(),
+        )
+        .unwrap();
+        let sticker = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "Sticker".to_string(),
+                departments: vec![],
+            },
+            sticker.clone(),
+        )
+        .unwrap();
+        // "YARD SALE" in the first heading, and the sticker across two lines
+        doc.add_tagged_element(
+            TaggedElementID::new(),
+            TaggedElement {
+                ocurrances: HashSet::new(),
+                origin: element_coord(1, 0, Some(1)),
+                endpoint: element_coord(1, 0, Some(2)),
+                tags: vec![yard_sale.clone()],
+                anchors: None,
+            },
+        )
+        .unwrap();
+        doc.add_tagged_element(
+            TaggedElementID::new(),
+            TaggedElement {
+                ocurrances: HashSet::new(),
+                origin: coord(1, 9),
+                endpoint: coord(1, 10),
+                tags: vec![sticker.clone()],
+                anchors: None,
+            },
+        )
+        .unwrap();
+
+        let mut settings = BreakdownScriptSettings::default();
+        let highlights = breakdown_script::get_highlights(&doc, &settings.page);
+        let page_highlights = &highlights[&1];
+        assert_eq!(page_highlights.len(), 3);
+        let heading = page_highlights.iter().find(|h| h.line == 0).unwrap();
+        let heading_extents = crate::serializables::lined_script::element_extents(
+            &doc.screenplay.pages[1].lines[0],
+            &settings.page,
+        );
+        assert_eq!(heading.x_start, heading_extents[1].0);
+        assert_eq!(heading.x_end, heading_extents[2].1);
+        assert_eq!(
+            heading.departments,
+            vec![production::Department::Props, production::Department::Vehicles]
+        );
+
+        let legend = breakdown_script::departments_in_use(&doc);
+        assert_eq!(
+            legend,
+            vec![
+                (Some(production::Department::Props), 1),
+                (Some(production::Department::Vehicles), 1),
+                (None, 1)
+            ]
+        );
+
+        settings.colors.insert(production::Department::Props, (1.0, 0.0, 0.0));
+        let pdf = breakdown_script::breakdown_script_pdf(&doc, &settings);
+        let pdf = String::from_utf8_lossy(&pdf);
+        // the legend, then the script
+        assert_eq!(pdf.matches("/Type /Page ").count(), doc.screenplay.pages.len() + 1);
+        assert!(pdf.contains("(Breakdown Colors) Tj"));
+        assert!(pdf.contains("(No Department) Tj"));
+        assert!(pdf.contains("1.000 0.000 0.000 rg"));
+
+        settings.style = HighlightStyle::Underline;
+        settings.legend = false;
+        let pdf = breakdown_script::breakdown_script_pdf(&doc, &settings);
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert_eq!(pdf.matches("/Type /Page ").count(), doc.screenplay.pages.len());
+        assert!(pdf.contains("1.000 0.000 0.000 RG"));
+    }
+
+    #[test]
+    fn test_breakdown_sheets() {
+        use crate::reports::breakdown_sheets;
+
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let mut add_tagged = |string: &str, departments: Vec<production::Department>, page: usize, line: usize| {
+            let tag_id = TagID::new();
+            doc.add_tag(
+                Tag {
+                    string: string.to_string(),
+                    departments: departments,
+                },
+                tag_id.clone(),
+            )
+            .unwrap();
+            let position = coord(page, line);
+            doc.add_tagged_element(
+                TaggedElementID::new(),
+                TaggedElement {
+                    ocurrances: HashSet::new(),
+                    origin: position.clone(),
+                    endpoint: position,
+                    tags: vec![tag_id],
+                    anchors: None,
+                },
+            )
+            .unwrap();
+        };
+        // all in the first scene, except the phone, which is in the second
+        add_tagged("Sticker", vec![production::Department::Props], 1, 9);
+        add_tagged("VCR", vec![production::Department::Props, production::Department::Art], 1, 3);
+        add_tagged("Sun Hat", vec![production::Department::Wardrobe], 1, 5);
+        add_tagged("Heat Haze", vec![], 1, 1);
+        add_tagged("Guy's Phone", vec![production::Department::Props], 2, 13);
+
+        let breakdown = breakdown_sheets::get_breakdown_sheets(&doc).unwrap();
+        assert_eq!(breakdown.sheets.len(), doc.screenplay.scenes.len());
+        let first = &breakdown.sheets[0];
+        assert_eq!(first.scene_number, "1");
+        assert_eq!(first.heading, "EXT. YARD SALE - DAY");
+        let groups: Vec<(Option<production::Department>, Vec<&str>)> = first
+            .departments
+            .iter()
+            .map(|group| {
+                (
+                    group.department.clone(),
+                    group.tags.iter().map(|tag| tag.string.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (Some(production::Department::Art), vec!["VCR"]),
+                (Some(production::Department::Wardrobe), vec!["Sun Hat"]),
+                (Some(production::Department::Props), vec!["Sticker", "VCR"]),
+                (None, vec!["Heat Haze"]),
+            ]
+        );
+        let second = &breakdown.sheets[1];
+        assert!(second.cast.iter().any(|character| character.name == "GUY"));
+        assert_eq!(second.departments.len(), 1);
+
+        let text = breakdown_sheets::to_text(&breakdown).unwrap();
+        assert!(text.starts_with("SCENE 1: EXT. YARD SALE - DAY\n"));
+        assert!(text.contains("  Props: Sticker, VCR\n"));
+        assert!(text.contains("  Unassigned: Heat Haze\n"));
+
+        let csv = breakdown_sheets::to_csv(&breakdown);
+        let mut rows = csv.split("\r\n");
+        assert!(rows.next().unwrap().starts_with("Scene,Heading,Environment,"));
+        assert!(csv.contains(",Props,Guy's Phone\r\n"));
+        assert!(csv.contains(",Cast,GUY\r\n"));
+
+        let html = breakdown_sheets::to_html(&breakdown).unwrap();
+        assert_eq!(html.matches("<section class=\"breakdown-sheet\">").count(), breakdown.sheets.len());
+        assert!(html.contains("<tr><th>Props</th><td>Guy&apos;s Phone</td></tr>"));
+    }
+
+    #[test]
+    fn test_department_element_lists() {
+        use crate::reports::element_lists::{self, ElementListOrder};
+
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let mut add_tag = |string: &str, departments: Vec<production::Department>| {
+            let tag_id = TagID::new();
+            doc.add_tag(
+                Tag {
+                    string: string.to_string(),
+                    departments: departments,
+                },
+                tag_id.clone(),
+            )
+            .unwrap();
+            tag_id
+        };
+        let vcr = add_tag("VCR", vec![production::Department::Props]);
+        let sticker = add_tag("Sticker", vec![production::Department::Props]);
+        let _unused = add_tag("Lamp", vec![production::Department::Props]);
+        let _hat = add_tag("Sun Hat", vec![production::Department::Wardrobe]);
+        let puppet = add_tag("Puppet", vec![production::Department::Other("Puppetry".to_string())]);
+
+        let mut add_element = |origin: ScreenplayCoordinate, ocurrances: Vec<ScreenplayCoordinate>, tags: Vec<TagID>| {
+            doc.add_tagged_element(
+                TaggedElementID::new(),
+                TaggedElement {
+                    ocurrances: ocurrances.into_iter().map(|c| (c.clone(), c)).collect(),
+                    origin: origin.clone(),
+                    endpoint: origin,
+                    tags: tags,
+                    anchors: None,
+                },
+            )
+            .unwrap();
+        };
+        // the sticker turns up first, but the VCR turns up more often
+        add_element(coord(1, 1), vec![], vec![sticker.clone()]);
+        add_element(coord(1, 3), vec![coord(2, 13), coord(4, 10)], vec![vcr.clone()]);
+        add_element(coord(3, 18), vec![], vec![puppet.clone()]);
+
+        let list = element_lists::get_department_element_list(
+            &doc,
+            &production::Department::Props,
+            ElementListOrder::FirstAppearance,
+        );
+        let names: Vec<&str> = list.elements.iter().map(|e| e.tag.string.as_str()).collect();
+        assert_eq!(names, vec!["Sticker", "VCR"]);
+        let vcr_usage = &list.elements[1];
+        assert_eq!(vcr_usage.ocurrances.len(), 3);
+        assert_eq!(vcr_usage.pages, vec![1, 2, 4]);
+        let scenes: Vec<&str> = vcr_usage.scenes.iter().map(|s| s.scene_number.as_str()).collect();
+        assert_eq!(scenes, vec!["1", "2", "4"]);
+
+        let list = element_lists::get_department_element_list(
+            &doc,
+            &production::Department::Props,
+            ElementListOrder::Frequency,
+        );
+        let names: Vec<&str> = list.elements.iter().map(|e| e.tag.string.as_str()).collect();
+        assert_eq!(names, vec!["VCR", "Sticker"]);
+        let text = element_lists::to_text(&list).unwrap();
+        assert!(text.starts_with("PROPS\nVCR (3x)\n  scenes: 1, 2, 4\n  pages: 1, 2, 4\n"));
+
+        let list = element_lists::get_department_element_list(
+            &doc,
+            &production::Department::Other("Puppetry".to_string()),
+            ElementListOrder::FirstAppearance,
+        );
+        assert_eq!(list.elements.len(), 1);
+        assert_eq!(list.elements[0].tag_id, &puppet);
+        assert_eq!(list.elements[0].scenes[0].scene_number, "3");
+    }
+
+    #[test]
+    fn test_annotation_queries() {
+        let Ok(mut shell) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf").map(Shell::new) else {
+            panic!("Couldn't open test screenplay.");
+        };
+        for line in [
+            "add tag \"Sticker\" props",
+            "add tag \"VCR\" props",
+            "add tag \"Handheld\" camera",
+            // across the first two scenes, inside the second, and in the fourth
+            "add shotline 1 3 2 5",
+            "add shotline 2 12 2 15",
+            "add shotline 4 3 4 9",
+        ] {
+            shell.run_line(line).unwrap();
+        }
+        let tag_ids = shell.ordered_tag_ids();
+        let (handheld, sticker, vcr) = (tag_ids[0].clone(), tag_ids[1].clone(), tag_ids[2].clone());
+        let shot_ids = shell.ordered_shot_ids();
+        shell.run_line("modify shotline 1 tag add 0").unwrap();
+
+        let doc = &mut shell.doc;
+        let sticker_element = TaggedElementID::new();
+        doc.add_tagged_element(
+            sticker_element.clone(),
+            TaggedElement {
+                ocurrances: HashSet::new(),
+                origin: coord(1, 9),
+                endpoint: coord(1, 10),
+                tags: vec![sticker.clone()],
+                anchors: None,
+            },
+        )
+        .unwrap();
+        let vcr_element = TaggedElementID::new();
+        doc.add_tagged_element(
+            vcr_element.clone(),
+            TaggedElement {
+                ocurrances: HashSet::from([(coord(5, 1), coord(5, 1))]),
+                origin: coord(2, 13),
+                endpoint: coord(2, 13),
+                tags: vec![vcr.clone()],
+                anchors: None,
+            },
+        )
+        .unwrap();
+
+        let shots = |result: &crate::annotation_index::AnnotationQueryResult| -> Vec<production::ShotID> {
+            result.shots.iter().map(|(id, _)| (*id).clone()).collect()
+        };
+        let elements = |result: &crate::annotation_index::AnnotationQueryResult| -> Vec<TaggedElementID> {
+            result.tagged_elements.iter().map(|(id, _)| (*id).clone()).collect()
+        };
+        let tags = |result: &crate::annotation_index::AnnotationQueryResult| -> Vec<TagID> {
+            result.tags.iter().map(|(id, _)| (*id).clone()).collect()
+        };
+
+        let page_2 = doc.annotations_on_page(2);
+        assert_eq!(shots(&page_2), vec![shot_ids[0].clone(), shot_ids[1].clone()]);
+        assert_eq!(elements(&page_2), vec![vcr_element.clone()]);
+        // tags on the tagged elements first, then the shots' own tags
+        assert_eq!(tags(&page_2), vec![vcr.clone(), handheld.clone()]);
+
+        let top_of_page_1 = doc.annotations_in_range(&coord(1, 0), &coord(1, 5));
+        assert_eq!(shots(&top_of_page_1), vec![shot_ids[0].clone()]);
+        assert!(top_of_page_1.tagged_elements.is_empty());
+        // found through its other ocurrance
+        assert_eq!(elements(&doc.annotations_on_page(5)), vec![vcr_element.clone()]);
+
+        let scenes = screenplay_doc_parser_rs::reports::get_all_scenes_ordered(&doc.screenplay).unwrap();
+        let second_scene = scenes[1].0.clone();
+        let in_scene = doc.annotations_in_scene(&second_scene).unwrap();
+        assert_eq!(shots(&in_scene), vec![shot_ids[1].clone()]);
+        assert_eq!(elements(&in_scene), vec![vcr_element.clone()]);
+
+        // moving, removing and undoing all keep the index in step
+        let mut moved = doc.annotation_map.shotlines[&shot_ids[0]].clone();
+        moved.shotline = Some(ShotLine::new(coord(6, 1), coord(6, 4)));
+        doc.execute(Command::ModifyShotline(shot_ids[0].clone(), Some(moved))).unwrap();
+        assert_eq!(shots(&doc.annotations_on_page(2)), vec![shot_ids[1].clone()]);
+        assert_eq!(shots(&doc.annotations_on_page(6)), vec![shot_ids[0].clone()]);
+        doc.execute(Command::RemoveTaggedElement(vcr_element.clone(), None)).unwrap();
+        assert!(doc.annotations_on_page(5).tagged_elements.is_empty());
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(elements(&doc.annotations_on_page(5)), vec![vcr_element.clone()]);
+        assert!(doc.annotations_on_page(6).shots.is_empty());
+        assert_eq!(doc.annotation_index, AnnotationIndex::from_annotation_map(&doc.annotation_map));
+        assert_eq!(doc.annotation_index.len(), 5);
+
+        let path = std::env::temp_dir().join(format!("shotliner_test_{}.sl", uuid::Uuid::new_v4()));
+        doc.save(&path).unwrap();
+        let loaded = ShotlinerDoc::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.annotation_index, doc.annotation_index);
+        assert_eq!(shots(&loaded.annotations_on_page(2)), shots(&doc.annotations_on_page(2)));
+    }
+
+    #[test]
+    fn test_tag_usages() {
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let vcr = TagID::new();
+        doc.add_tag(
+            Tag {
+                string: "VCR".to_string(),
+                departments: vec![production::Department::Props],
+            },
+            vcr.clone(),
+        )
+        .unwrap();
+        let te_id = TaggedElementID::new();
+        doc.add_tagged_element(
+            te_id.clone(),
+            TaggedElement {
+                ocurrances: HashSet::from([(coord(5, 1), coord(5, 2))]),
+                origin: coord(1, 3),
+                endpoint: coord(1, 3),
+                tags: vec![vcr.clone()],
+                anchors: None,
+            },
+        )
+        .unwrap();
+
+        // the primary composition and a sub-composition of one shot, and a group
+        let shot_id = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        shot.shotline = Some(ShotLine::new(coord(2, 1), coord(2, 9)));
+        shot.primary_composition.add_tag(&vcr).unwrap();
+        let mut close_up = ShotComposition::new();
+        close_up.add_tag(&vcr).unwrap();
+        shot.sub_compositions = Some(std::collections::HashMap::from([(coord(2, 5), close_up)]));
+        doc.add_shotline(shot, shot_id.clone()).unwrap();
+        let group_id = GroupID::new();
+        let mut group = Group::new("Electronics".to_string());
+        group.tags.insert(vcr.clone());
+        doc.execute(Command::AddGroup(group_id.clone(), group)).unwrap();
+
+        let usages: Vec<TagUsage> = doc.tag_usages(&vcr).into_iter().cloned().collect();
+        assert_eq!(
+            usages,
+            vec![
+                TagUsage::TaggedElement(te_id.clone()),
+                TagUsage::Shot(shot_id.clone()),
+                TagUsage::SubComposition(shot_id.clone(), coord(2, 5)),
+                TagUsage::Group(group_id.clone()),
+            ]
+        );
+        assert_eq!(
+            doc.tag_ocurrances(&vcr),
+            vec![(coord(1, 3), coord(1, 3)), (coord(2, 1), coord(2, 9)), (coord(5, 1), coord(5, 2))]
+        );
+
+        // taking the tag off the shot's primary composition leaves the sub-composition's usage
+        let mut shot = doc.annotation_map.shotlines[&shot_id].clone();
+        shot.primary_composition.remove_tag(&vcr).unwrap();
+        doc.execute(Command::ModifyShotline(shot_id.clone(), Some(shot))).unwrap();
+        assert_eq!(doc.tag_usages(&vcr).len(), 3);
+        doc.undo().unwrap();
+        assert_eq!(doc.tag_usages(&vcr).len(), 4);
+
+        // deleting the tag strips it from everything, groups included, and undoes as one edit
+        doc.delete_tag(&vcr).unwrap();
+        assert!(doc.tag_usages(&vcr).is_empty());
+        assert!(doc.annotation_map.groups[&group_id].tags.is_empty());
+        assert!(doc.annotation_map.tagged_elements[&te_id].tags.is_empty());
+        doc.undo().unwrap();
+        assert_eq!(doc.tag_usages(&vcr).len(), 4);
+        assert_eq!(doc.tag_usage_index, TagUsageIndex::from_annotation_map(&doc.annotation_map));
+    }
+
+    #[test]
+    fn test_validate_and_repair() {
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        assert!(doc.validate().is_empty());
+
+        // a shot drawn bottom to top, with a stray unfilmed line, a sub-composition past its end,
+        // a tag that doesn't exist and a setup nobody made
+        let missing_tag = TagID::new();
+        let shot_id = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        let mut shotline = ShotLine::new(coord(2, 9), coord(2, 1));
+        shotline.unfilmed_lines = Some(HashSet::from([coord(2, 4), coord(3, 0)]));
+        shot.shotline = Some(shotline);
+        shot.primary_composition.tags.push(missing_tag.clone());
+        shot.primary_composition.setup = Some(production::ShotSetup {
+            index: 1,
+            id: "A".to_string(),
+        });
+        shot.sub_compositions = Some(std::collections::HashMap::from([(
+            coord(4, 0),
+            ShotComposition::new(),
+        )]));
+        doc.add_shotline(shot, shot_id.clone()).unwrap();
+
+        // a tagged element with an ocurrance past the last page
+        let te_id = TaggedElementID::new();
+        let past_the_end = coord(doc.screenplay.pages.len(), 0);
+        doc.add_tagged_element(
+            te_id.clone(),
+            TaggedElement {
+                ocurrances: HashSet::from([(past_the_end.clone(), past_the_end.clone())]),
+                origin: coord(1, 3),
+                endpoint: coord(1, 3),
+                tags: vec![],
+                anchors: None,
+            },
+        )
+        .unwrap();
+
+        let problems = doc.validate();
+        assert_eq!(
+            problems,
+            vec![
+                AnnotationProblem::DanglingTag {
+                    usage: TagUsage::Shot(shot_id.clone()),
+                    tag_id: missing_tag.clone(),
+                },
+                AnnotationProblem::UnknownSetup {
+                    shot_id: shot_id.clone(),
+                    setup: production::ShotSetup {
+                        index: 1,
+                        id: "A".to_string(),
+                    },
+                },
+                AnnotationProblem::ShotlineReversed(shot_id.clone()),
+                AnnotationProblem::UnfilmedLineOutsideShotline {
+                    shot_id: shot_id.clone(),
+                    coordinate: coord(3, 0),
+                },
+                AnnotationProblem::SubCompositionOutsideShotline {
+                    shot_id: shot_id.clone(),
+                    coordinate: coord(4, 0),
+                },
+                AnnotationProblem::CoordinateOutOfBounds {
+                    annotation: AnnotationKey::TaggedElement(te_id.clone()),
+                    place: CoordinatePlace::Ocurrance,
+                    coordinate: past_the_end.clone(),
+                },
+            ]
+        );
+
+        // repairing fixes everything but the setup and the sub-composition, in one undoable edit
+        let repaired = doc.repair().unwrap();
+        assert_eq!(repaired.len(), 4);
+        let shot = &doc.annotation_map.shotlines[&shot_id];
+        let shotline = shot.shotline.as_ref().unwrap();
+        assert_eq!((shotline.start.clone(), shotline.end.clone()), (coord(2, 1), coord(2, 9)));
+        assert_eq!(shotline.unfilmed_lines, Some(HashSet::from([coord(2, 4)])));
+        assert!(shot.primary_composition.tags.is_empty());
+        assert!(doc.annotation_map.tagged_elements[&te_id].ocurrances.is_empty());
+        assert!(doc.validate().iter().all(|problem| !problem.is_repairable()));
+        assert_eq!(doc.validate().len(), 2);
+
+        // problems that are already fixed aren't repaired again, and don't produce empty edits
+        let (cmds, repaired) = validation::repair_commands(&doc.annotation_map, &problems);
+        assert!(cmds.is_empty());
+        assert!(repaired.is_empty());
+
+        doc.undo().unwrap();
+        assert_eq!(doc.validate(), problems);
+    }
+
+    #[test]
+    fn test_merge_forward() {
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/DraftTest_02.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        // page 2 is the Blue revision of page 1: a revision header on top, new scene numbers,
+        // and the "Blah" lines at the bottom gone. A line gets added to the cave scene, too.
+        let draft = doc.screenplay.clone();
+        let mut revised = draft.clone();
+        revised.pages.remove(1);
+        let mut bat = revised.pages[1].lines[12].clone();
+        bat.text_elements.truncate(1);
+        bat.text_elements[0].text = "A BAT swoops past.".to_string();
+        revised.pages[1].lines.insert(13, bat);
+        doc.screenplay.pages.truncate(2);
+
+        let cave_opening = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        let mut shotline = ShotLine::new(coord(1, 3), coord(1, 9));
+        shotline.unfilmed_lines = Some(HashSet::from([coord(1, 5)]));
+        shot.shotline = Some(shotline);
+        shot.sub_compositions = Some(std::collections::HashMap::from([(coord(1, 7), ShotComposition::new())]));
+        doc.add_shotline(shot, cave_opening.clone()).unwrap();
+
+        let cave = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        shot.shotline = Some(ShotLine::new(coord(1, 11), coord(1, 16)));
+        doc.add_shotline(shot, cave.clone()).unwrap();
+
+        let blah = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        shot.shotline = Some(ShotLine::new(coord(1, 17), coord(1, 28)));
+        shot.sub_compositions = Some(std::collections::HashMap::from([(coord(1, 20), ShotComposition::new())]));
+        doc.add_shotline(shot, blah.clone()).unwrap();
+
+        let stalactites = TaggedElementID::new();
+        doc.add_tagged_element(
+            stalactites.clone(),
+            TaggedElement {
+                ocurrances: HashSet::new(),
+                origin: element_coord(1, 13, Some(0)),
+                endpoint: element_coord(1, 13, Some(2)),
+                tags: vec![],
+                anchors: None,
+            },
+        )
+        .unwrap();
+        let flashlights = TaggedElementID::new();
+        doc.add_tagged_element(
+            flashlights.clone(),
+            TaggedElement {
+                ocurrances: HashSet::new(),
+                origin: coord(1, 11),
+                endpoint: coord(1, 12),
+                tags: vec![],
+                anchors: None,
+            },
+        )
+        .unwrap();
+        let more_blah = TaggedElementID::new();
+        doc.add_tagged_element(
+            more_blah.clone(),
+            TaggedElement {
+                ocurrances: HashSet::new(),
+                origin: coord(1, 25),
+                endpoint: coord(1, 25),
+                tags: vec![],
+                anchors: None,
+            },
+        )
+        .unwrap();
+
+        let report = doc.merge_forward(revised);
+        let outcome = |id: &AnnotationKey| {
+            report
+                .annotations
+                .iter()
+                .find(|merged| &merged.annotation == id)
+                .map(|merged| merged.outcome)
+        };
+        assert_eq!(outcome(&AnnotationKey::Shot(cave_opening.clone())), Some(MergeOutcome::Moved));
+        assert_eq!(outcome(&AnnotationKey::Shot(cave.clone())), Some(MergeOutcome::Split));
+        assert_eq!(outcome(&AnnotationKey::Shot(blah.clone())), Some(MergeOutcome::Moved));
+        assert_eq!(outcome(&AnnotationKey::TaggedElement(stalactites.clone())), Some(MergeOutcome::Moved));
+        assert_eq!(outcome(&AnnotationKey::TaggedElement(flashlights.clone())), Some(MergeOutcome::Split));
+        assert_eq!(outcome(&AnnotationKey::TaggedElement(more_blah.clone())), Some(MergeOutcome::Orphaned));
+        assert!(report.orphaned_sub_compositions.is_empty());
+
+        // everything slid down a line for the revision header
+        let shot = &doc.annotation_map.shotlines[&cave_opening];
+        let shotline = shot.shotline.as_ref().unwrap();
+        assert_eq!((shotline.start.clone(), shotline.end.clone()), (coord(1, 4), coord(1, 10)));
+        assert_eq!(shotline.unfilmed_lines, Some(HashSet::from([coord(1, 6)])));
+        assert!(shot.sub_compositions.as_ref().unwrap().contains_key(&coord(1, 8)));
+
+        // the added line lands in the middle of the cave shot, so it's left unfilmed
+        let shotline = doc.annotation_map.shotlines[&cave].shotline.clone().unwrap();
+        assert_eq!((shotline.start, shotline.end), (coord(1, 12), coord(1, 18)));
+        assert_eq!(shotline.unfilmed_lines, Some(HashSet::from([coord(1, 13)])));
+        let te = &doc.annotation_map.tagged_elements[&flashlights];
+        assert_eq!((te.origin.clone(), te.endpoint.clone()), (coord(1, 12), coord(1, 12)));
+        assert_eq!(te.ocurrances, HashSet::from([(coord(1, 14), coord(1, 14))]));
+        let te = &doc.annotation_map.tagged_elements[&stalactites];
+        assert_eq!((te.origin.clone(), te.endpoint.clone()), (element_coord(1, 15, Some(0)), element_coord(1, 15, Some(2))));
+
+        // only the scene heading is left of the "Blah" shot, and its sub-composition moves up onto it
+        let shot = &doc.annotation_map.shotlines[&blah];
+        let shotline = shot.shotline.as_ref().unwrap();
+        assert_eq!((shotline.start.clone(), shotline.end.clone()), (coord(1, 19), coord(1, 19)));
+        assert!(shot.sub_compositions.as_ref().unwrap().contains_key(&coord(1, 19)));
+        assert!(!doc.annotation_map.tagged_elements.contains_key(&more_blah));
+        assert_eq!(report.orphaned_tagged_elements.len(), 1);
+        assert_eq!(report.orphaned_tagged_elements[0].0, more_blah);
+
+        assert_eq!(doc.annotations_on_page(1).shots.len(), 3);
+        assert!(report.to_text().unwrap().starts_with("0 unchanged, 3 moved, 2 split, 1 orphaned\n"));
+    }
+
+    #[test]
+    fn test_smart_coordinates() {
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let guy = element_coord(2, 14, Some(0));
+        let anchor = SmartScreenplayCoordinate::anchor(&doc.screenplay, &guy).unwrap();
+        assert_eq!(anchor.resolve(&doc.screenplay), Some(guy.clone()));
+        assert_eq!(anchor.previous_text.as_deref(), Some("carting things away."));
+
+        let shot_id = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        shot.shotline = Some(ShotLine::new(coord(1, 31), coord(1, 35)));
+        doc.add_shotline(shot, shot_id.clone()).unwrap();
+        let tagged = |origin: ScreenplayCoordinate, endpoint: ScreenplayCoordinate| TaggedElement {
+            ocurrances: HashSet::new(),
+            origin: origin,
+            endpoint: endpoint,
+            tags: vec![],
+            anchors: None,
+        };
+        let guy_id = TaggedElementID::new();
+        doc.add_tagged_element(guy_id.clone(), tagged(guy.clone(), guy.clone())).unwrap();
+        let slowly_id = TaggedElementID::new();
+        doc.add_tagged_element(slowly_id.clone(), tagged(element_coord(1, 32, Some(3)), element_coord(1, 32, Some(3)))).unwrap();
+        let packs_id = TaggedElementID::new();
+        let mut packs = tagged(element_coord(2, 12, Some(2)), element_coord(2, 12, Some(3)));
+        packs.ocurrances.insert((packs.origin.clone(), packs.endpoint.clone()));
+        packs.ocurrances.insert((coord(2, 13), coord(2, 13)));
+        doc.add_tagged_element(packs_id.clone(), packs).unwrap();
+        // a shot with unfilmed lines and a sub-composition, which aren't anchored themselves
+        let packing_id = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        let mut shotline = ShotLine::new(coord(2, 11), coord(2, 14));
+        shotline.unfilmed_lines = Some(HashSet::from([coord(2, 12)]));
+        shot.shotline = Some(shotline);
+        shot.sub_compositions = Some(std::collections::HashMap::from([(coord(2, 13), ShotComposition::new())]));
+        doc.add_shotline(shot, packing_id.clone()).unwrap();
+        let scoffs_id = TaggedElementID::new();
+        doc.add_tagged_element(scoffs_id.clone(), tagged(coord(2, 6), coord(2, 6))).unwrap();
+        doc.anchor_annotations().unwrap();
+        assert!(doc.annotation_map.tagged_elements.values().all(|te| te.anchors.is_some()));
+
+        // reflow: the last lines of page 1 get pushed onto page 2, one line is cut,
+        // and another is reworded
+        let mut reflowed = doc.screenplay.clone();
+        let pushed: Vec<_> = reflowed.pages[1].lines.drain(33..37).collect();
+        for (index, line) in pushed.into_iter().enumerate() {
+            reflowed.pages[2].lines.insert(1 + index, line);
+        }
+        reflowed.pages[2].lines.remove(10);
+        reflowed.pages[2].lines[15].text_elements[5].text = "sweeps".to_string();
+        for scene in reflowed.scenes.values_mut() {
+            if scene.start.page == 2 {
+                scene.start.line += 3;
+            }
+        }
+        doc.screenplay = reflowed;
+
+        let unresolved = doc.resolve_anchors().unwrap();
+        assert_eq!(unresolved, vec![AnnotationKey::TaggedElement(scoffs_id.clone())]);
+        let shotline = doc.annotation_map.shotlines[&shot_id].shotline.clone().unwrap();
+        assert_eq!((shotline.start, shotline.end), (coord(1, 31), coord(2, 3)));
+        let te = &doc.annotation_map.tagged_elements;
+        // there's a GUY in other scenes too, but only this one follows "carting things away."
+        assert_eq!(te[&guy_id].origin, element_coord(2, 17, Some(0)));
+        assert_eq!(te[&slowly_id].origin, element_coord(1, 32, Some(3)));
+        assert_eq!((te[&packs_id].origin.clone(), te[&packs_id].endpoint.clone()), (element_coord(2, 15, Some(2)), element_coord(2, 15, Some(3))));
+        assert_eq!(te[&scoffs_id].origin, coord(2, 6));
+        // everything else inside them moves along with their start
+        assert_eq!(
+            te[&packs_id].ocurrances,
+            HashSet::from([
+                (element_coord(2, 15, Some(2)), element_coord(2, 15, Some(3))),
+                (coord(2, 16), coord(2, 16)),
+            ])
+        );
+        let packing = &doc.annotation_map.shotlines[&packing_id];
+        let shotline = packing.shotline.clone().unwrap();
+        assert_eq!((shotline.start, shotline.end), (coord(2, 14), coord(2, 17)));
+        assert_eq!(shotline.unfilmed_lines, Some(HashSet::from([coord(2, 15)])));
+        assert!(packing.sub_compositions.as_ref().unwrap().contains_key(&coord(2, 16)));
+
+        // resolving is one undoable edit
+        doc.undo().unwrap();
+        assert_eq!(doc.annotation_map.tagged_elements[&guy_id].origin, guy);
+    }
+
+    #[test]
+    fn test_revision_diff() {
+        use crate::reports::revision_diff::{self, ChangeKind};
+
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/DraftTest_02.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        // page 2 is the Blue revision of page 1; the revised draft also adds a line to the cave scene,
+        // and drops the last scene entirely
+        let mut revised = doc.screenplay.clone();
+        revised.pages.remove(1);
+        revised.scenes.retain(|_, scene| scene.start.page != 1);
+        for scene in revised.scenes.values_mut() {
+            scene.start.page = 1;
+        }
+        revised.scenes.retain(|_, scene| scene.start.line != 18);
+        revised.pages[1].lines.truncate(18);
+        let mut bat = revised.pages[1].lines[12].clone();
+        bat.text_elements.truncate(1);
+        bat.text_elements[0].text = "A BAT swoops past.".to_string();
+        revised.pages[1].lines.insert(13, bat);
+        doc.screenplay.pages.truncate(2);
+        doc.screenplay.scenes.retain(|_, scene| scene.start.page == 1);
+        // a revision label that changed along with the draft is page furniture, not a change
+        let mut label = doc.screenplay.pages[1].lines[1].clone();
+        label.line_type = Some(screenplay_document::SPType::SP_PAGE_REVISION_LABEL);
+        label.text_elements.truncate(1);
+        label.text_elements[0].text = "Blue Rev. (04/05/25)".to_string();
+        doc.screenplay.pages[1].lines.push(label.clone());
+        label.text_elements[0].text = "Blue Rev. (04/06/25)".to_string();
+        revised.pages[1].lines.push(label);
+
+        let cave = production::ShotID::new();
+        let mut shot = production::Shot::new();
+        shot.shotline = Some(ShotLine::new(coord(1, 10), coord(1, 16)));
+        shot.shot_number = Some(ShotNumber("2A".to_string()));
+        doc.add_shotline(shot, cave.clone()).unwrap();
+        let blah = TaggedElementID::new();
+        doc.add_tagged_element(
+            blah.clone(),
+            TaggedElement {
+                ocurrances: HashSet::new(),
+                origin: coord(1, 20),
+                endpoint: coord(1, 20),
+                tags: vec![],
+                anchors: None,
+            },
+        )
+        .unwrap();
+
+        let diff = revision_diff::get_revision_diff(&doc, &revised);
+        // the headings got new scene numbers, which counts as a change
+        assert_eq!(diff.scenes_with_kind(ChangeKind::Modified).len(), 3);
+        assert_eq!(diff.scenes_with_kind(ChangeKind::Added).len(), 0);
+        let removed = diff.scenes_with_kind(ChangeKind::Removed);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].heading, "INT. HOUSE - DAY*");
+        assert_eq!(removed[0].elements.iter().map(|e| e.lines.len()).sum::<usize>(), 12);
+        assert!(diff
+            .scenes
+            .iter()
+            .flat_map(|scene| scene.elements.iter())
+            .flat_map(|element| element.lines.iter())
+            .all(|line| !line.old_text.as_deref().unwrap_or_default().contains("Rev.")));
+        assert_eq!(removed[0].affected(), vec![&AnnotationKey::TaggedElement(blah.clone())]);
+
+        let cave_scene = diff
+            .scenes
+            .iter()
+            .find(|scene| scene.heading.starts_with("INT. CAVE"))
+            .unwrap();
+        let added: Vec<_> = cave_scene
+            .elements
+            .iter()
+            .flat_map(|element| element.lines.iter())
+            .filter(|line| line.kind == ChangeKind::Added)
+            .collect();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].new, Some((1, 13)));
+        assert_eq!(added[0].affected, vec![AnnotationKey::Shot(cave.clone())]);
+
+        let text = revision_diff::to_text(&diff, &doc).unwrap();
+        assert!(text.starts_with("0 scenes added, 1 removed, 3 modified; 2 annotations affected\n"));
+        assert!(text.contains("      + A BAT swoops past.\n      affects: Shot 2A\n"));
+        let html = revision_diff::to_html(&diff, &doc).unwrap();
+        assert_eq!(html.matches("<section class=\"scene").count(), 4);
+        // positions go by production page number, which for the revised page is its printed "2."
+        assert!(html.contains("<tr class=\"added\"><td>Action</td><td>2:13</td><td></td><td>A BAT swoops past.</td><td>Shot 2A</td></tr>"));
+    }
+
+    #[test]
+    fn test_revisions() {
+        use crate::revisions::letter_suffix;
+
+        // A-page letters skip I and O
+        assert_eq!(letter_suffix(8), "H");
+        assert_eq!(letter_suffix(9), "J");
+        assert_eq!(letter_suffix(25), "AA");
+        let page = |text: &str| ProductionPageNumber::parse(text).unwrap();
+        assert_eq!(page("12."), ProductionPageNumber::new(12));
+        assert!(page("12") < page("12A") && page("12A") < page("12B") && page("12Z") < page("12AA"));
+        assert!(page("12Z") < page("13"));
+        assert_eq!(page("12H").next_a_page().to_string(), "12J");
+        assert_eq!(ProductionPageNumber::parse("12O"), None);
+        assert_eq!(RevisionColor::Cherry.next(), RevisionColor::White);
+        assert_eq!(RevisionColor::from_name("goldenrod"), RevisionColor::Goldenrod);
+
+        // unlocked, the pages go by their printed numbers; the title page has none
+        let Ok(doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        assert_eq!(doc.production_page_number(0), None);
+        assert_eq!(doc.production_page_label(0), "title");
+        assert_eq!(doc.production_page_number(1), Some(ProductionPageNumber::new(1)));
+        let guy = coord(2, 14);
+        assert_eq!(doc.production_coordinate_label(&guy), "2:14");
+
+        // page 2 of DraftTest_02 is the Blue revision of page 1;
+        // it's issued spread over two pages, so the second becomes an A-page
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/DraftTest_02.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let draft = doc.screenplay.clone();
+        doc.screenplay.pages.truncate(2);
+        doc.lock_pages();
+        let mut revised = draft.clone();
+        let blue = revised.pages.remove(2);
+        revised.pages.truncate(1);
+        let mut continued = blue.clone();
+        continued.lines.drain(..10);
+        revised.pages.push(blue);
+        revised.pages[1].lines.truncate(10);
+        revised.pages.push(continued);
+
+        let set = RevisionSet {
+            color: RevisionColor::Blue,
+            date: chrono::NaiveDate::from_ymd_opt(2025, 4, 5).unwrap(),
+        };
+        assert_eq!(set.to_string(), "Blue Rev. (04/05/25)");
+        doc.issue_revision(revised.clone(), set.clone());
+        assert_eq!(doc.screenplay, revised);
+        let numbers: Vec<String> = (0..3).map(|page| doc.production_page_label(page)).collect();
+        assert_eq!(numbers, ["1", "2", "2A"]);
+        assert_eq!(doc.revisions.current(), Some(&set));
+
+        // the renumbered scene headings are marked, the unchanged action isn't
+        assert_eq!(doc.revision_mark(1, 1), Some(&set));
+        assert_eq!(doc.revision_mark(1, 2), None);
+        assert_eq!(doc.revision_mark(2, 1), Some(&set));
+        assert_eq!(doc.revision_mark(2, 2), None);
+
+        // a Pink revision that changes nothing keeps the Blue marks
+        let pink = RevisionSet {
+            color: set.color.next(),
+            date: chrono::NaiveDate::from_ymd_opt(2025, 5, 1).unwrap(),
+        };
+        doc.issue_revision(revised.clone(), pink);
+        assert_eq!(doc.revisions.sets.len(), 2);
+        assert_eq!(doc.revision_mark(2, 1), Some(&set));
+        assert_eq!(doc.production_page_label(2), "2A");
+
+        let Ok(json) = serde_json::to_string(&doc.revisions) else {
+            panic!("Failed to serialize revisions.");
+        };
+        assert!(json.contains("\"2025-04-05\""));
+        let Ok(loaded) = serde_json::from_str::<Revisions>(&json) else {
+            panic!("Failed to deserialize revisions.");
+        };
+        assert_eq!(loaded, doc.revisions);
+    }
+
+    #[test]
+    fn test_shot_numbering() {
+        use crate::shot_numbering::number_key;
+
+        let mut ordered = vec!["13", "12AA", "12B", "12A1", "12Z", "12A"];
+        ordered.sort_by_key(|number| number_key(number));
+        assert_eq!(ordered, ["12A", "12A1", "12B", "12Z", "12AA", "13"]);
+
+        let Ok(mut doc) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        let number = |doc: &ShotlinerDoc, id: &production::ShotID| {
+            doc.annotation_map.shotlines[id]
+                .shot_number
+                .as_ref()
+                .map(|number| number.0.clone())
+                .unwrap_or_default()
+        };
+
+        // scene 1 runs from 1:0 to 2:10, scene 2 from 2:11 to 2:21; neither is numbered in the PDF
+        let first = production::ShotID::new();
+        let second = production::ShotID::new();
+        let third = production::ShotID::new();
+        let inserted = production::ShotID::new();
+        assert_eq!(doc.shot_numbering, ShotNumbering::default());
+        doc.add_numbered_shotline(first.clone(), shot_at(coord(1, 5), coord(1, 8)))
+            .unwrap();
+        doc.add_numbered_shotline(second.clone(), shot_at(coord(2, 12), coord(2, 14)))
+            .unwrap();
+        doc.add_numbered_shotline(third.clone(), shot_at(coord(1, 10), coord(2, 2)))
+            .unwrap();
+        assert_eq!(number(&doc, &first), "1A");
+        assert_eq!(number(&doc, &third), "1B");
+        assert_eq!(number(&doc, &second), "2A");
+
+        // keeping numbers stable, a shot inserted between 1A and 1B is numbered after 1A
+        doc.add_numbered_shotline(inserted.clone(), shot_at(coord(1, 7), coord(1, 9)))
+            .unwrap();
+        assert_eq!(number(&doc, &inserted), "1A1");
+        assert_eq!(number(&doc, &third), "1B");
+        assert!(doc.shot_numbering_problems().is_empty());
+
+        // only the added shot is numbered; other unnumbered shots are left for `number_shots`
+        let unnumbered = production::ShotID::new();
+        doc.add_shotline(shot_at(coord(2, 15), coord(2, 16)), unnumbered.clone())
+            .unwrap();
+        let last = production::ShotID::new();
+        doc.add_numbered_shotline(last.clone(), shot_at(coord(2, 18), coord(2, 19)))
+            .unwrap();
+        assert_eq!(number(&doc, &unnumbered), "");
+        assert_eq!(number(&doc, &last), "2C");
+        doc.undo().unwrap();
+        doc.remove_shotline(&unnumbered).unwrap();
+
+        // renumbering on insert shifts the shots after it, and undoes as one edit
+        doc.shot_numbering.mode = NumberingMode::RenumberOnInsert;
+        assert_eq!(doc.number_shots(), Ok(2));
+        assert_eq!(number(&doc, &inserted), "1B");
+        assert_eq!(number(&doc, &third), "1C");
+        assert_eq!(doc.number_shots(), Ok(0));
+        doc.undo().unwrap();
+        assert_eq!(number(&doc, &inserted), "1A1");
+        assert_eq!(number(&doc, &third), "1B");
+
+        doc.shot_numbering.scheme = NumberingScheme::Sequential;
+        assert_eq!(doc.number_shots(), Ok(4));
+        let numbers: Vec<String> = [&first, &inserted, &third, &second]
+            .iter()
+            .map(|id| number(&doc, id))
+            .collect();
+        assert_eq!(numbers, ["1", "2", "3", "4"]);
+
+        // the first and third shots share a setup, which gets one letter
+        let setup = production::ShotSetup {
+            index: 1,
+            id: "wide on the couch".to_string(),
+        };
+        for id in [&first, &third] {
+            let mut shot = doc.annotation_map.shotlines[id].clone();
+            shot.primary_composition.setup = Some(setup.clone());
+            doc.modify_shotline(id, shot).unwrap();
+        }
+        doc.shot_numbering.scheme = NumberingScheme::SetupBased;
+        doc.number_shots().unwrap();
+        let numbers: Vec<String> = [&first, &inserted, &third, &second]
+            .iter()
+            .map(|id| number(&doc, id))
+            .collect();
+        assert_eq!(numbers, ["1A", "1B", "1A2", "2A"]);
+        assert!(doc.shot_numbering_problems().is_empty());
+
+        // keeping numbers stable, a new shot from a setup follows that setup's shots, not its neighbors
+        let Ok(mut stable) = ShotlinerDoc::from_pdf("test_data/VCR2L.pdf") else {
+            panic!("Couldn't open test screenplay.");
+        };
+        stable.shot_numbering.scheme = NumberingScheme::SetupBased;
+        let with_setup = |start: ScreenplayCoordinate, end: ScreenplayCoordinate| {
+            let mut shot = shot_at(start, end);
+            shot.primary_composition.setup = Some(setup.clone());
+            shot
+        };
+        let wide = production::ShotID::new();
+        let insert = production::ShotID::new();
+        let wide_again = production::ShotID::new();
+        stable.add_numbered_shotline(wide.clone(), with_setup(coord(1, 5), coord(1, 8)))
+            .unwrap();
+        stable.add_numbered_shotline(insert.clone(), shot_at(coord(1, 10), coord(1, 12)))
+            .unwrap();
+        stable.add_numbered_shotline(wide_again.clone(), with_setup(coord(1, 14), coord(1, 16)))
+            .unwrap();
+        let numbers: Vec<String> = [&wide, &insert, &wide_again]
+            .iter()
+            .map(|id| number(&stable, id))
+            .collect();
+        assert_eq!(numbers, ["1A", "1B", "1A2"]);
+        assert!(stable.shot_numbering_problems().is_empty());
+
+        // hand-typed numbers can clash or run backwards
+        doc.shot_numbering.scheme = NumberingScheme::SceneLetter;
+        let mut shot = doc.annotation_map.shotlines[&inserted].clone();
+        shot.shot_number = Some(ShotNumber("1a".to_string()));
+        doc.modify_shotline(&inserted, shot).unwrap();
+        let problems = doc.shot_numbering_problems();
+        assert_eq!(
+            problems,
+            [NumberingProblem::Duplicate {
+                number: "1A".to_string(),
+                shots: vec![first.clone(), inserted.clone()],
+            }]
+        );
+        let mut shot = doc.annotation_map.shotlines[&second].clone();
+        shot.shot_number = Some(ShotNumber("1".to_string()));
+        doc.modify_shotline(&second, shot).unwrap();
+        let problems = doc.shot_numbering_problems();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[1].to_string(), "shot 1 comes after shot 1A2");
+    }
+
     // TODO: TEST MODIFY AND REMOVE
     // TODO: need a ScreenplayRange struct that holds two ScreenplayCoordinates
     #[test]
@@ -70,8 +1981,13 @@ mod tests {
         };
         let mut new_shotliner_doc = ShotlinerDoc {
             screenplay: doc,
+            screenplay_filepath: None,
             command_history: None,
             annotation_map: AnnotationMap::new(),
+            annotation_index: AnnotationIndex::new(),
+            tag_usage_index: TagUsageIndex::new(),
+            revisions: Revisions::default(),
+            shot_numbering: ShotNumbering::default(),
         };
         let new_tag_id = TagID::new();
         let new_tag = Tag {
@@ -96,7 +2012,8 @@ mod tests {
                 line: 4,
                 element: None,
             },
-            unfilmed_lines: None
+            unfilmed_lines: None,
+            anchors: None,
         };
 
         let shot = production::Shot {
@@ -104,7 +2021,8 @@ mod tests {
             shot_number: Some(ShotNumber("1A".to_string())),
             primary_composition: new_composition,
             sub_compositions: None,
-            shotline: Some(shotline)
+            shotline: Some(shotline),
+            completed: false,
         };
         let Ok(_) = new_shotliner_doc.add_shotline(shot, production::ShotID::new()) else {
             panic!("Failed to add Shot.")
@@ -129,6 +2047,7 @@ mod tests {
             origin: new_tagged_region.0,
             endpoint: new_tagged_region.1,
             tags: vec![new_tag_id.clone()],
+            anchors: None,
         };
         let Ok(_) =
             new_shotliner_doc.add_tagged_element(new_tagged_element_id.clone(), new_tagged_element)
@@ -1,19 +1,201 @@
 extern crate shotliner_corelib;
 
-fn main(){
-    /*
-    add shotline 3 7 3 12
-    list shotlines
-    modify shotline (UID (simple index?))
-        shot type
-        tags
-    save doc mydoc.sl
-    undo
-    redo
-    go page 3
-    go page 9
-    export csv
-    export kra
-    
-    */
-}
\ No newline at end of file
+use std::io::{self, BufRead, Read, Write};
+use std::process::ExitCode;
+
+use shotliner_corelib::shell::{self, Shell, ShellCommand};
+use shotliner_corelib::shotliner_document::ShotlinerDoc;
+
+const USAGE: &str = "\
+usage: shotliner <screenplay.pdf | document.sl>
+       shotliner run <script.txt | -> (--pdf <screenplay.pdf> | --doc <document.sl>) [--out <document.sl>]";
+
+fn open_doc(path: &str) -> Result<ShotlinerDoc, String> {
+    if path.ends_with(".sl") {
+        return ShotlinerDoc::load(path).map_err(|e| e.to_string());
+    }
+    ShotlinerDoc::from_pdf(path).map_err(|e| e.to_string())
+}
+
+fn interactive(path: &str) -> ExitCode {
+    let doc = match open_doc(path) {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("couldn't open {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut shell = Shell::new(doc);
+    println!(
+        "opened {} ({} pages). type 'help' for commands.",
+        path,
+        shell.doc.screenplay.pages.len()
+    );
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        let cmd = match shell::parse_line(&line) {
+            Ok(Some(cmd)) => cmd,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        if cmd == ShellCommand::Quit {
+            break;
+        }
+        match shell.run_command(cmd) {
+            Ok(output) => println!("{}", output),
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Where `run` gets its document from.
+enum Source {
+    Pdf(String),
+    Doc(String),
+}
+impl Source {
+    fn path(&self) -> &str {
+        match self {
+            Source::Pdf(path) | Source::Doc(path) => path,
+        }
+    }
+
+    fn flag(&self) -> &'static str {
+        match self {
+            Source::Pdf(_) => "--pdf",
+            Source::Doc(_) => "--doc",
+        }
+    }
+
+    fn open(&self) -> Result<ShotlinerDoc, String> {
+        match self {
+            Source::Pdf(path) => ShotlinerDoc::from_pdf(path).map_err(|e| e.to_string()),
+            Source::Doc(path) => ShotlinerDoc::load(path).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+struct RunArgs {
+    script: String,
+    source: Source,
+    out: Option<String>,
+}
+
+fn parse_run_args(args: &[String]) -> Result<RunArgs, String> {
+    let mut script: Option<String> = None;
+    let mut source: Option<Source> = None;
+    let mut out: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pdf" | "--doc" | "--out" => {
+                let Some(value) = args.next() else {
+                    return Err(format!("{} needs a filepath", arg));
+                };
+                if arg == "--out" {
+                    if out.is_some() {
+                        return Err("--out given more than once".to_string());
+                    }
+                    out = Some(value.clone());
+                    continue;
+                }
+                if let Some(source) = &source {
+                    if source.flag() == arg {
+                        return Err(format!("{} given more than once", arg));
+                    }
+                    return Err("--pdf and --doc are mutually exclusive".to_string());
+                }
+                source = Some(if arg == "--pdf" {
+                    Source::Pdf(value.clone())
+                } else {
+                    Source::Doc(value.clone())
+                });
+            }
+            _ if script.is_none() => script = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let Some(script) = script else {
+        return Err("no script given".to_string());
+    };
+    let Some(source) = source else {
+        return Err("one of --pdf or --doc is required".to_string());
+    };
+    Ok(RunArgs {
+        script: script,
+        source: source,
+        out: out,
+    })
+}
+
+/// Runs a script non-interactively, failing on the first command that fails.
+fn run(args: &[String]) -> ExitCode {
+    let args = match parse_run_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let script = if args.script == "-" {
+        let mut script = String::new();
+        io::stdin().read_to_string(&mut script).map(|_| script)
+    } else {
+        std::fs::read_to_string(&args.script)
+    };
+    let script = match script {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("couldn't read {}: {}", args.script, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let doc = match args.source.open() {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("couldn't open {}: {}", args.source.path(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut shell = Shell::new(doc);
+
+    if let Err((line, e)) = shell.run_script(&script, |output| println!("{}", output)) {
+        eprintln!("{}:{}: {}", args.script, line, e);
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(out) = &args.out
+        && let Err(e) = shell.doc.save(out)
+    {
+        eprintln!("couldn't save {}: {}", out, e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [cmd, rest @ ..] if cmd == "run" => run(rest),
+        [path] if path != "-h" && path != "--help" => interactive(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
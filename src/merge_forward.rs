@@ -0,0 +1,883 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use screenplay_doc_parser_rs::screenplay_document::{
+    Line, ScreenplayCoordinate, ScreenplayDocument,
+};
+
+use crate::annotation_index::AnnotationKey;
+use crate::production::{self, ShotID};
+use crate::reports;
+use crate::revisions::{self, Revisions};
+use crate::shotliner_document::{AnnotationMap, ShotLine, TaggedElement, TaggedElementID};
+use crate::smart_coordinate::{AnchorContext, SmartRange};
+
+/// Lines at least this similar (see `text_similarity`) are taken to be the same line, revised.
+pub const SIMILARITY_THRESHOLD: f64 = 0.6;
+
+/// Gaps bigger than this (old lines times new lines) aren't searched line-by-line,
+/// so that a draft that's been rewritten wholesale doesn't take forever to align.
+const MAX_GAP_CELLS: usize = 4_000_000;
+
+/// A (page, line) position in a screenplay.
+pub type LinePosition = (usize, usize);
+
+type CoordinateRange = (ScreenplayCoordinate, ScreenplayCoordinate);
+
+/// A line's text as it's compared between drafts: lowercase, single-spaced, without revision asterisks.
+pub fn normalize_text(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter(|c| *c != '*')
+        .collect::<String>()
+        .to_lowercase();
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// How alike two (normalized) strings are, from 0.0 to 1.0, by the character pairs they share.
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    Bigrams::new(a).similarity(&Bigrams::new(b))
+}
+
+/// A string's character pairs, counted once so it can be compared against many others.
+struct Bigrams {
+    counts: HashMap<(char, char), usize>,
+    total: usize,
+}
+impl Bigrams {
+    fn new(text: &str) -> Self {
+        let mut counts: HashMap<(char, char), usize> = HashMap::new();
+        let mut total = 0;
+        let mut chars = text.chars();
+        if let Some(mut previous) = chars.next() {
+            for c in chars {
+                *counts.entry((previous, c)).or_default() += 1;
+                total += 1;
+                previous = c;
+            }
+        }
+        Bigrams {
+            counts: counts,
+            total: total,
+        }
+    }
+
+    /// `text_similarity` of the strings these were counted from, except that equal strings too short
+    /// to have any pairs count as 0.0.
+    fn similarity(&self, other: &Bigrams) -> f64 {
+        if self.total == 0 || other.total == 0 {
+            return 0.0;
+        }
+        let (fewer, more) = if self.counts.len() <= other.counts.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let shared: usize = fewer
+            .counts
+            .iter()
+            .map(|(bigram, count)| (*count).min(more.counts.get(bigram).copied().unwrap_or(0)))
+            .sum();
+        2.0 * shared as f64 / (self.total + other.total) as f64
+    }
+}
+
+/// A line of the old draft and the line of the new draft it became.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineMatch {
+    pub old: LinePosition,
+    pub new: LinePosition,
+    /// Whether the text is unchanged, as far as `normalize_text` is concerned.
+    pub exact: bool,
+}
+
+/// Which lines of one draft of a screenplay became which lines of another.
+///
+/// Unchanged lines are matched first, then the lines left between them are paired up by similarity,
+/// so revised lines are still followed. Matches never cross: text that was moved elsewhere
+/// counts as deleted in one place and added in another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineAlignment {
+    /// In script order, in both drafts.
+    pub matches: Vec<LineMatch>,
+    /// Every line of the old draft, in order.
+    pub old_lines: Vec<LinePosition>,
+    /// Every line of the new draft, in order.
+    pub new_lines: Vec<LinePosition>,
+    old_to_match: HashMap<LinePosition, usize>,
+    new_to_match: HashMap<LinePosition, usize>,
+    new_order: HashMap<LinePosition, usize>,
+}
+impl LineAlignment {
+    pub fn new(old: &ScreenplayDocument, new: &ScreenplayDocument) -> Self {
+        let (old_lines, old_text) = flatten(old);
+        let (new_lines, new_text) = flatten(new);
+
+        let mut exact: Vec<(usize, usize)> = Vec::new();
+        align_exact(
+            &old_text,
+            &new_text,
+            (0, old_text.len()),
+            (0, new_text.len()),
+            &mut exact,
+        );
+        exact.sort();
+
+        // pair up what's left between the unchanged lines
+        let mut pairs: Vec<(usize, usize, bool)> = Vec::new();
+        let mut gap_start = (0, 0);
+        for (o, n) in exact
+            .iter()
+            .copied()
+            .chain(std::iter::once((old_text.len(), new_text.len())))
+        {
+            align_similar(
+                &old_text,
+                &new_text,
+                (gap_start.0, o),
+                (gap_start.1, n),
+                &mut pairs,
+            );
+            if o < old_text.len() {
+                pairs.push((o, n, true));
+            }
+            gap_start = (o + 1, n + 1);
+        }
+
+        let matches: Vec<LineMatch> = pairs
+            .into_iter()
+            .map(|(o, n, exact)| LineMatch {
+                old: old_lines[o],
+                new: new_lines[n],
+                exact: exact,
+            })
+            .collect();
+        LineAlignment {
+            old_to_match: matches
+                .iter()
+                .enumerate()
+                .map(|(i, m)| (m.old, i))
+                .collect(),
+            new_to_match: matches
+                .iter()
+                .enumerate()
+                .map(|(i, m)| (m.new, i))
+                .collect(),
+            new_order: new_lines
+                .iter()
+                .enumerate()
+                .map(|(i, pos)| (*pos, i))
+                .collect(),
+            matches: matches,
+            old_lines: old_lines,
+            new_lines: new_lines,
+        }
+    }
+
+    /// Where a line of the old draft went, if it survived.
+    pub fn new_line(&self, old: &LinePosition) -> Option<&LineMatch> {
+        self.old_to_match.get(old).map(|i| &self.matches[*i])
+    }
+
+    /// Where a line of the new draft came from, if it isn't new.
+    pub fn old_line(&self, new: &LinePosition) -> Option<&LineMatch> {
+        self.new_to_match.get(new).map(|i| &self.matches[*i])
+    }
+
+    /// Where a coordinate of the old draft is in the new one, down to the text element if it has one.
+    pub fn map_coordinate(
+        &self,
+        old: &ScreenplayDocument,
+        new: &ScreenplayDocument,
+        coord: &ScreenplayCoordinate,
+    ) -> Option<ScreenplayCoordinate> {
+        let line_match = self.new_line(&(coord.page, coord.line))?;
+        Some(ScreenplayCoordinate {
+            page: line_match.new.0,
+            line: line_match.new.1,
+            element: coord
+                .element
+                .and_then(|element| map_element(old, new, line_match, element)),
+        })
+    }
+
+    /// Where the lines from `start` to `end` (inclusive) of the old draft went,
+    /// as one range for every stretch of them that's still together.
+    ///
+    /// Returns nothing if every line was deleted.
+    pub fn map_range(
+        &self,
+        old: &ScreenplayDocument,
+        new: &ScreenplayDocument,
+        start: &ScreenplayCoordinate,
+        end: &ScreenplayCoordinate,
+    ) -> Vec<CoordinateRange> {
+        let mapped: Vec<(LinePosition, &LineMatch)> = reports::get_lines_in_range(old, start, end)
+            .into_iter()
+            .filter_map(|(coord, _)| {
+                let position = (coord.page, coord.line);
+                Some((position, self.new_line(&position)?))
+            })
+            .collect();
+
+        let mut runs: Vec<Vec<(LinePosition, &LineMatch)>> = Vec::new();
+        for (position, line_match) in mapped {
+            let follows = runs
+                .last()
+                .and_then(|run| run.last())
+                .is_some_and(|(_, prev)| {
+                    self.new_order[&line_match.new] == self.new_order[&prev.new] + 1
+                });
+            match runs.last_mut() {
+                Some(run) if follows => run.push((position, line_match)),
+                _ => runs.push(vec![(position, line_match)]),
+            }
+        }
+
+        runs.into_iter()
+            .map(|run| {
+                let (first_old, first) = run[0];
+                let (last_old, last) = run[run.len() - 1];
+                let start_element = match start.element {
+                    Some(element) if first_old == (start.page, start.line) => {
+                        map_element(old, new, first, element)
+                    }
+                    _ => None,
+                };
+                let end_element = match end.element {
+                    Some(element) if last_old == (end.page, end.line) => {
+                        map_element(old, new, last, element)
+                    }
+                    _ => None,
+                };
+                (
+                    ScreenplayCoordinate {
+                        page: first.new.0,
+                        line: first.new.1,
+                        element: start_element,
+                    },
+                    ScreenplayCoordinate {
+                        page: last.new.0,
+                        line: last.new.1,
+                        element: end_element,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Lines of the new draft between `start` and `end` (inclusive), in order.
+    pub fn new_lines_between(&self, start: &LinePosition, end: &LinePosition) -> &[LinePosition] {
+        match (self.new_order.get(start), self.new_order.get(end)) {
+            (Some(a), Some(b)) if a <= b => &self.new_lines[*a..=*b],
+            _ => &[],
+        }
+    }
+}
+
+/// Every line position of the screenplay, and its normalized text.
+fn flatten(screenplay: &ScreenplayDocument) -> (Vec<LinePosition>, Vec<String>) {
+    let mut positions: Vec<LinePosition> = Vec::new();
+    let mut text: Vec<String> = Vec::new();
+    for (page_index, page) in screenplay.pages.iter().enumerate() {
+        for (line_index, line) in page.lines.iter().enumerate() {
+            positions.push((page_index, line_index));
+            text.push(normalize_text(&reports::get_line_text(line)));
+        }
+    }
+    (positions, text)
+}
+
+/// Matches unchanged lines in `old[o.0..o.1]` and `new[n.0..n.1]`.
+///
+/// Lines that are the same at either end are matched straight off; then lines that appear exactly once
+/// on both sides anchor the rest (as in a patience diff), and what's left between them is recursed into.
+/// Blank lines never match.
+fn align_exact(
+    old: &[String],
+    new: &[String],
+    mut o: (usize, usize),
+    mut n: (usize, usize),
+    out: &mut Vec<(usize, usize)>,
+) {
+    while o.0 < o.1 && n.0 < n.1 && !old[o.0].is_empty() && old[o.0] == new[n.0] {
+        out.push((o.0, n.0));
+        o.0 += 1;
+        n.0 += 1;
+    }
+    while o.0 < o.1 && n.0 < n.1 && !old[o.1 - 1].is_empty() && old[o.1 - 1] == new[n.1 - 1] {
+        out.push((o.1 - 1, n.1 - 1));
+        o.1 -= 1;
+        n.1 -= 1;
+    }
+    if o.0 >= o.1 || n.0 >= n.1 {
+        return;
+    }
+
+    let mut counts: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (i, text) in old.iter().enumerate().take(o.1).skip(o.0) {
+        let entry = counts.entry(text.as_str()).or_default();
+        entry.0 += 1;
+        entry.2 = i;
+    }
+    for (j, text) in new.iter().enumerate().take(n.1).skip(n.0) {
+        let entry = counts.entry(text.as_str()).or_default();
+        entry.1 += 1;
+        entry.3 = j;
+    }
+    let mut unique: Vec<(usize, usize)> = counts
+        .iter()
+        .filter(|(text, (in_old, in_new, _, _))| !text.is_empty() && *in_old == 1 && *in_new == 1)
+        .map(|(_, (_, _, i, j))| (*i, *j))
+        .collect();
+    unique.sort();
+    let anchors = longest_increasing(&unique);
+
+    if anchors.is_empty() {
+        lcs(old, new, o, n, out);
+        return;
+    }
+    let mut from = (o.0, n.0);
+    for (i, j) in anchors {
+        align_exact(old, new, (from.0, i), (from.1, j), out);
+        out.push((i, j));
+        from = (i + 1, j + 1);
+    }
+    align_exact(old, new, (from.0, o.1), (from.1, n.1), out);
+}
+
+/// The longest run of `pairs` (sorted by their first index) whose second index increases too.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // tails[k] is the index into `pairs` of the smallest tail of an increasing run of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (index, (_, j)) in pairs.iter().enumerate() {
+        let length = tails.partition_point(|tail| pairs[*tail].1 < *j);
+        previous[index] = length.checked_sub(1).map(|k| tails[k]);
+        if length == tails.len() {
+            tails.push(index);
+        } else {
+            tails[length] = index;
+        }
+    }
+    let mut run: Vec<(usize, usize)> = Vec::new();
+    let mut next = tails.last().copied();
+    while let Some(index) = next {
+        run.push(pairs[index]);
+        next = previous[index];
+    }
+    run.reverse();
+    run
+}
+
+/// A plain longest-common-subsequence match, for stretches without any unique lines to anchor on.
+fn lcs(
+    old: &[String],
+    new: &[String],
+    o: (usize, usize),
+    n: (usize, usize),
+    out: &mut Vec<(usize, usize)>,
+) {
+    let (rows, cols) = (o.1 - o.0, n.1 - n.0);
+    if rows * cols > MAX_GAP_CELLS {
+        return;
+    }
+    let mut table = vec![0u32; (rows + 1) * (cols + 1)];
+    let at = |i: usize, j: usize| i * (cols + 1) + j;
+    for i in (0..rows).rev() {
+        for j in (0..cols).rev() {
+            table[at(i, j)] = if !old[o.0 + i].is_empty() && old[o.0 + i] == new[n.0 + j] {
+                table[at(i + 1, j + 1)] + 1
+            } else {
+                table[at(i + 1, j)].max(table[at(i, j + 1)])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < rows && j < cols {
+        if !old[o.0 + i].is_empty() && old[o.0 + i] == new[n.0 + j] {
+            out.push((o.0 + i, n.0 + j));
+            i += 1;
+            j += 1;
+        } else if table[at(i + 1, j)] >= table[at(i, j + 1)] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+}
+
+/// Pairs up revised lines in a gap between unchanged ones, keeping them in order
+/// and getting the most total similarity out of the pairs that clear `SIMILARITY_THRESHOLD`.
+fn align_similar(
+    old: &[String],
+    new: &[String],
+    o: (usize, usize),
+    n: (usize, usize),
+    out: &mut Vec<(usize, usize, bool)>,
+) {
+    if o.0 >= o.1 || n.0 >= n.1 {
+        return;
+    }
+    let (rows, cols) = (o.1 - o.0, n.1 - n.0);
+    if rows * cols > MAX_GAP_CELLS {
+        return;
+    }
+    let old_bigrams: Vec<Bigrams> = old[o.0..o.1].iter().map(|text| Bigrams::new(text)).collect();
+    let new_bigrams: Vec<Bigrams> = new[n.0..n.1].iter().map(|text| Bigrams::new(text)).collect();
+    let similarity = |i: usize, j: usize| {
+        let score = if old[o.0 + i] == new[n.0 + j] {
+            1.0
+        } else {
+            old_bigrams[i].similarity(&new_bigrams[j])
+        };
+        if score >= SIMILARITY_THRESHOLD {
+            score
+        } else {
+            0.0
+        }
+    };
+    let mut scores = vec![0.0f64; rows * cols];
+    let mut table = vec![0.0f64; (rows + 1) * (cols + 1)];
+    let at = |i: usize, j: usize| i * (cols + 1) + j;
+    for i in (0..rows).rev() {
+        for j in (0..cols).rev() {
+            let score = similarity(i, j);
+            scores[i * cols + j] = score;
+            let paired = if score > 0.0 {
+                table[at(i + 1, j + 1)] + score
+            } else {
+                0.0
+            };
+            table[at(i, j)] = paired.max(table[at(i + 1, j)]).max(table[at(i, j + 1)]);
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < rows && j < cols {
+        let score = scores[i * cols + j];
+        if score > 0.0 && table[at(i, j)] == table[at(i + 1, j + 1)] + score {
+            out.push((o.0 + i, n.0 + j, false));
+            i += 1;
+            j += 1;
+        } else if table[at(i + 1, j)] >= table[at(i, j + 1)] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+}
+
+fn get_line<'a>(screenplay: &'a ScreenplayDocument, position: &LinePosition) -> Option<&'a Line> {
+    screenplay.pages.get(position.0)?.lines.get(position.1)
+}
+
+/// Which text element of the new line a text element of the old line became.
+///
+/// Elements of unchanged lines stay put. Otherwise it's the nearest element with the same text,
+/// or failing that, the one in the same place (or the last one, if the line got shorter).
+fn map_element(
+    old: &ScreenplayDocument,
+    new: &ScreenplayDocument,
+    line_match: &LineMatch,
+    element: u64,
+) -> Option<u64> {
+    let old_line = get_line(old, &line_match.old)?;
+    let new_line = get_line(new, &line_match.new)?;
+    if new_line.text_elements.is_empty() {
+        return None;
+    }
+    let element = element as usize;
+    if line_match.exact && old_line.text_elements.len() == new_line.text_elements.len() {
+        return Some(element.min(new_line.text_elements.len() - 1) as u64);
+    }
+    let same_text = old_line
+        .text_elements
+        .get(element)
+        .map(|old_element| normalize_text(&old_element.text))
+        .and_then(|text| {
+            new_line
+                .text_elements
+                .iter()
+                .enumerate()
+                .filter(|(_, new_element)| normalize_text(&new_element.text) == text)
+                .min_by_key(|(index, _)| index.abs_diff(element))
+                .map(|(index, _)| index)
+        });
+    Some(same_text.unwrap_or(element.min(new_line.text_elements.len() - 1)) as u64)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// Still at the same coordinates.
+    Unchanged,
+    /// Its text survived in one piece, somewhere else (or with lines deleted from it).
+    Moved,
+    /// Its text is now in several places, e.g. because lines were added in the middle of it.
+    Split,
+    /// All of its text was deleted.
+    Orphaned,
+}
+
+/// What happened to one annotation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergedAnnotation {
+    pub annotation: AnnotationKey,
+    pub outcome: MergeOutcome,
+    /// For a shot, its ShotLine. For a TaggedElement, every range it had.
+    pub old_ranges: Vec<CoordinateRange>,
+    pub new_ranges: Vec<CoordinateRange>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergeReport {
+    /// Shots first, then TaggedElements, each sorted by where they were in the old draft.
+    pub annotations: Vec<MergedAnnotation>,
+    /// Sub-compositions that couldn't be placed: their shot was orphaned,
+    /// or another sub-composition of the shot already landed on the same line.
+    pub orphaned_sub_compositions: Vec<(ShotID, ScreenplayCoordinate)>,
+    /// TaggedElements whose text was all deleted, as they were in the old draft.
+    /// They're left out of the merged AnnotationMap, since a TaggedElement can't be unplaced.
+    pub orphaned_tagged_elements: Vec<(TaggedElementID, TaggedElement)>,
+    /// Production page numbers of each draft, by page index (see `Revisions::page_labels`).
+    pub old_page_labels: Vec<String>,
+    pub new_page_labels: Vec<String>,
+}
+impl MergeReport {
+    pub fn with_outcome(&self, outcome: MergeOutcome) -> Vec<&MergedAnnotation> {
+        self.annotations
+            .iter()
+            .filter(|merged| merged.outcome == outcome)
+            .collect()
+    }
+
+    /// A summary line, then every annotation that didn't stay where it was,
+    /// by production page number (see `old_page_labels` and `new_page_labels`).
+    pub fn to_text(&self) -> Result<String, std::fmt::Error> {
+        let count = |outcome| self.with_outcome(outcome).len();
+        let mut text = String::new();
+        writeln!(
+            text,
+            "{} unchanged, {} moved, {} split, {} orphaned",
+            count(MergeOutcome::Unchanged),
+            count(MergeOutcome::Moved),
+            count(MergeOutcome::Split),
+            count(MergeOutcome::Orphaned)
+        )?;
+        let range = |page_labels: &[String], (start, end): &CoordinateRange| {
+            format!(
+                "{}-{}",
+                revisions::position_label(page_labels, start.page, start.line),
+                revisions::position_label(page_labels, end.page, end.line)
+            )
+        };
+        for merged in &self.annotations {
+            if merged.outcome == MergeOutcome::Unchanged {
+                continue;
+            }
+            let (kind, id) = match &merged.annotation {
+                AnnotationKey::Shot(id) => ("shot", **id),
+                AnnotationKey::TaggedElement(id) => ("tagged element", **id),
+            };
+            let old: Vec<String> = merged
+                .old_ranges
+                .iter()
+                .map(|r| range(&self.old_page_labels, r))
+                .collect();
+            let new: Vec<String> = merged
+                .new_ranges
+                .iter()
+                .map(|r| range(&self.new_page_labels, r))
+                .collect();
+            writeln!(
+                text,
+                "{:?}: {} {} {} -> {}",
+                merged.outcome,
+                kind,
+                id,
+                old.join(", "),
+                if new.is_empty() {
+                    "(deleted)".to_string()
+                } else {
+                    new.join(", ")
+                }
+            )?;
+        }
+        for (shot_id, coord) in &self.orphaned_sub_compositions {
+            writeln!(
+                text,
+                "Orphaned: sub-composition of shot {} at {}",
+                **shot_id,
+                revisions::position_label(&self.old_page_labels, coord.page, coord.line)
+            )?;
+        }
+        Ok(text)
+    }
+}
+
+fn merge_shot(
+    alignment: &LineAlignment,
+    old: &ScreenplayDocument,
+    new: &ScreenplayDocument,
+    id: &ShotID,
+    shot: &production::Shot,
+    report: &mut MergeReport,
+) -> production::Shot {
+    let mut merged = shot.clone();
+    let Some(shotline) = &shot.shotline else {
+        return merged;
+    };
+    let old_ranges = vec![(shotline.start.clone(), shotline.end.clone())];
+    let runs = alignment.map_range(old, new, &shotline.start, &shotline.end);
+
+    let mut sub_compositions: Vec<(&ScreenplayCoordinate, &production::ShotComposition)> =
+        shot.sub_compositions.iter().flatten().collect();
+    sub_compositions.sort_by_key(|(coord, _)| (coord.page, coord.line, coord.element));
+
+    if runs.is_empty() {
+        merged.shotline = None;
+        merged.sub_compositions = None;
+        for (coord, _) in sub_compositions {
+            report
+                .orphaned_sub_compositions
+                .push((id.clone(), coord.clone()));
+        }
+        report.annotations.push(MergedAnnotation {
+            annotation: AnnotationKey::Shot(id.clone()),
+            outcome: MergeOutcome::Orphaned,
+            old_ranges: old_ranges,
+            new_ranges: vec![],
+        });
+        return merged;
+    }
+
+    let position = |coord: &ScreenplayCoordinate| alignment.new_order[&(coord.page, coord.line)];
+    let start = runs
+        .iter()
+        .map(|(start, _)| start)
+        .min_by_key(|c| position(c))
+        .cloned();
+    let end = runs
+        .iter()
+        .map(|(_, end)| end)
+        .max_by_key(|c| position(c))
+        .cloned();
+    let (Some(start), Some(end)) = (start, end) else {
+        return merged;
+    };
+    let mut new_shotline = ShotLine::new(start.clone(), end.clone());
+    // re-anchored onto the new draft by `merge_forward`, once everything's been merged
+    new_shotline.anchors = shotline.anchors.clone();
+
+    // lines that were unfilmed stay unfilmed; lines that weren't part of the shot before
+    // (added in the middle of it, or moved there) weren't filmed either
+    let covered: HashSet<LinePosition> = runs
+        .iter()
+        .flat_map(|(a, b)| alignment.new_lines_between(&(a.page, a.line), &(b.page, b.line)))
+        .copied()
+        .collect();
+    let mut unfilmed: HashSet<ScreenplayCoordinate> = shotline
+        .unfilmed_lines
+        .iter()
+        .flatten()
+        .filter_map(|coord| alignment.map_coordinate(old, new, coord))
+        .collect();
+    for position in alignment.new_lines_between(&(start.page, start.line), &(end.page, end.line)) {
+        if !covered.contains(position) {
+            unfilmed.insert(ScreenplayCoordinate {
+                page: position.0,
+                line: position.1,
+                element: None,
+            });
+        }
+    }
+    if !unfilmed.is_empty() {
+        new_shotline.unfilmed_lines = Some(unfilmed);
+    }
+
+    // a sub-composition whose line was deleted starts on the next line of the shot that's left,
+    // or the last one if there isn't a next
+    if shot.sub_compositions.is_some() {
+        let surviving: Vec<(LinePosition, &LineMatch)> =
+            reports::get_lines_in_range(old, &shotline.start, &shotline.end)
+                .into_iter()
+                .filter_map(|(coord, _)| {
+                    let position = (coord.page, coord.line);
+                    Some((position, alignment.new_line(&position)?))
+                })
+                .collect();
+        let mut new_sub_compositions: HashMap<ScreenplayCoordinate, production::ShotComposition> =
+            HashMap::new();
+        for (coord, composition) in sub_compositions {
+            let new_coord = alignment.map_coordinate(old, new, coord).or_else(|| {
+                let (_, line_match) = surviving
+                    .iter()
+                    .find(|(position, _)| *position > (coord.page, coord.line))
+                    .or(surviving.last())?;
+                Some(ScreenplayCoordinate {
+                    page: line_match.new.0,
+                    line: line_match.new.1,
+                    element: None,
+                })
+            });
+            match new_coord {
+                Some(new_coord) if !new_sub_compositions.contains_key(&new_coord) => {
+                    new_sub_compositions.insert(new_coord, composition.clone());
+                }
+                _ => report
+                    .orphaned_sub_compositions
+                    .push((id.clone(), coord.clone())),
+            }
+        }
+        merged.sub_compositions = Some(new_sub_compositions);
+    }
+
+    let outcome = if runs.len() > 1 {
+        MergeOutcome::Split
+    } else if Some(&new_shotline) == shot.shotline.as_ref()
+        && merged.sub_compositions == shot.sub_compositions
+    {
+        MergeOutcome::Unchanged
+    } else {
+        MergeOutcome::Moved
+    };
+    merged.shotline = Some(new_shotline);
+    report.annotations.push(MergedAnnotation {
+        annotation: AnnotationKey::Shot(id.clone()),
+        outcome: outcome,
+        old_ranges: old_ranges,
+        new_ranges: runs,
+    });
+    merged
+}
+
+fn merge_tagged_element(
+    alignment: &LineAlignment,
+    old: &ScreenplayDocument,
+    new: &ScreenplayDocument,
+    id: &TaggedElementID,
+    tagged_element: &TaggedElement,
+    report: &mut MergeReport,
+) -> Option<TaggedElement> {
+    let old_ranges = reports::get_tagged_element_ranges(tagged_element);
+    let mut new_ranges: Vec<CoordinateRange> = Vec::new();
+    let mut split = false;
+    for (start, end) in &old_ranges {
+        let runs = alignment.map_range(old, new, start, end);
+        split |= runs.len() > 1;
+        for run in runs {
+            if !new_ranges.contains(&run) {
+                new_ranges.push(run);
+            }
+        }
+    }
+
+    let outcome = if new_ranges.is_empty() {
+        MergeOutcome::Orphaned
+    } else if split {
+        MergeOutcome::Split
+    } else if new_ranges == old_ranges {
+        MergeOutcome::Unchanged
+    } else {
+        MergeOutcome::Moved
+    };
+    report.annotations.push(MergedAnnotation {
+        annotation: AnnotationKey::TaggedElement(id.clone()),
+        outcome: outcome,
+        old_ranges: old_ranges,
+        new_ranges: new_ranges.clone(),
+    });
+    if outcome == MergeOutcome::Orphaned {
+        return None;
+    }
+
+    // the primary range is whatever's left of the first one; if the old ocurrances listed
+    // the primary range too, the new ones do as well
+    let primary = new_ranges[0].clone();
+    let primary_was_listed = tagged_element.ocurrances.contains(&(
+        tagged_element.origin.clone(),
+        tagged_element.endpoint.clone(),
+    ));
+    let ocurrances: HashSet<CoordinateRange> = new_ranges
+        .into_iter()
+        .skip(if primary_was_listed { 0 } else { 1 })
+        .collect();
+    Some(TaggedElement {
+        ocurrances: ocurrances,
+        origin: primary.0,
+        endpoint: primary.1,
+        tags: tagged_element.tags.clone(),
+        anchors: tagged_element.anchors.clone(),
+    })
+}
+
+/// Moves every annotation of the old draft onto the new draft, by matching up the lines of the two.
+///
+/// Orphaned shots keep everything but their ShotLine and sub-compositions; orphaned TaggedElements are left out,
+/// and handed back in the MergeReport instead.
+/// Tags, groups and setups aren't anywhere in the screenplay, so they're carried over as they are.
+pub fn merge_forward(
+    old: &ScreenplayDocument,
+    new: &ScreenplayDocument,
+    annotation_map: &AnnotationMap,
+) -> (AnnotationMap, MergeReport) {
+    let alignment = LineAlignment::new(old, new);
+    let mut report = MergeReport {
+        old_page_labels: Revisions::default().page_labels(old),
+        new_page_labels: Revisions::default().page_labels(new),
+        ..MergeReport::default()
+    };
+    let mut merged = annotation_map.clone();
+
+    let mut shots: Vec<(&ShotID, &production::Shot)> = annotation_map.shotlines.iter().collect();
+    shots.sort_by_key(|(id, shot)| {
+        (
+            shot.shotline
+                .as_ref()
+                .map(|sl| (sl.start.page, sl.start.line, sl.end.page, sl.end.line)),
+            ***id,
+        )
+    });
+    for (id, shot) in shots {
+        let new_shot = merge_shot(&alignment, old, new, id, shot, &mut report);
+        merged.shotlines.insert(id.clone(), new_shot);
+    }
+
+    let mut tagged_elements: Vec<(&TaggedElementID, &TaggedElement)> =
+        annotation_map.tagged_elements.iter().collect();
+    tagged_elements
+        .sort_by_key(|(id, te)| ((te.origin.page, te.origin.line, te.origin.element), ***id));
+    for (id, tagged_element) in tagged_elements {
+        match merge_tagged_element(&alignment, old, new, id, tagged_element, &mut report) {
+            Some(new_element) => {
+                merged.tagged_elements.insert(id.clone(), new_element);
+            }
+            None => {
+                merged.tagged_elements.remove(id);
+                report
+                    .orphaned_tagged_elements
+                    .push((id.clone(), tagged_element.clone()));
+            }
+        }
+    }
+
+    // anything that was anchored gets anchored again, to where it is in the new draft
+    let context = AnchorContext::new(new);
+    for shot in merged.shotlines.values_mut() {
+        if let Some(shotline) = &mut shot.shotline
+            && shotline.anchors.is_some()
+        {
+            shotline.anchors = SmartRange::anchor(&context, &shotline.start, &shotline.end);
+        }
+    }
+    for tagged_element in merged.tagged_elements.values_mut() {
+        if tagged_element.anchors.is_some() {
+            tagged_element.anchors =
+                SmartRange::anchor(&context, &tagged_element.origin, &tagged_element.endpoint);
+        }
+    }
+
+    (merged, report)
+}
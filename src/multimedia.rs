@@ -1,11 +1,27 @@
-#[derive(Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum MediaType {
     Image,
     // could add other media types down the road...
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MediaLink {
     filepath: String,
     media_type: MediaType,
 }
+impl MediaLink {
+    pub fn new(filepath: String, media_type: MediaType) -> Self {
+        MediaLink {
+            filepath: filepath,
+            media_type: media_type,
+        }
+    }
+    pub fn filepath(&self) -> &str {
+        &self.filepath
+    }
+    pub fn media_type(&self) -> &MediaType {
+        &self.media_type
+    }
+}
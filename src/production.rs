@@ -2,12 +2,13 @@ use std::{collections::{HashMap, HashSet}, fmt::Error, ops::{Deref, DerefMut, Ra
 
 use chrono::TimeZone;
 use screenplay_doc_parser_rs::screenplay_document::{self};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{shotliner_document::{ShotLine, Tag, TagID}, multimedia::MediaLink};
 
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum Department {
     Production,
     Art,
@@ -35,8 +36,44 @@ pub enum Department {
     Other(String),
 
 }
+impl Department {
+    /// Every department but `Other`, in the order reports list them.
+    pub const ALL: [Department; 20] = [
+        Department::Production,
+        Department::Art,
+        Department::Wardrobe,
+        Department::HairMakeup,
+        Department::Camera,
+        Department::Sound,
+        Department::Electric,
+        Department::LightingGrip,
+        Department::Props,
+        Department::PracticalFX,
+        Department::VisualFX,
+        Department::Stunts,
+        Department::Animals,
+        Department::Vehicles,
+        Department::Dance,
+        Department::Choreography,
+        Department::Pyrotechnics,
+        Department::CraftServices,
+        Department::Transportation,
+        Department::Miscellaneous,
+    ];
+
+    /// Sorts departments in `ALL` order, followed by `Other` departments alphabetically.
+    pub fn report_order(&self) -> (usize, String) {
+        match self {
+            Department::Other(name) => (Department::ALL.len(), name.clone()),
+            department => (
+                Department::ALL.iter().position(|d| d == department).unwrap_or(0),
+                String::new(),
+            ),
+        }
+    }
+}
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ShotID(Uuid);
 impl Deref for ShotID {
     type Target = Uuid;
@@ -51,12 +88,15 @@ impl ShotID {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Shot {
     pub shot_number: Option<ShotNumber>,
     pub primary_composition: ShotComposition,
+    #[serde(with = "crate::serializables::serde_helpers::sub_compositions")]
     pub sub_compositions: Option<HashMap<screenplay_document::ScreenplayCoordinate, ShotComposition>>,
-    pub shotline: Option<crate::shotliner_document::ShotLine>
+    pub shotline: Option<crate::shotliner_document::ShotLine>,
+    #[serde(default)]
+    pub completed: bool,
 }
 impl Shot {
     pub fn new(
@@ -67,6 +107,7 @@ impl Shot {
             primary_composition: ShotComposition::new(), // default is WIDE
             sub_compositions: None,
             shotline: None,
+            completed: false,
         }
     }
 }
@@ -79,7 +120,7 @@ pub struct ProductionLocation {
 
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ShotType {
     ExtremeWide,
     Wide,
@@ -91,7 +132,7 @@ pub enum ShotType {
 }
 
 //TODO: Make these better, maybe split up more categories    
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ShotSubType {
     //TwoShot, should just be a separate "number_of_subjects" in a higher struct
     Trucking,
@@ -105,13 +146,13 @@ pub enum ShotSubType {
 
 
 ///Represents a specific, discrete position to place the camera.    
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ShotSetup {
     pub index: u64, // simple numerical counter
     pub id: String, // uuid?
 } 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ShotNumber(pub String);
 
 
@@ -135,7 +176,7 @@ impl _other_shot_id {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ShotComposition {
 
     // Shot Composition (angle, staging, movement, etc.)
@@ -147,7 +188,8 @@ pub struct ShotComposition {
     pub camera_metadata: Option<CameraMetadata>,
     
     pub tags: Vec<TagID>,
-    //pub media: Vec<crate::multimedia::MediaLink>
+    #[serde(default)]
+    pub media: Vec<crate::multimedia::MediaLink>,
 }
 impl ShotComposition {
     pub fn new() -> Self {
@@ -157,7 +199,7 @@ impl ShotComposition {
             setup: None, 
             camera_metadata: None, 
             tags: Vec::new(), 
-            //media: Vec::new() 
+            media: Vec::new(),
         }
         
     }
@@ -173,7 +215,9 @@ impl ShotComposition {
     pub fn camera_metadata(&mut self, camera_metadata: Option<CameraMetadata>) {
         self.camera_metadata = camera_metadata;
     }
-    //pub fn add_media(&mut self, media_link: MediaLink)
+    pub fn add_media(&mut self, media_link: MediaLink) {
+        self.media.push(media_link);
+    }
     pub fn add_tag(&mut self, tag: &TagID) -> Result<(), Error>{
         if self.tags.contains(tag) {
             return Err(Error);
@@ -194,9 +238,17 @@ impl ShotComposition {
 #[derive(Clone, Debug)]
 pub struct Prop(String);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CameraMetadata {
     lens_mm: u64,
     //Camera body, make, model, resolution, codec, etc.
 
 }
+impl CameraMetadata {
+    pub fn new(lens_mm: u64) -> Self {
+        CameraMetadata { lens_mm: lens_mm }
+    }
+    pub fn lens_mm(&self) -> u64 {
+        self.lens_mm
+    }
+}
@@ -1,33 +1,54 @@
+use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::ops::Range;
 
 use crate::shotliner_document::{self, ShotlinerDoc};
-use crate::{production, serializables};
-use screenplay_doc_parser_rs::screenplay_document;
+use crate::production;
+use screenplay_doc_parser_rs::screenplay_document::{self, SPType};
+use screenplay_doc_parser_rs::reports as screenplay_reports;
+
+/// Measures scenes in eighths of a page, and totals them per shooting day and per document.
+pub mod page_eighths;
+use page_eighths::{EighthsSettings, PageEighths};
+
+/// Per-scene breakdown sheets: cast, location, and every tagged element grouped by department.
+pub mod breakdown_sheets;
+
+/// Every element tagged for one department, with the scenes and pages it appears on.
+pub mod element_lists;
+
+/// What changed between two drafts of the screenplay, scene by scene, and which annotations it touches.
+pub mod revision_diff;
 
-// FOR REFERENCE...
 pub struct ShotListEntry<'a> {
+    pub shot_id: &'a production::ShotID,
     pub shot: &'a production::Shot,
-    pub scene: &'a screenplay_document::Scene,
+    /// The scene the shot's ShotLine starts in. `None` for shots without a ShotLine.
+    pub scene_id: Option<&'a screenplay_document::SceneID>,
+    pub scene: Option<&'a screenplay_document::Scene>,
 
-    pub group: shotliner_document::Group,
-    pub characters: String,
+    pub shot_number: Option<&'a production::ShotNumber>,
+    pub composition: &'a production::ShotComposition,
+    /// Tags from the primary composition and every sub-composition, without duplicates.
     pub tags: Vec<&'a shotliner_document::Tag>,
-    pub props: Vec<&'a production::Prop>,
-
-    pub estimated_setup_time: String,
-    pub completed: bool,
+    /// Characters with dialogue inside the ShotLine.
+    pub characters: Vec<&'a screenplay_document::Character>,
 }
 
-pub struct ShotList(Vec<production::Shot>);
+pub struct ShotList<'a> {
+    pub entries: Vec<ShotListEntry<'a>>,
+}
 
 
 pub struct SceneStrip<'a> {
+    pub scene_id: &'a screenplay_document::SceneID,
     pub scene: &'a screenplay_document::Scene, // gives us scene number, location, environment, and time of day
     pub story_day: Option<u32>,
     pub page_span: Range<usize>,
     pub pages_eigths: (u32, u32),
     pub cast_in_scene: HashSet<screenplay_document::Character>,
+    /// Tags on every TaggedElement that occurs in the scene, without duplicates.
+    pub tags: Vec<&'a shotliner_document::Tag>,
     pub production_locations: HashSet<production::ProductionLocation>,
     pub estimated_duration: chrono::Duration,
     pub completed: bool,
@@ -46,11 +67,317 @@ pub struct StripBoard<'a> {
 }
 
 
-pub fn get_shotlist(shotliner_doc: &ShotlinerDoc) -> Option<ShotList> {
-    None
+/// A line's text, with its elements joined by single spaces.
+pub fn get_line_text(line: &screenplay_document::Line) -> String {
+    let words: Vec<&str> = line.text_elements.iter().map(|e| e.text.as_str()).collect();
+    words.join(" ")
+}
+
+/// Page numbers, revision labels and the like, which change whenever pages do.
+pub fn is_page_furniture(line_type: &Option<SPType>) -> bool {
+    matches!(
+        line_type,
+        Some(SPType::SP_PAGENUM)
+            | Some(SPType::SP_PAGE_HEADER)
+            | Some(SPType::SP_PAGE_REVISION_LABEL)
+            | Some(SPType::SP_LINE_REVISION_MARKER)
+            | Some(SPType::SP_MORE_CONTINUED)
+            | Some(SPType::SP_FOOTER)
+    )
+}
+
+/// Every line from `start` to `end` (inclusive), across page breaks, along with its coordinate.
+pub fn get_lines_in_range<'a>(
+    screenplay: &'a screenplay_document::ScreenplayDocument,
+    start: &screenplay_document::ScreenplayCoordinate,
+    end: &screenplay_document::ScreenplayCoordinate,
+) -> Vec<(screenplay_document::ScreenplayCoordinate, &'a screenplay_document::Line)> {
+    let mut lines = Vec::new();
+    for page_index in start.page..=end.page {
+        let Some(page) = screenplay.pages.get(page_index) else {
+            break;
+        };
+        for (line_index, line) in page.lines.iter().enumerate() {
+            let coord = (page_index, line_index);
+            if coord < (start.page, start.line) || coord > (end.page, end.line) {
+                continue;
+            }
+            lines.push((
+                screenplay_document::ScreenplayCoordinate {
+                    page: page_index,
+                    line: line_index,
+                    element: None,
+                },
+                line,
+            ));
+        }
+    }
+    lines
+}
 
+/// Orders shot numbers like "1", "1A", "2", "10": by their leading number, then by whatever follows it.
+///
+/// Shot numbers without a leading number sort after those with one.
+pub fn compare_shot_numbers(a: &production::ShotNumber, b: &production::ShotNumber) -> Ordering {
+    let split = |number: &str| -> (Option<u64>, String) {
+        let digits: String = number.chars().take_while(|c| c.is_ascii_digit()).collect();
+        (digits.parse::<u64>().ok(), number[digits.len()..].to_string())
+    };
+    let (a_number, a_rest) = split(&a.0);
+    let (b_number, b_rest) = split(&b.0);
+    match (a_number, b_number) {
+        (Some(a_number), Some(b_number)) => a_number.cmp(&b_number).then(a_rest.cmp(&b_rest)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a_rest.cmp(&b_rest),
+    }
 }
 
-pub fn get_stripboard_for_document(shotliner_doc: &ShotlinerDoc) -> Option<StripBoard> {
-    None
+fn get_characters_speaking_in_shotline<'a>(
+    screenplay: &'a screenplay_document::ScreenplayDocument,
+    shotline: &shotliner_document::ShotLine,
+) -> Vec<&'a screenplay_document::Character> {
+    let mut characters: Vec<&screenplay_document::Character> = Vec::new();
+    for (_, line) in get_lines_in_range(screenplay, &shotline.start, &shotline.end) {
+        if line.line_type != Some(SPType::SP_CHARACTER) {
+            continue;
+        }
+        for character in &screenplay.characters {
+            if !characters.contains(&character) && character.is_line(line) {
+                characters.push(character);
+            }
+        }
+    }
+    characters.sort_by(|a, b| a.name.cmp(&b.name));
+    characters
+}
+
+/// Every Shot in the document, ordered by scene, then by shot number.
+///
+/// Unnumbered shots come after the numbered shots in their scene,
+/// and shots without a ShotLine come last.
+/// Returns `None` if the document has no shots.
+pub fn get_shotlist(shotliner_doc: &ShotlinerDoc) -> Option<ShotList<'_>> {
+    if shotliner_doc.annotation_map.shotlines.is_empty() {
+        return None;
+    }
+    let screenplay = &shotliner_doc.screenplay;
+    let scene_order: Vec<&screenplay_document::SceneID> = screenplay_reports::get_all_scenes_ordered(screenplay)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+
+    let mut entries: Vec<ShotListEntry> = Vec::new();
+    for (shot_id, shot) in &shotliner_doc.annotation_map.shotlines {
+        let scene = shot.shotline.as_ref().and_then(|sl| {
+            screenplay_reports::get_scene_for_screenplay_coordinate(screenplay, &sl.start)
+        });
+
+        let mut tags: Vec<&shotliner_document::Tag> = Vec::new();
+        let sub_composition_tags = shot
+            .sub_compositions
+            .iter()
+            .flat_map(|subs| subs.values())
+            .flat_map(|comp| comp.tags.iter());
+        for tag_id in shot.primary_composition.tags.iter().chain(sub_composition_tags) {
+            let Some(tag) = shotliner_doc.annotation_map.tags.get(tag_id) else {
+                continue;
+            };
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        entries.push(ShotListEntry {
+            shot_id: shot_id,
+            shot: shot,
+            scene_id: scene.map(|(id, _)| id),
+            scene: scene.map(|(_, scn)| scn),
+            shot_number: shot.shot_number.as_ref(),
+            composition: &shot.primary_composition,
+            tags: tags,
+            characters: shot
+                .shotline
+                .as_ref()
+                .map(|sl| get_characters_speaking_in_shotline(screenplay, sl))
+                .unwrap_or_default(),
+        });
+    }
+
+    let scene_index = |entry: &ShotListEntry| {
+        entry
+            .scene_id
+            .and_then(|id| scene_order.iter().position(|s| *s == id))
+            .unwrap_or(usize::MAX)
+    };
+    let shotline_start = |entry: &ShotListEntry| {
+        entry
+            .shot
+            .shotline
+            .as_ref()
+            .map(|sl| (sl.start.page, sl.start.line))
+            .unwrap_or((usize::MAX, usize::MAX))
+    };
+    entries.sort_by(|a, b| {
+        scene_index(a)
+            .cmp(&scene_index(b))
+            .then_with(|| match (a.shot_number, b.shot_number) {
+                (Some(a_number), Some(b_number)) => compare_shot_numbers(a_number, b_number),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+            .then_with(|| shotline_start(a).cmp(&shotline_start(b)))
+            .then_with(|| a.shot_id.cmp(b.shot_id))
+    });
+
+    Some(ShotList { entries: entries })
+}
+
+/// Every scene in script order, along with the (inclusive) coordinate of its last line.
+///
+/// A scene runs until the line before the next scene's heading, or until the end of the screenplay.
+pub fn get_scene_extents(
+    screenplay: &screenplay_document::ScreenplayDocument,
+) -> Vec<(
+    &screenplay_document::SceneID,
+    &screenplay_document::Scene,
+    screenplay_document::ScreenplayCoordinate,
+)> {
+    let scenes = screenplay_reports::get_all_scenes_ordered(screenplay).unwrap_or_default();
+    let last_line = |page: usize| {
+        screenplay
+            .pages
+            .get(page)
+            .map(|p| p.lines.len().saturating_sub(1))
+            .unwrap_or(0)
+    };
+
+    let mut extents = Vec::new();
+    for (index, (id, scene)) in scenes.iter().enumerate() {
+        let end = match scenes.get(index + 1) {
+            Some((_, next)) if next.start.line > 0 => screenplay_document::ScreenplayCoordinate {
+                page: next.start.page,
+                line: next.start.line - 1,
+                element: None,
+            },
+            Some((_, next)) => {
+                let page = next.start.page.saturating_sub(1).max(scene.start.page);
+                screenplay_document::ScreenplayCoordinate {
+                    page: page,
+                    line: last_line(page),
+                    element: None,
+                }
+            }
+            None => {
+                let page = screenplay.pages.len().saturating_sub(1).max(scene.start.page);
+                screenplay_document::ScreenplayCoordinate {
+                    page: page,
+                    line: last_line(page),
+                    element: None,
+                }
+            }
+        };
+        extents.push((*id, *scene, end));
+    }
+    extents
+}
+
+/// A TaggedElement's primary range (`origin` to `endpoint`), followed by its other ocurrances in script order, without repeats.
+pub fn get_tagged_element_ranges(
+    tagged_element: &shotliner_document::TaggedElement,
+) -> Vec<(
+    screenplay_document::ScreenplayCoordinate,
+    screenplay_document::ScreenplayCoordinate,
+)> {
+    let mut ranges = vec![(tagged_element.origin.clone(), tagged_element.endpoint.clone())];
+    let mut ocurrances: Vec<_> = tagged_element.ocurrances.iter().collect();
+    ocurrances.sort_by_key(|(start, end)| (start.page, start.line, start.element, end.page, end.line, end.element));
+    for range in ocurrances {
+        if !ranges.contains(range) {
+            ranges.push(range.clone());
+        }
+    }
+    ranges
+}
+
+/// Whether any of a TaggedElement's ranges overlaps the lines from `start` to `end` (inclusive).
+pub fn tagged_element_in_range(
+    tagged_element: &shotliner_document::TaggedElement,
+    start: &screenplay_document::ScreenplayCoordinate,
+    end: &screenplay_document::ScreenplayCoordinate,
+) -> bool {
+    std::iter::once((&tagged_element.origin, &tagged_element.endpoint))
+        .chain(tagged_element.ocurrances.iter().map(|(a, b)| (a, b)))
+        .any(|(a, b)| (a.page, a.line) <= (end.page, end.line) && (b.page, b.line) >= (start.page, start.line))
+}
+
+/// Tags on every TaggedElement that overlaps the lines from `start` to `end`, alphabetically and without duplicates.
+pub fn get_tags_in_range<'a>(
+    shotliner_doc: &'a ShotlinerDoc,
+    start: &screenplay_document::ScreenplayCoordinate,
+    end: &screenplay_document::ScreenplayCoordinate,
+) -> Vec<&'a shotliner_document::Tag> {
+    let mut tags: Vec<&shotliner_document::Tag> = Vec::new();
+    for tagged_element in shotliner_doc.annotation_map.tagged_elements.values() {
+        if !tagged_element_in_range(tagged_element, start, end) {
+            continue;
+        }
+        for tag_id in &tagged_element.tags {
+            let Some(tag) = shotliner_doc.annotation_map.tags.get(tag_id) else {
+                continue;
+            };
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+    tags.sort_by(|a, b| a.string.cmp(&b.string));
+    tags
+}
+
+/// One `StripBoardEntry::Scene` per scene, in script order.
+///
+/// Returns `None` if the screenplay has no scenes.
+pub fn get_stripboard_for_document(shotliner_doc: &ShotlinerDoc) -> Option<StripBoard<'_>> {
+    let screenplay = &shotliner_doc.screenplay;
+    let extents = get_scene_extents(screenplay);
+    if extents.is_empty() {
+        return None;
+    }
+
+    let mut entries: Vec<StripBoardEntry> = Vec::new();
+    for (scene_id, scene, end) in extents {
+        let tags = get_tags_in_range(shotliner_doc, &scene.start, &end);
+
+        let cast_in_scene: HashSet<screenplay_document::Character> =
+            screenplay_reports::get_characters_for_scene(screenplay, scene_id)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect();
+
+        entries.push(StripBoardEntry::Scene(SceneStrip {
+            scene_id: scene_id,
+            scene: scene,
+            story_day: None,
+            page_span: scene.start.page..end.page + 1,
+            pages_eigths: PageEighths::from_pages(page_eighths::measure_pages(
+                screenplay,
+                &scene.start,
+                &end,
+                &EighthsSettings::us_letter_default(),
+            ))
+            .max(PageEighths(1))
+            .as_tuple(),
+            cast_in_scene: cast_in_scene,
+            tags: tags,
+            production_locations: HashSet::new(),
+            estimated_duration: chrono::Duration::zero(),
+            completed: false,
+        }));
+    }
+
+    Some(StripBoard { entries: entries })
 }
\ No newline at end of file
@@ -0,0 +1,312 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use screenplay_doc_parser_rs::reports as screenplay_reports;
+use screenplay_doc_parser_rs::screenplay_document::{self, Environment, TimeOfDay};
+
+use crate::production::Department;
+use crate::reports::{self, page_eighths::EighthsSettings, page_eighths::PageEighths};
+use crate::revisions;
+use crate::serializables::kra_storyboard::escape_xml;
+use crate::serializables::shot_list_csv::{department_name, escape_field};
+use crate::shotliner_document::{ShotlinerDoc, Tag};
+
+/// The elements of one department in a scene.
+pub struct DepartmentElements<'a> {
+    /// `None` collects the tags that haven't been given a department.
+    pub department: Option<Department>,
+    pub tags: Vec<&'a Tag>,
+}
+
+pub struct BreakdownSheet<'a> {
+    pub scene_id: &'a screenplay_document::SceneID,
+    pub scene: &'a screenplay_document::Scene,
+    /// The printed scene number, or the scene's position in the script if it doesn't have one.
+    pub scene_number: String,
+    pub heading: String,
+    /// Full location paths, e.g. "INT. HOUSE - KITCHEN".
+    pub locations: Vec<String>,
+    pub page_span: Range<usize>,
+    /// Production page numbers of the first and last pages (see `ShotlinerDoc::production_page_labels`).
+    pub first_page: String,
+    pub last_page: String,
+    pub pages_eighths: PageEighths,
+    /// Sorted by name.
+    pub cast: Vec<&'a screenplay_document::Character>,
+    /// Departments in `Department::report_order`, then tags without a department. Empty departments are left out.
+    pub departments: Vec<DepartmentElements<'a>>,
+}
+
+pub struct BreakdownSheets<'a> {
+    pub sheets: Vec<BreakdownSheet<'a>>,
+}
+
+/// "INT.", "EXT." or "INT./EXT."
+pub fn environment_label(environment: &Environment) -> &'static str {
+    match environment {
+        Environment::Int => "INT.",
+        Environment::Ext => "EXT.",
+        Environment::Combo(_) => "INT./EXT.",
+    }
+}
+
+/// The time of day as written in the heading, if there is one.
+pub fn time_of_day_label(time_of_day: &Option<TimeOfDay>) -> String {
+    match time_of_day {
+        Some(TimeOfDay::Day(s))
+        | Some(TimeOfDay::Night(s))
+        | Some(TimeOfDay::Morning(s))
+        | Some(TimeOfDay::Evening(s))
+        | Some(TimeOfDay::Afternoon(s)) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Groups tags by department. A tag with several departments is listed under each of them.
+fn group_by_department<'a>(tags: &[&'a Tag]) -> Vec<DepartmentElements<'a>> {
+    let mut groups: Vec<DepartmentElements> = Vec::new();
+    let mut add = |department: Option<Department>, tag: &'a Tag| match groups
+        .iter_mut()
+        .find(|group| group.department == department)
+    {
+        Some(group) => group.tags.push(tag),
+        None => groups.push(DepartmentElements {
+            department: department,
+            tags: vec![tag],
+        }),
+    };
+    for tag in tags {
+        if tag.departments.is_empty() {
+            add(None, tag);
+        }
+        for department in &tag.departments {
+            add(Some(department.clone()), tag);
+        }
+    }
+    groups.sort_by_key(|group| match &group.department {
+        Some(department) => (0, department.report_order()),
+        None => (1, (0, String::new())),
+    });
+    groups
+}
+
+/// One breakdown sheet per scene, in script order.
+///
+/// Returns `None` if the screenplay has no scenes.
+pub fn get_breakdown_sheets(shotliner_doc: &ShotlinerDoc) -> Option<BreakdownSheets<'_>> {
+    let screenplay = &shotliner_doc.screenplay;
+    let extents = reports::get_scene_extents(screenplay);
+    if extents.is_empty() {
+        return None;
+    }
+
+    let page_labels = shotliner_doc.production_page_labels();
+    let mut sheets: Vec<BreakdownSheet> = Vec::new();
+    for (index, (scene_id, scene, end)) in extents.into_iter().enumerate() {
+        let scene_number = scene
+            .number
+            .as_ref()
+            .map(|number| number.0.clone())
+            .unwrap_or((index + 1).to_string());
+        let heading = screenplay
+            .pages
+            .get(scene.start.page)
+            .and_then(|page| page.lines.get(scene.start.line))
+            .map(reports::get_line_text)
+            .unwrap_or_default();
+        let locations: Vec<String> = scene
+            .story_locations
+            .iter()
+            .filter_map(|id| screenplay_reports::get_full_string_for_location_path(screenplay, id))
+            .collect();
+
+        let mut cast: Vec<&screenplay_document::Character> =
+            screenplay_reports::get_characters_for_scene(screenplay, scene_id).unwrap_or_default();
+        cast.sort_by(|a, b| a.name.cmp(&b.name));
+        cast.dedup_by(|a, b| a.name == b.name);
+
+        let tags = reports::get_tags_in_range(shotliner_doc, &scene.start, &end);
+        sheets.push(BreakdownSheet {
+            scene_id: scene_id,
+            scene: scene,
+            scene_number: scene_number,
+            heading: heading,
+            locations: locations,
+            page_span: scene.start.page..end.page + 1,
+            first_page: revisions::page_label_at(&page_labels, scene.start.page),
+            last_page: revisions::page_label_at(&page_labels, end.page),
+            pages_eighths: PageEighths::from_pages(reports::page_eighths::measure_pages(
+                screenplay,
+                &scene.start,
+                &end,
+                &EighthsSettings::us_letter_default(),
+            ))
+            .max(PageEighths(1)),
+            cast: cast,
+            departments: group_by_department(&tags),
+        });
+    }
+
+    Some(BreakdownSheets { sheets: sheets })
+}
+
+fn group_name(group: &DepartmentElements) -> String {
+    match &group.department {
+        Some(department) => department_name(department),
+        None => "Unassigned".to_string(),
+    }
+}
+
+fn cast_names(sheet: &BreakdownSheet) -> Vec<String> {
+    sheet
+        .cast
+        .iter()
+        .map(|character| character.name.clone())
+        .collect()
+}
+
+/// Pages as printed on a breakdown sheet, e.g. "pp. 4-6", by production page number.
+fn pages_label(sheet: &BreakdownSheet) -> String {
+    if sheet.page_span.len() <= 1 {
+        format!("p. {}", sheet.first_page)
+    } else {
+        format!("pp. {}-{}", sheet.first_page, sheet.last_page)
+    }
+}
+
+/// Plain text, one block per scene.
+pub fn to_text(breakdown: &BreakdownSheets) -> Result<String, std::fmt::Error> {
+    let mut text = String::new();
+    for sheet in &breakdown.sheets {
+        writeln!(text, "SCENE {}: {}", sheet.scene_number, sheet.heading)?;
+        // location paths start from the environment, e.g. "INT. GARAGE"
+        writeln!(
+            text,
+            "  {} | {} | {} pages",
+            sheet.locations.join(", "),
+            time_of_day_label(&sheet.scene.story_time_of_day),
+            sheet.pages_eighths
+        )?;
+        writeln!(text, "  {}", pages_label(sheet))?;
+        writeln!(text, "  Cast: {}", cast_names(sheet).join(", "))?;
+        for group in &sheet.departments {
+            let names: Vec<&str> = group.tags.iter().map(|tag| tag.string.as_str()).collect();
+            writeln!(text, "  {}: {}", group_name(group), names.join(", "))?;
+        }
+        writeln!(text)?;
+    }
+    Ok(text)
+}
+
+/// One CSV row per element: cast members, then every tag under each of its departments.
+pub fn to_csv(breakdown: &BreakdownSheets) -> String {
+    let mut rows: Vec<Vec<String>> = vec![
+        [
+            "Scene",
+            "Heading",
+            "Environment",
+            "Location",
+            "Time of Day",
+            "Pages",
+            "Eighths",
+            "Department",
+            "Element",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+    ];
+    for sheet in &breakdown.sheets {
+        let header = vec![
+            sheet.scene_number.clone(),
+            sheet.heading.clone(),
+            environment_label(&sheet.scene.environment).to_string(),
+            sheet.locations.join(", "),
+            time_of_day_label(&sheet.scene.story_time_of_day),
+            pages_label(sheet),
+            sheet.pages_eighths.to_string(),
+        ];
+        let mut row = |department: String, element: String| {
+            let mut row = header.clone();
+            row.push(department);
+            row.push(element);
+            rows.push(row);
+        };
+        for name in cast_names(sheet) {
+            row("Cast".to_string(), name);
+        }
+        for group in &sheet.departments {
+            for tag in &group.tags {
+                row(group_name(group), tag.string.clone());
+            }
+        }
+    }
+
+    let mut csv = String::new();
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|field| escape_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// A standalone HTML page with a table per scene.
+pub fn to_html(breakdown: &BreakdownSheets) -> Result<String, std::fmt::Error> {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(
+        html,
+        "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Breakdown Sheets</title>"
+    )?;
+    writeln!(
+        html,
+        "<style>\n\
+body {{ font-family: sans-serif; }}\n\
+section {{ page-break-after: always; margin-bottom: 2em; }}\n\
+table {{ border-collapse: collapse; width: 100%; }}\n\
+th, td {{ border: 1px solid #888; padding: 4px 8px; text-align: left; vertical-align: top; }}\n\
+th {{ width: 12em; background: #eee; }}\n\
+</style>\n</head>\n<body>"
+    )?;
+    for sheet in &breakdown.sheets {
+        writeln!(html, "<section class=\"breakdown-sheet\">")?;
+        writeln!(
+            html,
+            "<h2>Scene {}: {}</h2>",
+            escape_xml(&sheet.scene_number),
+            escape_xml(&sheet.heading)
+        )?;
+        writeln!(html, "<table>")?;
+        let mut row = |label: &str, value: &str| {
+            writeln!(
+                html,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape_xml(label),
+                escape_xml(value)
+            )
+        };
+        row("Environment", environment_label(&sheet.scene.environment))?;
+        row("Location", &sheet.locations.join(", "))?;
+        row(
+            "Time of Day",
+            &time_of_day_label(&sheet.scene.story_time_of_day),
+        )?;
+        row(
+            "Pages",
+            &format!(
+                "{} ({})",
+                pages_label(sheet),
+                sheet.pages_eighths
+            ),
+        )?;
+        row("Cast", &cast_names(sheet).join(", "))?;
+        for group in &sheet.departments {
+            let names: Vec<&str> = group.tags.iter().map(|tag| tag.string.as_str()).collect();
+            row(&group_name(group), &names.join(", "))?;
+        }
+        writeln!(html, "</table>\n</section>")?;
+    }
+    writeln!(html, "</body>\n</html>")?;
+    Ok(html)
+}
@@ -0,0 +1,168 @@
+use std::fmt::Write;
+
+use screenplay_doc_parser_rs::screenplay_document::{self, ScreenplayCoordinate};
+
+use crate::production::Department;
+use crate::reports;
+use crate::revisions;
+use crate::serializables::shot_list_csv::department_name;
+use crate::shotliner_document::{ShotlinerDoc, Tag, TagID};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElementListOrder {
+    /// Where each element first turns up in the script.
+    FirstAppearance,
+    /// Most ocurrances first; ties go by first appearance.
+    Frequency,
+}
+
+pub struct SceneAppearance<'a> {
+    pub scene_id: &'a screenplay_document::SceneID,
+    pub scene: &'a screenplay_document::Scene,
+    /// The printed scene number, or the scene's position in the script if it doesn't have one.
+    pub scene_number: String,
+}
+
+/// One distinct Tag and everywhere it's used.
+pub struct ElementUsage<'a> {
+    pub tag_id: &'a TagID,
+    pub tag: &'a Tag,
+    /// Every range tagged with it, from all of its TaggedElements, in script order.
+    pub ocurrances: Vec<(ScreenplayCoordinate, ScreenplayCoordinate)>,
+    /// In script order.
+    pub scenes: Vec<SceneAppearance<'a>>,
+    /// Page indices, in order.
+    pub pages: Vec<usize>,
+    /// The same pages, by production page number (see `ShotlinerDoc::production_page_labels`).
+    pub page_numbers: Vec<String>,
+}
+
+pub struct DepartmentElementList<'a> {
+    pub department: Department,
+    pub elements: Vec<ElementUsage<'a>>,
+}
+
+/// Every tag in `department` that's used somewhere in the script.
+///
+/// Tags that no TaggedElement refers to are left out.
+pub fn get_department_element_list<'a>(
+    shotliner_doc: &'a ShotlinerDoc,
+    department: &Department,
+    order: ElementListOrder,
+) -> DepartmentElementList<'a> {
+    let screenplay = &shotliner_doc.screenplay;
+    let scene_extents = reports::get_scene_extents(screenplay);
+    let page_labels = shotliner_doc.production_page_labels();
+    let mut elements: Vec<ElementUsage> = Vec::new();
+
+    for (tag_id, tag) in &shotliner_doc.annotation_map.tags {
+        if !tag.departments.contains(department) {
+            continue;
+        }
+        let mut ocurrances: Vec<(ScreenplayCoordinate, ScreenplayCoordinate)> = Vec::new();
+        for tagged_element in shotliner_doc.annotation_map.tagged_elements.values() {
+            if !tagged_element.tags.contains(tag_id) {
+                continue;
+            }
+            for range in reports::get_tagged_element_ranges(tagged_element) {
+                if !ocurrances.contains(&range) {
+                    ocurrances.push(range);
+                }
+            }
+        }
+        if ocurrances.is_empty() {
+            continue;
+        }
+        ocurrances.sort_by_key(|(start, end)| {
+            (
+                start.page,
+                start.line,
+                start.element,
+                end.page,
+                end.line,
+                end.element,
+            )
+        });
+
+        let mut pages: Vec<usize> = ocurrances
+            .iter()
+            .flat_map(|(start, end)| start.page..=end.page)
+            .collect();
+        pages.sort();
+        pages.dedup();
+
+        let scenes: Vec<SceneAppearance> = scene_extents
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, scene, end))| {
+                ocurrances.iter().any(|(a, b)| {
+                    (a.page, a.line) <= (end.page, end.line)
+                        && (b.page, b.line) >= (scene.start.page, scene.start.line)
+                })
+            })
+            .map(|(index, (scene_id, scene, _))| SceneAppearance {
+                scene_id: scene_id,
+                scene: scene,
+                scene_number: scene
+                    .number
+                    .as_ref()
+                    .map(|number| number.0.clone())
+                    .unwrap_or((index + 1).to_string()),
+            })
+            .collect();
+
+        elements.push(ElementUsage {
+            tag_id: tag_id,
+            tag: tag,
+            ocurrances: ocurrances,
+            scenes: scenes,
+            page_numbers: pages
+                .iter()
+                .map(|page| revisions::page_label_at(&page_labels, *page))
+                .collect(),
+            pages: pages,
+        });
+    }
+
+    let first_appearance = |element: &ElementUsage| {
+        let (start, _) = &element.ocurrances[0];
+        (start.page, start.line, start.element)
+    };
+    elements.sort_by(|a, b| {
+        let by_order = match order {
+            ElementListOrder::FirstAppearance => std::cmp::Ordering::Equal,
+            ElementListOrder::Frequency => b.ocurrances.len().cmp(&a.ocurrances.len()),
+        };
+        by_order
+            .then_with(|| first_appearance(a).cmp(&first_appearance(b)))
+            .then_with(|| a.tag.string.cmp(&b.tag.string))
+            .then_with(|| a.tag_id.cmp(b.tag_id))
+    });
+
+    DepartmentElementList {
+        department: department.clone(),
+        elements: elements,
+    }
+}
+
+/// A plain text list to hand to the department: each element, then its scenes and pages.
+pub fn to_text(list: &DepartmentElementList) -> Result<String, std::fmt::Error> {
+    let mut text = String::new();
+    writeln!(text, "{}", department_name(&list.department).to_uppercase())?;
+    for element in &list.elements {
+        let scenes: Vec<&str> = element
+            .scenes
+            .iter()
+            .map(|scene| scene.scene_number.as_str())
+            .collect();
+        writeln!(
+            text,
+            "{} ({}x)\n  scenes: {}\n  pages: {}",
+            element.tag.string,
+            element.ocurrances.len(),
+            scenes.join(", "),
+            element.page_numbers.join(", ")
+        )?;
+    }
+    Ok(text)
+}
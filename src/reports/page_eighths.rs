@@ -0,0 +1,218 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+
+use screenplay_doc_parser_rs::screenplay_document::{self, ScreenplayCoordinate, ScreenplayDocument};
+
+use crate::reports::{self, StripBoard, StripBoardEntry};
+
+/// A length of screenplay, counted in eighths of a page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PageEighths(pub u32);
+impl PageEighths {
+    pub fn from_whole_and_eighths(whole: u32, eighths: u32) -> Self {
+        PageEighths(whole * 8 + eighths)
+    }
+    pub fn whole(&self) -> u32 {
+        self.0 / 8
+    }
+    pub fn eighths(&self) -> u32 {
+        self.0 % 8
+    }
+    /// The (whole pages, eighths) pair stored in `SceneStrip.pages_eigths`.
+    pub fn as_tuple(&self) -> (u32, u32) {
+        (self.whole(), self.eighths())
+    }
+    /// Rounds a fraction of pages to the nearest eighth. Anything at all counts as at least 1/8.
+    pub fn from_pages(pages: f64) -> Self {
+        if pages <= 0.0 {
+            return PageEighths(0);
+        }
+        PageEighths(((pages * 8.0).round() as u32).max(1))
+    }
+}
+impl Add for PageEighths {
+    type Output = PageEighths;
+    fn add(self, other: PageEighths) -> PageEighths {
+        PageEighths(self.0 + other.0)
+    }
+}
+impl Sum for PageEighths {
+    fn sum<I: Iterator<Item = PageEighths>>(iter: I) -> Self {
+        iter.fold(PageEighths(0), |a, b| a + b)
+    }
+}
+/// Formats the way stripboards write them: "1", "3/8", "2 5/8".
+impl fmt::Display for PageEighths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.whole(), self.eighths()) {
+            (whole, 0) => write!(f, "{}", whole),
+            (0, eighths) => write!(f, "{}/8", eighths),
+            (whole, eighths) => write!(f, "{} {}/8", whole, eighths),
+        }
+    }
+}
+
+/// Where the body of the page sits, in the same units (PDF points, y going up) as `TextPosition`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EighthsSettings {
+    pub body_top: f64,
+    pub body_bottom: f64,
+    pub line_height: f64,
+}
+impl EighthsSettings {
+    /// One inch margins top and bottom, six lines per inch; 54 lines of body.
+    pub fn us_letter_default() -> Self {
+        EighthsSettings {
+            body_top: 10.0 * 72.0,
+            body_bottom: 72.0,
+            line_height: 12.0,
+        }
+    }
+    fn body_height(&self) -> f64 {
+        self.body_top - self.body_bottom
+    }
+}
+impl Default for EighthsSettings {
+    fn default() -> Self {
+        EighthsSettings::us_letter_default()
+    }
+}
+
+fn line_y(line: &screenplay_document::Line) -> Option<f64> {
+    line.text_elements
+        .iter()
+        .find_map(|element| element.element_position)
+        .map(|position| position.y)
+}
+
+/// The height of lines `first..=last` of one page, including the gap after `last` up to the next line.
+///
+/// Uses the lines' positions when they have them, and falls back to counting lines (blank lines included).
+fn measure_page_segment(
+    page: &screenplay_document::Page,
+    first: usize,
+    last: usize,
+    settings: &EighthsSettings,
+) -> f64 {
+    let top = page.lines.get(first).and_then(line_y);
+    let bottom = match page.lines.get(last + 1) {
+        Some(next) => line_y(next).map(|y| y + settings.line_height),
+        None => page.lines.get(last).and_then(line_y),
+    };
+
+    if let (Some(top), Some(bottom)) = (top, bottom) {
+        let top = (top + settings.line_height).min(settings.body_top);
+        let bottom = bottom.max(settings.body_bottom);
+        return (top - bottom).max(0.0);
+    }
+
+    let counted_lines: u64 = page.lines[first..=last]
+        .iter()
+        .enumerate()
+        .map(|(index, line)| if index == 0 { 1 } else { 1 + line.preceding_empty_lines })
+        .sum::<u64>()
+        + page
+            .lines
+            .get(last + 1)
+            .map(|next| next.preceding_empty_lines)
+            .unwrap_or(0);
+    (counted_lines as f64 * settings.line_height).min(settings.body_height())
+}
+
+/// How many pages the lines from `start` to `end` (inclusive) take up, as an unrounded fraction.
+pub fn measure_pages(
+    screenplay: &ScreenplayDocument,
+    start: &ScreenplayCoordinate,
+    end: &ScreenplayCoordinate,
+    settings: &EighthsSettings,
+) -> f64 {
+    let mut height = 0.0;
+    for page_index in start.page..=end.page {
+        let Some(page) = screenplay.pages.get(page_index) else {
+            break;
+        };
+        if page.lines.is_empty() {
+            continue;
+        }
+        let first = if page_index == start.page { start.line } else { 0 };
+        let last = if page_index == end.page {
+            end.line.min(page.lines.len() - 1)
+        } else {
+            page.lines.len() - 1
+        };
+        if last < first {
+            continue;
+        }
+        height += measure_page_segment(page, first, last, settings);
+    }
+    height / settings.body_height()
+}
+
+/// Every scene's length in eighths, in script order.
+///
+/// Each scene is rounded on its own, so no scene is ever shorter than 1/8.
+pub fn get_eighths_for_all_scenes<'a>(
+    screenplay: &'a ScreenplayDocument,
+    settings: &EighthsSettings,
+) -> Vec<(&'a screenplay_document::SceneID, PageEighths)> {
+    reports::get_scene_extents(screenplay)
+        .into_iter()
+        .map(|(id, scene, end)| {
+            let pages = measure_pages(screenplay, &scene.start, &end, settings);
+            (id, PageEighths::from_pages(pages).max(PageEighths(1)))
+        })
+        .collect()
+}
+
+pub fn get_eighths_for_scene(
+    screenplay: &ScreenplayDocument,
+    scene_id: &screenplay_document::SceneID,
+    settings: &EighthsSettings,
+) -> Option<PageEighths> {
+    get_eighths_for_all_scenes(screenplay, settings)
+        .into_iter()
+        .find(|(id, _)| *id == scene_id)
+        .map(|(_, eighths)| eighths)
+}
+
+/// The document's length, as the sum of its (already rounded) scenes.
+pub fn get_eighths_for_document(
+    screenplay: &ScreenplayDocument,
+    settings: &EighthsSettings,
+) -> PageEighths {
+    get_eighths_for_all_scenes(screenplay, settings)
+        .into_iter()
+        .map(|(_, eighths)| eighths)
+        .sum()
+}
+
+/// Totals the scene strips of each shooting day.
+///
+/// A `DayBreak` strip closes the day it's dated with; scenes after the last `DayBreak` are totaled under `None`.
+pub fn get_eighths_per_shooting_day(
+    stripboard: &StripBoard,
+) -> Vec<(Option<chrono::NaiveDate>, PageEighths)> {
+    let mut days: Vec<(Option<chrono::NaiveDate>, PageEighths)> = Vec::new();
+    let mut current = PageEighths(0);
+    let mut has_scenes = false;
+    for entry in &stripboard.entries {
+        match entry {
+            StripBoardEntry::Scene(strip) => {
+                let (whole, eighths) = strip.pages_eigths;
+                current = current + PageEighths::from_whole_and_eighths(whole, eighths);
+                has_scenes = true;
+            }
+            StripBoardEntry::DayBreak(date) => {
+                days.push((Some(*date), current));
+                current = PageEighths(0);
+                has_scenes = false;
+            }
+            _ => {}
+        }
+    }
+    if has_scenes {
+        days.push((None, current));
+    }
+    days
+}
@@ -0,0 +1,706 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use screenplay_doc_parser_rs::screenplay_document::{
+    self, SPType, ScreenplayCoordinate, ScreenplayDocument,
+};
+
+use crate::annotation_index::{AnnotationKey, AnnotationQueryResult};
+use crate::merge_forward::{LineAlignment, LinePosition, normalize_text};
+use crate::reports;
+use crate::revisions;
+use crate::serializables::kra_storyboard::escape_xml;
+use crate::shotliner_document::ShotlinerDoc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+impl ChangeKind {
+    fn label(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        }
+    }
+    fn marker(&self) -> char {
+        match self {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Modified => '~',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineChange {
+    pub kind: ChangeKind,
+    /// Where the line was in the old draft; `None` for added lines.
+    pub old: Option<LinePosition>,
+    /// Where the line is in the new draft; `None` for removed lines.
+    pub new: Option<LinePosition>,
+    pub old_text: Option<String>,
+    pub new_text: Option<String>,
+    /// The shots and tagged elements on the line, in script order.
+    /// For an added line, the ones it was added into the middle of.
+    pub affected: Vec<AnnotationKey>,
+}
+
+/// A run of changed lines of the same type, e.g. a line of dialogue and the line after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElementChange {
+    /// `Added` or `Removed` if every line was, otherwise `Modified`.
+    pub kind: ChangeKind,
+    /// e.g. "Action" or "Dialogue".
+    pub element_type: &'static str,
+    pub lines: Vec<LineChange>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneChange {
+    /// `Added` and `Removed` scenes are only in one draft; a `Modified` scene is in both.
+    pub kind: ChangeKind,
+    pub old_scene: Option<screenplay_document::SceneID>,
+    pub new_scene: Option<screenplay_document::SceneID>,
+    /// The printed scene number, or the scene's position in the script if it doesn't have one.
+    /// Taken from the new draft, unless the scene was removed.
+    pub scene_number: String,
+    pub heading: String,
+    pub elements: Vec<ElementChange>,
+}
+impl SceneChange {
+    /// Every shot and tagged element affected by a change to the scene, without repeats.
+    pub fn affected(&self) -> Vec<&AnnotationKey> {
+        unique_affected(self.elements.iter())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RevisionDiff {
+    /// Changes before the first scene, e.g. to the title page.
+    pub preamble: Vec<ElementChange>,
+    /// Only scenes with changes, in the order of the new draft (removed scenes where they used to be).
+    pub scenes: Vec<SceneChange>,
+    /// Production page numbers of each draft, by page index (see `Revisions::page_labels`).
+    /// The new draft's are the ones merging it forward would give it.
+    pub old_page_labels: Vec<String>,
+    pub new_page_labels: Vec<String>,
+}
+impl RevisionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.preamble.is_empty() && self.scenes.is_empty()
+    }
+
+    /// Every shot and tagged element affected by any change, without repeats.
+    pub fn affected(&self) -> Vec<&AnnotationKey> {
+        unique_affected(
+            self.preamble
+                .iter()
+                .chain(self.scenes.iter().flat_map(|scene| scene.elements.iter())),
+        )
+    }
+
+    pub fn scenes_with_kind(&self, kind: ChangeKind) -> Vec<&SceneChange> {
+        self.scenes
+            .iter()
+            .filter(|scene| scene.kind == kind)
+            .collect()
+    }
+}
+
+fn unique_affected<'a>(
+    elements: impl Iterator<Item = &'a ElementChange>,
+) -> Vec<&'a AnnotationKey> {
+    let mut affected: Vec<&AnnotationKey> = Vec::new();
+    for key in elements
+        .flat_map(|element| element.lines.iter())
+        .flat_map(|line| line.affected.iter())
+    {
+        if !affected.contains(&key) {
+            affected.push(key);
+        }
+    }
+    affected
+}
+
+/// What a line is, as shown on the report.
+pub fn element_type_label(line_type: &Option<SPType>) -> &'static str {
+    match line_type {
+        Some(SPType::SP_ACTION) => "Action",
+        Some(SPType::SP_CHARACTER)
+        | Some(SPType::SP_CHARACTER_EXTENSION)
+        | Some(SPType::SP_DUAL_CHARACTERS) => "Character",
+        Some(SPType::SP_PARENTHETICAL) => "Parenthetical",
+        Some(SPType::SP_DIALOGUE) | Some(SPType::SP_DUAL_DIALOGUES) => "Dialogue",
+        Some(SPType::SP_TRANSITION) => "Transition",
+        Some(SPType::SP_SCENE_HEADING(_)) => "Scene Heading",
+        Some(SPType::SP_SHOT_ANGLE) => "Shot",
+        _ => "Other",
+    }
+}
+
+struct DraftScene {
+    id: screenplay_document::SceneID,
+    number: String,
+    heading: String,
+    start: LinePosition,
+}
+
+/// The scenes of a draft in order, and which of them each line is in.
+fn draft_scenes(
+    screenplay: &ScreenplayDocument,
+) -> (Vec<DraftScene>, HashMap<LinePosition, usize>) {
+    let mut scenes: Vec<DraftScene> = Vec::new();
+    let mut starts: HashMap<LinePosition, usize> = HashMap::new();
+    for (index, (id, scene, _)) in reports::get_scene_extents(screenplay)
+        .into_iter()
+        .enumerate()
+    {
+        let start = (scene.start.page, scene.start.line);
+        starts.insert(start, index);
+        scenes.push(DraftScene {
+            id: *id,
+            number: scene
+                .number
+                .as_ref()
+                .map(|number| number.0.clone())
+                .unwrap_or((index + 1).to_string()),
+            heading: get_text(screenplay, &start).unwrap_or_default(),
+            start: start,
+        });
+    }
+    let mut line_scenes: HashMap<LinePosition, usize> = HashMap::new();
+    let mut current: Option<usize> = None;
+    for (page_index, page) in screenplay.pages.iter().enumerate() {
+        for line_index in 0..page.lines.len() {
+            let position = (page_index, line_index);
+            if let Some(index) = starts.get(&position) {
+                current = Some(*index);
+            }
+            if let Some(index) = current {
+                line_scenes.insert(position, index);
+            }
+        }
+    }
+    (scenes, line_scenes)
+}
+
+fn get_line<'a>(
+    screenplay: &'a ScreenplayDocument,
+    position: &LinePosition,
+) -> Option<&'a screenplay_document::Line> {
+    screenplay.pages.get(position.0)?.lines.get(position.1)
+}
+
+fn get_text(screenplay: &ScreenplayDocument, position: &LinePosition) -> Option<String> {
+    get_line(screenplay, position).map(reports::get_line_text)
+}
+
+/// Whether a line takes part in the diff at all: blank lines and page furniture don't.
+fn is_content(screenplay: &ScreenplayDocument, position: &LinePosition) -> bool {
+    get_line(screenplay, position).is_some_and(|line| {
+        !reports::is_page_furniture(&line.line_type)
+            && !normalize_text(&reports::get_line_text(line)).is_empty()
+    })
+}
+
+/// Which scene of which draft a change goes under.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum SceneKey {
+    Preamble,
+    Old(usize),
+    New(usize),
+    Both(usize, usize),
+}
+
+/// One step through the two drafts: a line only in the old one, only in the new one, or in both.
+enum Step {
+    Removed(LinePosition),
+    Added(LinePosition),
+    Matched(LinePosition, LinePosition, bool),
+}
+
+fn coordinate(position: &LinePosition) -> ScreenplayCoordinate {
+    ScreenplayCoordinate {
+        page: position.0,
+        line: position.1,
+        element: None,
+    }
+}
+
+/// Every added, removed and modified line between the document's screenplay and a revised draft of it,
+/// grouped into elements and scenes, along with the document's annotations that each change touches.
+pub fn get_revision_diff(
+    shotliner_doc: &ShotlinerDoc,
+    revised: &ScreenplayDocument,
+) -> RevisionDiff {
+    let old = &shotliner_doc.screenplay;
+    let alignment = LineAlignment::new(old, revised);
+    let (old_scenes, old_line_scenes) = draft_scenes(old);
+    let (new_scenes, new_line_scenes) = draft_scenes(revised);
+
+    // a scene is the same scene in both drafts if its heading line is
+    let mut old_to_new: HashMap<usize, usize> = HashMap::new();
+    let mut new_to_old: HashMap<usize, usize> = HashMap::new();
+    let new_starts: HashMap<LinePosition, usize> = new_scenes
+        .iter()
+        .enumerate()
+        .map(|(index, scene)| (scene.start, index))
+        .collect();
+    for (old_index, scene) in old_scenes.iter().enumerate() {
+        if let Some(new_index) = alignment
+            .new_line(&scene.start)
+            .and_then(|line_match| new_starts.get(&line_match.new))
+        {
+            old_to_new.insert(old_index, *new_index);
+            new_to_old.insert(*new_index, old_index);
+        }
+    }
+    let old_key = |position: &LinePosition| match old_line_scenes.get(position) {
+        Some(index) => match old_to_new.get(index) {
+            Some(new_index) => SceneKey::Both(*index, *new_index),
+            None => SceneKey::Old(*index),
+        },
+        None => SceneKey::Preamble,
+    };
+    let new_key = |position: &LinePosition| match new_line_scenes.get(position) {
+        Some(index) => match new_to_old.get(index) {
+            Some(old_index) => SceneKey::Both(*old_index, *index),
+            None => SceneKey::New(*index),
+        },
+        None => SceneKey::Preamble,
+    };
+
+    // walk both drafts in step
+    let mut steps: Vec<Step> = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    for line_match in &alignment.matches {
+        while alignment.old_lines[old_index] != line_match.old {
+            steps.push(Step::Removed(alignment.old_lines[old_index]));
+            old_index += 1;
+        }
+        while alignment.new_lines[new_index] != line_match.new {
+            steps.push(Step::Added(alignment.new_lines[new_index]));
+            new_index += 1;
+        }
+        steps.push(Step::Matched(
+            line_match.old,
+            line_match.new,
+            line_match.exact,
+        ));
+        old_index += 1;
+        new_index += 1;
+    }
+    steps.extend(
+        alignment.old_lines[old_index..]
+            .iter()
+            .map(|p| Step::Removed(*p)),
+    );
+    steps.extend(
+        alignment.new_lines[new_index..]
+            .iter()
+            .map(|p| Step::Added(*p)),
+    );
+
+    // an added line affects what spans the unchanged lines on either side of it
+    let mut previous_old: Vec<Option<LinePosition>> = Vec::with_capacity(steps.len());
+    let mut last: Option<LinePosition> = None;
+    for step in &steps {
+        previous_old.push(last);
+        if let Step::Matched(old, _, _) = step {
+            last = Some(*old);
+        }
+    }
+    let mut next_old: Vec<Option<LinePosition>> = vec![None; steps.len()];
+    let mut last: Option<LinePosition> = None;
+    for (index, step) in steps.iter().enumerate().rev() {
+        next_old[index] = last;
+        if let Step::Matched(old, _, _) = step {
+            last = Some(*old);
+        }
+    }
+    let in_script_order = |keys: HashSet<&AnnotationKey>| -> Vec<AnnotationKey> {
+        let found = AnnotationQueryResult::from_keys(&shotliner_doc.annotation_map, keys);
+        found
+            .shots
+            .iter()
+            .map(|(id, _)| AnnotationKey::Shot((*id).clone()))
+            .chain(
+                found
+                    .tagged_elements
+                    .iter()
+                    .map(|(id, _)| AnnotationKey::TaggedElement((*id).clone())),
+            )
+            .collect()
+    };
+    let on_line = |position: &LinePosition| {
+        let coord = coordinate(position);
+        in_script_order(shotliner_doc.annotation_index.query(&coord, &coord))
+    };
+    let spanning = |before: Option<LinePosition>, after: Option<LinePosition>| {
+        let (Some(before), Some(after)) = (before, after) else {
+            return Vec::new();
+        };
+        let (before, after) = (coordinate(&before), coordinate(&after));
+        let keys: HashSet<&AnnotationKey> = shotliner_doc
+            .annotation_index
+            .query(&before, &after)
+            .into_iter()
+            .filter(|key| {
+                let ranges = match key {
+                    AnnotationKey::Shot(id) => shotliner_doc
+                        .annotation_map
+                        .shotlines
+                        .get(id)
+                        .and_then(|shot| shot.shotline.as_ref())
+                        .map(|shotline| vec![(shotline.start.clone(), shotline.end.clone())])
+                        .unwrap_or_default(),
+                    AnnotationKey::TaggedElement(id) => shotliner_doc
+                        .annotation_map
+                        .tagged_elements
+                        .get(id)
+                        .map(reports::get_tagged_element_ranges)
+                        .unwrap_or_default(),
+                };
+                ranges.iter().any(|(start, end)| {
+                    (start.page, start.line) <= (before.page, before.line)
+                        && (end.page, end.line) >= (after.page, after.line)
+                })
+            })
+            .collect();
+        in_script_order(keys)
+    };
+
+    // each change, tagged with its scene and the stretch of changes it's part of
+    let mut changes: Vec<(SceneKey, usize, LineChange)> = Vec::new();
+    let mut stretch = 0;
+    for (index, step) in steps.iter().enumerate() {
+        let (key, change) = match step {
+            Step::Matched(_, _, true) => {
+                stretch += 1;
+                continue;
+            }
+            Step::Matched(old_position, new_position, false) => {
+                if !is_content(old, old_position) || !is_content(revised, new_position) {
+                    continue;
+                }
+                (
+                    new_key(new_position),
+                    LineChange {
+                        kind: ChangeKind::Modified,
+                        old: Some(*old_position),
+                        new: Some(*new_position),
+                        old_text: get_text(old, old_position),
+                        new_text: get_text(revised, new_position),
+                        affected: on_line(old_position),
+                    },
+                )
+            }
+            Step::Removed(position) => {
+                if !is_content(old, position) {
+                    continue;
+                }
+                (
+                    old_key(position),
+                    LineChange {
+                        kind: ChangeKind::Removed,
+                        old: Some(*position),
+                        new: None,
+                        old_text: get_text(old, position),
+                        new_text: None,
+                        affected: on_line(position),
+                    },
+                )
+            }
+            Step::Added(position) => {
+                if !is_content(revised, position) {
+                    continue;
+                }
+                (
+                    new_key(position),
+                    LineChange {
+                        kind: ChangeKind::Added,
+                        old: None,
+                        new: Some(*position),
+                        old_text: None,
+                        new_text: get_text(revised, position),
+                        affected: spanning(previous_old[index], next_old[index]),
+                    },
+                )
+            }
+        };
+        changes.push((key, stretch, change));
+    }
+
+    let element_type = |change: &LineChange| {
+        let line = match (&change.new, &change.old) {
+            (Some(new), _) => get_line(revised, new),
+            (None, Some(position)) => get_line(old, position),
+            (None, None) => None,
+        };
+        element_type_label(&line.and_then(|line| line.line_type))
+    };
+
+    let mut diff = RevisionDiff {
+        old_page_labels: shotliner_doc.production_page_labels(),
+        new_page_labels: shotliner_doc
+            .revisions
+            .carry_forward(old, revised)
+            .page_labels(revised),
+        ..RevisionDiff::default()
+    };
+    let mut scene_keys: Vec<SceneKey> = Vec::new();
+    let mut last_element: Option<(SceneKey, usize, &'static str)> = None;
+    for (key, stretch, change) in changes {
+        let element_type = element_type(&change);
+        let elements = match key {
+            SceneKey::Preamble => &mut diff.preamble,
+            _ => {
+                let index = match scene_keys.iter().position(|k| *k == key) {
+                    Some(index) => index,
+                    None => {
+                        let (kind, old_scene, new_scene) = match key {
+                            SceneKey::Old(o) => (ChangeKind::Removed, Some(&old_scenes[o]), None),
+                            SceneKey::New(n) => (ChangeKind::Added, None, Some(&new_scenes[n])),
+                            SceneKey::Both(o, n) => (
+                                ChangeKind::Modified,
+                                Some(&old_scenes[o]),
+                                Some(&new_scenes[n]),
+                            ),
+                            SceneKey::Preamble => unreachable!(),
+                        };
+                        let Some(shown) = new_scene.or(old_scene) else {
+                            continue;
+                        };
+                        diff.scenes.push(SceneChange {
+                            kind: kind,
+                            old_scene: old_scene.map(|scene| scene.id),
+                            new_scene: new_scene.map(|scene| scene.id),
+                            scene_number: shown.number.clone(),
+                            heading: shown.heading.clone(),
+                            elements: Vec::new(),
+                        });
+                        scene_keys.push(key);
+                        scene_keys.len() - 1
+                    }
+                };
+                &mut diff.scenes[index].elements
+            }
+        };
+        match elements.last_mut() {
+            Some(element) if last_element == Some((key, stretch, element_type)) => {
+                if element.kind != change.kind {
+                    element.kind = ChangeKind::Modified;
+                }
+                element.lines.push(change);
+            }
+            _ => elements.push(ElementChange {
+                kind: change.kind,
+                element_type: element_type,
+                lines: vec![change],
+            }),
+        }
+        last_element = Some((key, stretch, element_type));
+    }
+    diff
+}
+
+/// A short name for a shot or tagged element: its shot number, or the names of its tags.
+pub fn describe_annotation(shotliner_doc: &ShotlinerDoc, key: &AnnotationKey) -> String {
+    let annotation_map = &shotliner_doc.annotation_map;
+    match key {
+        AnnotationKey::Shot(id) => match annotation_map
+            .shotlines
+            .get(id)
+            .and_then(|shot| shot.shot_number.as_ref())
+        {
+            Some(number) => format!("Shot {}", number.0),
+            None => "Shot (unnumbered)".to_string(),
+        },
+        AnnotationKey::TaggedElement(id) => {
+            let tags: Vec<&str> = annotation_map
+                .tagged_elements
+                .get(id)
+                .map(|te| te.tags.iter())
+                .into_iter()
+                .flatten()
+                .filter_map(|tag_id| annotation_map.tags.get(tag_id))
+                .map(|tag| tag.string.as_str())
+                .collect();
+            if tags.is_empty() {
+                "Untagged element".to_string()
+            } else {
+                format!("Tag {}", tags.join(", "))
+            }
+        }
+    }
+}
+
+fn position_label(diff: &RevisionDiff, change: &LineChange) -> String {
+    let label = |page_labels: &[String], position: &Option<LinePosition>| match position {
+        Some((page, line)) => revisions::position_label(page_labels, *page, *line),
+        None => "-".to_string(),
+    };
+    let old = |change: &LineChange| label(&diff.old_page_labels, &change.old);
+    let new = |change: &LineChange| label(&diff.new_page_labels, &change.new);
+    match change.kind {
+        ChangeKind::Added => new(change),
+        ChangeKind::Removed => old(change),
+        ChangeKind::Modified => format!("{} -> {}", old(change), new(change)),
+    }
+}
+
+fn write_elements_text(
+    text: &mut String,
+    diff: &RevisionDiff,
+    shotliner_doc: &ShotlinerDoc,
+    elements: &[ElementChange],
+) -> Result<(), std::fmt::Error> {
+    for element in elements {
+        writeln!(
+            text,
+            "  {} ({})",
+            element.element_type,
+            element.kind.label()
+        )?;
+        for line in &element.lines {
+            writeln!(text, "    {} {}", line.kind.marker(), position_label(diff, line))?;
+            if let Some(old_text) = &line.old_text {
+                writeln!(text, "      - {}", old_text)?;
+            }
+            if let Some(new_text) = &line.new_text {
+                writeln!(text, "      + {}", new_text)?;
+            }
+            if !line.affected.is_empty() {
+                let names: Vec<String> = line
+                    .affected
+                    .iter()
+                    .map(|key| describe_annotation(shotliner_doc, key))
+                    .collect();
+                writeln!(text, "      affects: {}", names.join(", "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Plain text: a summary, then each changed scene with its changed lines (by production page number).
+pub fn to_text(
+    diff: &RevisionDiff,
+    shotliner_doc: &ShotlinerDoc,
+) -> Result<String, std::fmt::Error> {
+    let mut text = String::new();
+    writeln!(
+        text,
+        "{} scenes added, {} removed, {} modified; {} annotations affected",
+        diff.scenes_with_kind(ChangeKind::Added).len(),
+        diff.scenes_with_kind(ChangeKind::Removed).len(),
+        diff.scenes_with_kind(ChangeKind::Modified).len(),
+        diff.affected().len()
+    )?;
+    if !diff.preamble.is_empty() {
+        writeln!(text, "\nBEFORE THE FIRST SCENE")?;
+        write_elements_text(&mut text, diff, shotliner_doc, &diff.preamble)?;
+    }
+    for scene in &diff.scenes {
+        writeln!(
+            text,
+            "\nSCENE {}: {} ({})",
+            scene.scene_number,
+            scene.heading,
+            scene.kind.label()
+        )?;
+        write_elements_text(&mut text, diff, shotliner_doc, &scene.elements)?;
+    }
+    Ok(text)
+}
+
+fn write_elements_html(
+    html: &mut String,
+    diff: &RevisionDiff,
+    shotliner_doc: &ShotlinerDoc,
+    elements: &[ElementChange],
+) -> Result<(), std::fmt::Error> {
+    writeln!(
+        html,
+        "<table>\n<tr><th>Element</th><th>Line</th><th>Old</th><th>New</th><th>Affects</th></tr>"
+    )?;
+    for element in elements {
+        for line in &element.lines {
+            let names: Vec<String> = line
+                .affected
+                .iter()
+                .map(|key| describe_annotation(shotliner_doc, key))
+                .collect();
+            writeln!(
+                html,
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                line.kind.label(),
+                escape_xml(element.element_type),
+                escape_xml(&position_label(diff, line)),
+                escape_xml(line.old_text.as_deref().unwrap_or("")),
+                escape_xml(line.new_text.as_deref().unwrap_or("")),
+                escape_xml(&names.join(", "))
+            )?;
+        }
+    }
+    writeln!(html, "</table>")
+}
+
+/// A standalone HTML page with a table of changed lines per scene, colored by the kind of change.
+pub fn to_html(
+    diff: &RevisionDiff,
+    shotliner_doc: &ShotlinerDoc,
+) -> Result<String, std::fmt::Error> {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(
+        html,
+        "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Revision Changes</title>"
+    )?;
+    writeln!(
+        html,
+        "<style>\n\
+body {{ font-family: sans-serif; }}\n\
+table {{ border-collapse: collapse; width: 100%; margin-bottom: 2em; }}\n\
+th, td {{ border: 1px solid #888; padding: 4px 8px; text-align: left; vertical-align: top; }}\n\
+th {{ background: #eee; }}\n\
+tr.added {{ background: #e6ffe6; }}\n\
+tr.removed {{ background: #ffe6e6; }}\n\
+tr.modified {{ background: #fff8d6; }}\n\
+</style>\n</head>\n<body>"
+    )?;
+    writeln!(
+        html,
+        "<p>{} scenes added, {} removed, {} modified; {} annotations affected</p>",
+        diff.scenes_with_kind(ChangeKind::Added).len(),
+        diff.scenes_with_kind(ChangeKind::Removed).len(),
+        diff.scenes_with_kind(ChangeKind::Modified).len(),
+        diff.affected().len()
+    )?;
+    if !diff.preamble.is_empty() {
+        writeln!(
+            html,
+            "<section class=\"preamble\">\n<h2>Before the First Scene</h2>"
+        )?;
+        write_elements_html(&mut html, diff, shotliner_doc, &diff.preamble)?;
+        writeln!(html, "</section>")?;
+    }
+    for scene in &diff.scenes {
+        writeln!(html, "<section class=\"scene {}\">", scene.kind.label())?;
+        writeln!(
+            html,
+            "<h2>Scene {}: {} ({})</h2>",
+            escape_xml(&scene.scene_number),
+            escape_xml(&scene.heading),
+            scene.kind.label()
+        )?;
+        write_elements_html(&mut html, diff, shotliner_doc, &scene.elements)?;
+        writeln!(html, "</section>")?;
+    }
+    writeln!(html, "</body>\n</html>")?;
+    Ok(html)
+}
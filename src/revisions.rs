@@ -0,0 +1,457 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use chrono::NaiveDate;
+use screenplay_doc_parser_rs::screenplay_document::{ScreenplayCoordinate, ScreenplayDocument};
+use serde::{Deserialize, Serialize};
+
+use crate::merge_forward::{LineAlignment, normalize_text};
+use crate::reports;
+
+/// The letters used for A-pages and lettered numbers. I and O are skipped, so they aren't mistaken for 1 and 0.
+const SUFFIX_LETTERS: [char; 24] = [
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'L', 'M', 'N', 'P', 'Q', 'R', 'S', 'T', 'U',
+    'V', 'W', 'X', 'Y', 'Z',
+];
+
+/// The `index`th letter suffix: "" for 0, then "A" to "Z", then "AA", "AB" and so on.
+pub fn letter_suffix(index: usize) -> String {
+    let mut letters: Vec<char> = Vec::new();
+    let mut remaining = index;
+    while remaining > 0 {
+        remaining -= 1;
+        letters.push(SUFFIX_LETTERS[remaining % SUFFIX_LETTERS.len()]);
+        remaining /= SUFFIX_LETTERS.len();
+    }
+    letters.iter().rev().collect()
+}
+
+/// Where `suffix` comes in the order of `letter_suffix`, ignoring case.
+/// `None` if it has anything but suffix letters in it.
+pub fn suffix_index(suffix: &str) -> Option<usize> {
+    let mut index: usize = 0;
+    for c in suffix.chars() {
+        let letter = SUFFIX_LETTERS
+            .iter()
+            .position(|letter| *letter == c.to_ascii_uppercase())?;
+        index = index
+            .checked_mul(SUFFIX_LETTERS.len())?
+            .checked_add(letter + 1)?;
+    }
+    Some(index)
+}
+
+/// A page number as it's printed on a production draft, e.g. 12 or 12A.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProductionPageNumber {
+    pub number: u32,
+    /// Empty for a page that was in the locked script; A, B... for pages added after it.
+    pub suffix: String,
+}
+impl ProductionPageNumber {
+    pub fn new(number: u32) -> Self {
+        ProductionPageNumber {
+            number: number,
+            suffix: String::new(),
+        }
+    }
+
+    /// Reads a printed page number such as "12." or "12A", ignoring the trailing period.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().trim_end_matches('.');
+        let digits = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let number: u32 = text[..digits].parse().ok()?;
+        let suffix = &text[digits..];
+        suffix_index(suffix)?;
+        Some(ProductionPageNumber {
+            number: number,
+            suffix: suffix.to_ascii_uppercase(),
+        })
+    }
+
+    /// The A-page that follows this one: 12A after 12, 12B after 12A.
+    pub fn next_a_page(&self) -> Self {
+        ProductionPageNumber {
+            number: self.number,
+            suffix: letter_suffix(suffix_index(&self.suffix).unwrap_or(0) + 1),
+        }
+    }
+}
+impl Ord for ProductionPageNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = |page: &Self| {
+            (
+                page.number,
+                suffix_index(&page.suffix).unwrap_or(usize::MAX),
+            )
+        };
+        key(self)
+            .cmp(&key(other))
+            .then_with(|| self.suffix.cmp(&other.suffix))
+    }
+}
+impl PartialOrd for ProductionPageNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl fmt::Display for ProductionPageNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.number, self.suffix)
+    }
+}
+
+/// The colors revision pages are printed on, in the order they're usually issued.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RevisionColor {
+    White,
+    Blue,
+    Pink,
+    Yellow,
+    Green,
+    Goldenrod,
+    Buff,
+    Salmon,
+    Cherry,
+    Other(String),
+}
+impl RevisionColor {
+    const ORDER: [RevisionColor; 9] = [
+        RevisionColor::White,
+        RevisionColor::Blue,
+        RevisionColor::Pink,
+        RevisionColor::Yellow,
+        RevisionColor::Green,
+        RevisionColor::Goldenrod,
+        RevisionColor::Buff,
+        RevisionColor::Salmon,
+        RevisionColor::Cherry,
+    ];
+
+    /// The color of the revision after this one. After Cherry (or a color outside the order) it's White again.
+    pub fn next(&self) -> Self {
+        let index = RevisionColor::ORDER.iter().position(|color| color == self);
+        match index {
+            Some(index) if index + 1 < RevisionColor::ORDER.len() => {
+                RevisionColor::ORDER[index + 1].clone()
+            }
+            _ => RevisionColor::White,
+        }
+    }
+
+    /// Matches the standard colors by name, ignoring case; anything else is `Other`.
+    pub fn from_name(name: &str) -> Self {
+        RevisionColor::ORDER
+            .iter()
+            .find(|color| color.to_string().eq_ignore_ascii_case(name.trim()))
+            .cloned()
+            .unwrap_or(RevisionColor::Other(name.trim().to_string()))
+    }
+}
+impl fmt::Display for RevisionColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevisionColor::Other(name) => write!(f, "{}", name),
+            color => write!(f, "{:?}", color),
+        }
+    }
+}
+
+/// Dates are saved as "YYYY-MM-DD".
+mod iso_date {
+    use chrono::NaiveDate;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    const FORMAT: &str = "%Y-%m-%d";
+
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&date.format(FORMAT).to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&text, FORMAT).map_err(D::Error::custom)
+    }
+}
+
+/// One colored revision of the script.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RevisionSet {
+    pub color: RevisionColor,
+    #[serde(with = "iso_date")]
+    pub date: NaiveDate,
+}
+impl fmt::Display for RevisionSet {
+    /// As in a page's revision header, e.g. "Blue Rev. (04/05/25)".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} Rev. ({})", self.color, self.date.format("%m/%d/%y"))
+    }
+}
+
+/// A line that was changed in a revision, and would get an asterisk in the margin.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RevisionMark {
+    pub page: usize,
+    pub line: usize,
+    /// Index into `Revisions::sets`.
+    pub revision: usize,
+}
+
+/// The revision history of a production draft: which revisions were issued,
+/// the page numbers it was locked with, and which lines each revision changed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Revisions {
+    /// Oldest first.
+    pub sets: Vec<RevisionSet>,
+    /// Each page's number by page index, once the pages are locked. Empty while they aren't.
+    /// `None` for unnumbered pages, like the title page.
+    pub locked_pages: Vec<Option<ProductionPageNumber>>,
+    /// The latest revision of each changed line, in script order.
+    pub marks: Vec<RevisionMark>,
+}
+impl Revisions {
+    pub fn is_locked(&self) -> bool {
+        !self.locked_pages.is_empty()
+    }
+
+    /// The revision the current draft was issued as, if any.
+    pub fn current(&self) -> Option<&RevisionSet> {
+        self.sets.last()
+    }
+
+    /// The locked number of the page, or its number as printed (see `page_numbers`) while the pages aren't locked.
+    pub fn page_number(
+        &self,
+        screenplay: &ScreenplayDocument,
+        page_index: usize,
+    ) -> Option<ProductionPageNumber> {
+        if self.is_locked() {
+            return self.locked_pages.get(page_index).cloned().flatten();
+        }
+        page_numbers(screenplay)
+            .into_iter()
+            .nth(page_index)
+            .flatten()
+    }
+
+    /// Every page's number, by page index. See `page_number`.
+    pub fn all_page_numbers(
+        &self,
+        screenplay: &ScreenplayDocument,
+    ) -> Vec<Option<ProductionPageNumber>> {
+        if self.is_locked() {
+            let mut numbers = self.locked_pages.clone();
+            numbers.resize(screenplay.pages.len(), None);
+            return numbers;
+        }
+        page_numbers(screenplay)
+    }
+
+    /// The page number as text, or `UNNUMBERED_PAGE_LABEL` for unnumbered pages.
+    pub fn page_label(&self, screenplay: &ScreenplayDocument, page_index: usize) -> String {
+        page_number_label(self.page_number(screenplay, page_index).as_ref())
+    }
+
+    /// Every page's label, by page index. Cheaper than `page_label` for more than one page.
+    pub fn page_labels(&self, screenplay: &ScreenplayDocument) -> Vec<String> {
+        self.all_page_numbers(screenplay)
+            .iter()
+            .map(|number| page_number_label(number.as_ref()))
+            .collect()
+    }
+
+    /// A coordinate as production refers to it, page then line, e.g. "12A:5". See `page_label`.
+    pub fn coordinate_label(
+        &self,
+        screenplay: &ScreenplayDocument,
+        coord: &ScreenplayCoordinate,
+    ) -> String {
+        format!("{}:{}", self.page_label(screenplay, coord.page), coord.line)
+    }
+
+    fn mark_index(&self, page: usize, line: usize) -> Option<usize> {
+        self.marks
+            .binary_search_by_key(&(page, line), |mark| (mark.page, mark.line))
+            .ok()
+            .map(|index| self.marks[index].revision)
+    }
+
+    /// The revision that last changed a line, if any did.
+    pub fn mark(&self, page: usize, line: usize) -> Option<&RevisionSet> {
+        self.sets.get(self.mark_index(page, line)?)
+    }
+
+    /// The revision history once `new` has been issued as `set`, a revision of `old`.
+    ///
+    /// Lines that were added or changed are marked with `set`; unchanged lines keep their marks.
+    /// If the pages are locked, each new page keeps the number of the old page its text came from,
+    /// and pages pushed past the end of one get A-page numbers (12A, 12B...).
+    pub fn issue(
+        &self,
+        old: &ScreenplayDocument,
+        new: &ScreenplayDocument,
+        set: RevisionSet,
+    ) -> Revisions {
+        self.remap(old, new, Some(set))
+    }
+
+    /// The revision history moved onto `new`, a new draft of `old` that isn't a revision of its own,
+    /// e.g. the same draft re-parsed. Marks stay on lines whose text didn't change, and locked pages
+    /// are renumbered as `issue` would.
+    pub fn carry_forward(&self, old: &ScreenplayDocument, new: &ScreenplayDocument) -> Revisions {
+        self.remap(old, new, None)
+    }
+
+    fn remap(
+        &self,
+        old: &ScreenplayDocument,
+        new: &ScreenplayDocument,
+        set: Option<RevisionSet>,
+    ) -> Revisions {
+        let alignment = LineAlignment::new(old, new);
+        let mut sets = self.sets.clone();
+        let revision = set.map(|set| {
+            sets.push(set);
+            sets.len() - 1
+        });
+
+        let mut marks: Vec<RevisionMark> = Vec::new();
+        for (page_index, page) in new.pages.iter().enumerate() {
+            for (line_index, line) in page.lines.iter().enumerate() {
+                if reports::is_page_furniture(&line.line_type)
+                    || normalize_text(&reports::get_line_text(line)).is_empty()
+                {
+                    continue;
+                }
+                let mark = match alignment.old_line(&(page_index, line_index)) {
+                    Some(line_match) if line_match.exact => {
+                        self.mark_index(line_match.old.0, line_match.old.1)
+                    }
+                    _ => revision,
+                };
+                if let Some(mark) = mark {
+                    marks.push(RevisionMark {
+                        page: page_index,
+                        line: line_index,
+                        revision: mark,
+                    });
+                }
+            }
+        }
+
+        let locked_pages = if self.is_locked() {
+            self.relock(new, &alignment)
+        } else {
+            Vec::new()
+        };
+        Revisions {
+            sets: sets,
+            locked_pages: locked_pages,
+            marks: marks,
+        }
+    }
+
+    /// Locked page numbers for the pages of a new draft.
+    ///
+    /// A page takes the number of the old page its first surviving line came from,
+    /// unless an earlier page already took it (or a later one); then it's the A-page after the page before it.
+    fn relock(
+        &self,
+        new: &ScreenplayDocument,
+        alignment: &LineAlignment,
+    ) -> Vec<Option<ProductionPageNumber>> {
+        let mut numbers: Vec<Option<ProductionPageNumber>> = Vec::new();
+        let mut last_old_page: Option<usize> = None;
+        for (page_index, page) in new.pages.iter().enumerate() {
+            let old_page = page
+                .lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| !reports::is_page_furniture(&line.line_type))
+                .find_map(|(line_index, _)| alignment.old_line(&(page_index, line_index)))
+                .map(|line_match| line_match.old.0);
+            let number = match old_page {
+                Some(old_page) if Some(old_page) > last_old_page => {
+                    last_old_page = Some(old_page);
+                    self.locked_pages.get(old_page).cloned().flatten()
+                }
+                _ => numbers
+                    .last()
+                    .cloned()
+                    .flatten()
+                    .map(|previous| previous.next_a_page()),
+            };
+            numbers.push(number);
+        }
+        numbers
+    }
+}
+
+/// What pages without a number, like the title page, are labelled as.
+pub const UNNUMBERED_PAGE_LABEL: &str = "title";
+
+/// A page number as text, or `UNNUMBERED_PAGE_LABEL`.
+pub fn page_number_label(number: Option<&ProductionPageNumber>) -> String {
+    number
+        .map(|number| number.to_string())
+        .unwrap_or(UNNUMBERED_PAGE_LABEL.to_string())
+}
+
+/// A page's label out of `page_labels` from `Revisions::page_labels`; `UNNUMBERED_PAGE_LABEL` past their end.
+pub fn page_label_at(page_labels: &[String], page: usize) -> String {
+    page_labels
+        .get(page)
+        .cloned()
+        .unwrap_or(UNNUMBERED_PAGE_LABEL.to_string())
+}
+
+/// A position as production refers to it, e.g. "12A:5". See `page_label_at`.
+pub fn position_label(page_labels: &[String], page: usize, line: usize) -> String {
+    format!("{}:{}", page_label_at(page_labels, page), line)
+}
+
+/// Each page's number as printed on it, by page index.
+///
+/// Pages without a printed number are counted on from the numbered page before them,
+/// or back from the one after them; counting back from page 2 makes the page before it 1,
+/// and anything before that (like the title page) `None`.
+/// If no page has a number printed on it, pages are numbered from 1.
+pub fn page_numbers(screenplay: &ScreenplayDocument) -> Vec<Option<ProductionPageNumber>> {
+    let printed: Vec<Option<ProductionPageNumber>> = screenplay
+        .pages
+        .iter()
+        .map(|page| {
+            page.page_number
+                .as_ref()
+                .and_then(|number| ProductionPageNumber::parse(&number.0))
+        })
+        .collect();
+    let Some(first) = printed.iter().position(|number| number.is_some()) else {
+        return (1..=screenplay.pages.len())
+            .map(|number| Some(ProductionPageNumber::new(number as u32)))
+            .collect();
+    };
+
+    let mut numbers: Vec<Option<ProductionPageNumber>> = Vec::new();
+    let mut previous: Option<(usize, u32)> = None;
+    for (page_index, number) in printed.iter().enumerate() {
+        let number = match (number, previous) {
+            (Some(number), _) => Some(number.clone()),
+            (None, Some((index, number))) => Some(ProductionPageNumber::new(
+                number + (page_index - index) as u32,
+            )),
+            (None, None) => printed[first]
+                .as_ref()
+                .and_then(|number| number.number.checked_sub((first - page_index) as u32))
+                .filter(|number| *number > 0)
+                .map(ProductionPageNumber::new),
+        };
+        if let Some(number) = number.as_ref().filter(|_| page_index >= first) {
+            previous = Some((page_index, number.number));
+        }
+        numbers.push(number);
+    }
+    numbers
+}
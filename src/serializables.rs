@@ -1,8 +1,48 @@
-use std::collections::HashSet;
-use std::ops::Range;
+/// The native, versioned ShotLiner document format (`.sl`).
+pub mod sl_file;
 
-use crate::production::*;
-use crate::shotliner_document;
-use screenplay_doc_parser_rs::{self, screenplay_document};
+/// Upgrades `.sl` documents saved by older format versions to the current one.
+pub mod migrations;
 
+/// Serde adapters for `screenplay_doc_parser_rs` types, which don't implement `Serialize` or `Deserialize` themselves.
+pub mod serde_helpers;
 
+/// Shot lists as CSV, with configurable columns.
+pub mod shot_list_csv;
+
+/// Krita (`.kra`) storyboard templates, with a layer group for every shot.
+pub mod kra_storyboard;
+
+/// Writes the uncompressed zip archives that container formats like `.kra` are built on.
+pub mod zip_writer;
+
+/// Hand-written PDF output: standard fonts, vector drawing and embedded images.
+pub mod pdf_writer;
+
+/// Printable N-up storyboard sheets, as SVG pages or a single PDF.
+pub mod storyboard_sheets;
+
+/// The lined script: screenplay pages redrawn as PDF with shotlines over the text.
+pub mod lined_script;
+
+/// The breakdown-colored script: tagged ranges highlighted by department, with a color key.
+pub mod breakdown_script;
+
+/// Greedily wraps lines to roughly `max_chars` characters, breaking only between words.
+pub(crate) fn wrap_lines(lines: &[String], max_chars: usize) -> Vec<String> {
+    let mut wrapped: Vec<String> = Vec::new();
+    for line in lines {
+        let mut current = String::new();
+        for word in line.split_whitespace() {
+            if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+                wrapped.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        wrapped.push(current);
+    }
+    wrapped
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::{fs, path::Path};
+
+use crate::production::Department;
+use crate::reports;
+use crate::serializables::lined_script::{self, LinedScriptSettings};
+use crate::serializables::pdf_writer::{PdfContent, PdfFont, PdfWriter};
+use crate::serializables::shot_list_csv::department_name;
+use crate::shotliner_document::{ShotlinerDoc, TaggedElement};
+
+pub type Rgb = (f64, f64, f64);
+
+/// Drawn for tags that haven't been given a department yet.
+const NO_DEPARTMENT_COLOR: Rgb = (0.8, 0.8, 0.8);
+
+/// The usual breakdown colors where there's a convention (props violet, vehicles pink, stunts orange,
+/// effects blue, ...), and pale, distinct colors for the rest.
+pub fn department_color(department: &Department) -> Rgb {
+    match department {
+        Department::Production => (1.0, 0.55, 0.55),
+        Department::Art => (0.65, 0.8, 0.6),
+        Department::Wardrobe => (0.95, 0.8, 0.55),
+        Department::HairMakeup => (1.0, 0.75, 0.9),
+        Department::Camera => (0.7, 0.75, 0.85),
+        Department::Sound => (0.8, 0.65, 0.45),
+        Department::Electric => (1.0, 0.95, 0.45),
+        Department::LightingGrip => (0.95, 0.9, 0.65),
+        Department::Props => (0.75, 0.6, 1.0),
+        Department::PracticalFX => (0.45, 0.65, 1.0),
+        Department::VisualFX => (0.6, 0.85, 1.0),
+        Department::Stunts => (1.0, 0.7, 0.3),
+        Department::Animals => (0.55, 0.85, 0.45),
+        Department::Vehicles => (1.0, 0.6, 0.8),
+        Department::Dance => (0.9, 0.6, 0.9),
+        Department::Choreography => (0.85, 0.7, 0.95),
+        Department::Pyrotechnics => (1.0, 0.5, 0.35),
+        Department::CraftServices => (0.85, 0.9, 0.6),
+        Department::Transportation => (0.95, 0.65, 0.7),
+        Department::Miscellaneous => (0.85, 0.85, 0.75),
+        Department::Other(_) => (0.75, 0.75, 0.75),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighlightStyle {
+    /// A filled box behind the text, split into a band per department.
+    Highlight,
+    /// A colored rule under the text, one per department.
+    Underline,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BreakdownScriptSettings {
+    pub page: LinedScriptSettings,
+    pub style: HighlightStyle,
+    /// Replaces the default color of a department.
+    pub colors: HashMap<Department, Rgb>,
+    /// Put a color key before the first page of the script.
+    pub legend: bool,
+}
+impl BreakdownScriptSettings {
+    pub fn color(&self, department: &Department) -> Rgb {
+        self.colors
+            .get(department)
+            .copied()
+            .unwrap_or(department_color(department))
+    }
+}
+impl Default for BreakdownScriptSettings {
+    fn default() -> Self {
+        BreakdownScriptSettings {
+            page: LinedScriptSettings::default(),
+            style: HighlightStyle::Highlight,
+            colors: HashMap::new(),
+            legend: true,
+        }
+    }
+}
+
+/// One highlighted stretch of a single line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Highlight {
+    pub line: usize,
+    pub x_start: f64,
+    pub x_end: f64,
+    /// The departments of every tag on the element, without repeats; empty if none of them have one.
+    pub departments: Vec<Department>,
+}
+
+/// The departments of all of an element's tags, in tag order.
+fn element_departments(doc: &ShotlinerDoc, element: &TaggedElement) -> Vec<Department> {
+    let mut departments: Vec<Department> = Vec::new();
+    for tag in element
+        .tags
+        .iter()
+        .filter_map(|id| doc.annotation_map.tags.get(id))
+    {
+        for department in &tag.departments {
+            if !departments.contains(department) {
+                departments.push(department.clone());
+            }
+        }
+    }
+    departments
+}
+
+/// Every highlight in the document, grouped by page.
+///
+/// A range that starts or ends partway through a line only covers the text elements inside it.
+pub fn get_highlights(
+    doc: &ShotlinerDoc,
+    settings: &LinedScriptSettings,
+) -> HashMap<usize, Vec<Highlight>> {
+    let mut highlights: HashMap<usize, Vec<Highlight>> = HashMap::new();
+    let mut elements: Vec<&TaggedElement> = doc.annotation_map.tagged_elements.values().collect();
+    elements.sort_by_key(|element| {
+        (
+            element.origin.page,
+            element.origin.line,
+            element.origin.element,
+        )
+    });
+
+    for element in elements {
+        let departments = element_departments(doc, element);
+        for (start, end) in reports::get_tagged_element_ranges(element) {
+            for (coord, line) in reports::get_lines_in_range(&doc.screenplay, &start, &end) {
+                let extents = lined_script::element_extents(line, settings);
+                if extents.is_empty() {
+                    continue;
+                }
+                let first = match start.element {
+                    Some(index) if (coord.page, coord.line) == (start.page, start.line) => {
+                        index as usize
+                    }
+                    _ => 0,
+                };
+                let last = match end.element {
+                    Some(index) if (coord.page, coord.line) == (end.page, end.line) => {
+                        index as usize
+                    }
+                    _ => extents.len() - 1,
+                }
+                .min(extents.len() - 1);
+                if first > last {
+                    continue;
+                }
+                highlights.entry(coord.page).or_default().push(Highlight {
+                    line: coord.line,
+                    x_start: extents[first].0,
+                    x_end: extents[last].1,
+                    departments: departments.clone(),
+                });
+            }
+        }
+    }
+    highlights
+}
+
+fn draw_highlight(
+    content: &mut PdfContent,
+    highlight: &Highlight,
+    baseline: f64,
+    settings: &BreakdownScriptSettings,
+) {
+    let colors: Vec<Rgb> = if highlight.departments.is_empty() {
+        vec![NO_DEPARTMENT_COLOR]
+    } else {
+        highlight
+            .departments
+            .iter()
+            .map(|department| settings.color(department))
+            .collect()
+    };
+    let padding = 1.0;
+    let x = highlight.x_start - padding;
+    let width = highlight.x_end - highlight.x_start + padding * 2.0;
+    match settings.style {
+        HighlightStyle::Highlight => {
+            let bottom = baseline - settings.page.descent();
+            let band = (settings.page.ascent() + settings.page.descent()) / colors.len() as f64;
+            // the first department gets the top band
+            for (index, color) in colors.iter().enumerate() {
+                content.fill_color(*color);
+                content.fill_rect(
+                    x,
+                    bottom + band * (colors.len() - 1 - index) as f64,
+                    width,
+                    band,
+                );
+            }
+        }
+        HighlightStyle::Underline => {
+            content.line_width(1.5);
+            for (index, color) in colors.iter().enumerate() {
+                let y = baseline - 2.0 - index as f64 * 2.0;
+                content.stroke_color(*color);
+                content.line((x, y), (x + width, y));
+            }
+        }
+    }
+}
+
+/// One page of the breakdown script: the highlights, with the page's text drawn over them.
+pub fn render_page(
+    doc: &ShotlinerDoc,
+    page_index: usize,
+    highlights: &HashMap<usize, Vec<Highlight>>,
+    settings: &BreakdownScriptSettings,
+) -> PdfContent {
+    let mut content = PdfContent::new();
+    let Some(page) = doc.screenplay.pages.get(page_index) else {
+        return content;
+    };
+    content.save_state();
+    for highlight in highlights.get(&page_index).into_iter().flatten() {
+        let baseline = lined_script::line_baseline(page, highlight.line, &settings.page);
+        draw_highlight(&mut content, highlight, baseline, settings);
+    }
+    content.restore_state();
+    lined_script::draw_screenplay_page_text(&mut content, page, &settings.page);
+    content
+}
+
+/// The departments used by the document's tags, in legend order, with how many tags are in each.
+///
+/// Tags without a department are counted under `None`, last.
+pub fn departments_in_use(doc: &ShotlinerDoc) -> Vec<(Option<Department>, usize)> {
+    let mut counts: HashMap<Option<Department>, usize> = HashMap::new();
+    for tag in doc.annotation_map.tags.values() {
+        if tag.departments.is_empty() {
+            *counts.entry(None).or_default() += 1;
+        }
+        for department in &tag.departments {
+            *counts.entry(Some(department.clone())).or_default() += 1;
+        }
+    }
+    let mut departments: Vec<(Option<Department>, usize)> = counts.into_iter().collect();
+    departments.sort_by_key(|(department, _)| match department {
+        Some(department) => (0, department.report_order()),
+        None => (1, (0, String::new())),
+    });
+    departments
+}
+
+/// The color key: a swatch, name and tag count for every department in use.
+pub fn render_legend(doc: &ShotlinerDoc, settings: &BreakdownScriptSettings) -> PdfContent {
+    let mut content = PdfContent::new();
+    let margin = 72.0;
+    let mut y = settings.page.page_height - margin;
+    content.fill_color((0.0, 0.0, 0.0));
+    content.text(margin, y, PdfFont::HelveticaBold, 18.0, "Breakdown Colors");
+    y -= 36.0;
+
+    for (department, count) in departments_in_use(doc) {
+        let (color, name) = match &department {
+            Some(department) => (settings.color(department), department_name(department)),
+            None => (NO_DEPARTMENT_COLOR, "No Department".to_string()),
+        };
+        content.fill_color(color);
+        content.fill_rect(margin, y - 3.0, 36.0, 14.0);
+        content.fill_color((0.0, 0.0, 0.0));
+        content.text(margin + 48.0, y, PdfFont::Helvetica, 12.0, &name);
+        let count = if count == 1 {
+            "1 tag".to_string()
+        } else {
+            format!("{} tags", count)
+        };
+        content.text(margin + 240.0, y, PdfFont::Helvetica, 12.0, &count);
+        y -= 22.0;
+        if y < margin {
+            break;
+        }
+    }
+    content
+}
+
+/// The breakdown-colored script: the legend, then every page with its tagged ranges highlighted.
+pub fn breakdown_script_pdf(doc: &ShotlinerDoc, settings: &BreakdownScriptSettings) -> Vec<u8> {
+    let highlights = get_highlights(doc, &settings.page);
+    let mut pdf = PdfWriter::new();
+    if settings.legend {
+        pdf.add_page(
+            settings.page.page_width,
+            settings.page.page_height,
+            &render_legend(doc, settings),
+        );
+    }
+    for page_index in 0..doc.screenplay.pages.len() {
+        pdf.add_page(
+            settings.page.page_width,
+            settings.page.page_height,
+            &render_page(doc, page_index, &highlights, settings),
+        );
+    }
+    pdf.finish()
+}
+
+pub fn save_to_file(
+    doc: &ShotlinerDoc,
+    settings: &BreakdownScriptSettings,
+    path: impl AsRef<Path>,
+) -> Result<(), std::io::Error> {
+    fs::write(path, breakdown_script_pdf(doc, settings))
+}
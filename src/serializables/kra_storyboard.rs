@@ -0,0 +1,312 @@
+use std::{fs, io, path::Path};
+
+use uuid::Uuid;
+
+use screenplay_doc_parser_rs::reports as screenplay_reports;
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayDocument;
+
+use crate::reports::{self, ShotList, ShotListEntry};
+use crate::serializables::wrap_lines;
+use crate::serializables::zip_writer::ZipWriter;
+
+pub const KRA_FILE_EXTENSION: &str = "kra";
+const KRA_MIMETYPE: &str = "application/x-krita";
+/// Krita stores each layer's data under a directory named after the image.
+const IMAGE_NAME: &str = "Storyboard";
+
+/// Panel layout of the storyboard canvas, in pixels.
+///
+/// Panels are laid out left to right, top to bottom, each with a caption area underneath.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KraTemplateSettings {
+    pub panel_width: u32,
+    pub panel_height: u32,
+    pub caption_height: u32,
+    pub margin: u32,
+    pub columns: u32,
+    pub font_size: u32,
+}
+impl Default for KraTemplateSettings {
+    fn default() -> Self {
+        KraTemplateSettings {
+            panel_width: 640,
+            panel_height: 360,
+            caption_height: 160,
+            margin: 40,
+            columns: 3,
+            font_size: 16,
+        }
+    }
+}
+impl KraTemplateSettings {
+    fn cell_width(&self) -> u32 {
+        self.panel_width + self.margin
+    }
+    fn cell_height(&self) -> u32 {
+        self.panel_height + self.caption_height + self.margin
+    }
+    /// Top-left corner of the `index`th panel.
+    fn panel_origin(&self, index: usize) -> (u32, u32) {
+        let column = index as u32 % self.columns.max(1);
+        let row = index as u32 / self.columns.max(1);
+        (
+            self.margin + column * self.cell_width(),
+            self.margin + row * self.cell_height(),
+        )
+    }
+    fn canvas_size(&self, panel_count: usize) -> (u32, u32) {
+        let columns = self.columns.max(1).min(panel_count.max(1) as u32);
+        let rows = (panel_count.max(1) as u32).div_ceil(self.columns.max(1));
+        (
+            self.margin + columns * self.cell_width(),
+            self.margin + rows * self.cell_height(),
+        )
+    }
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// "Sc 3 / Shot 3A: CloseUp Dolly"
+///
+/// Scenes without a printed scene number are labelled by their position in the script.
+pub fn panel_label(screenplay: &ScreenplayDocument, entry: &ShotListEntry) -> String {
+    let scene = match (entry.scene_id, entry.scene) {
+        (_, Some(scene)) if scene.number.is_some() => scene.number.as_ref().unwrap().0.clone(),
+        (Some(scene_id), _) => screenplay_reports::get_all_scenes_ordered(screenplay)
+            .unwrap_or_default()
+            .iter()
+            .position(|(id, _)| *id == scene_id)
+            .map(|index| (index + 1).to_string())
+            .unwrap_or("?".to_string()),
+        _ => "-".to_string(),
+    };
+    let shot = entry
+        .shot_number
+        .map(|number| number.0.clone())
+        .unwrap_or("?".to_string());
+    let mut label = format!("Sc {} / Shot {}: {:?}", scene, shot, entry.composition.shot_type);
+    if let Some(subtype) = &entry.composition.subtype {
+        label.push_str(&format!(" {:?}", subtype));
+    }
+    label
+}
+
+/// The lined text of a shot, leaving out the lines marked as unfilmed.
+pub fn panel_text(screenplay: &ScreenplayDocument, entry: &ShotListEntry) -> Vec<String> {
+    let Some(shotline) = &entry.shot.shotline else {
+        return Vec::new();
+    };
+    reports::get_lines_in_range(screenplay, &shotline.start, &shotline.end)
+        .into_iter()
+        .filter(|(coord, _)| {
+            !shotline
+                .unfilmed_lines
+                .as_ref()
+                .is_some_and(|unfilmed| unfilmed.contains(coord))
+        })
+        .map(|(_, line)| reports::get_line_text(line))
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
+fn svg_document(width: u32, height: u32, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" standalone=\"no\"?>\n\
+<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 20010904//EN\" \"http://www.w3.org/TR/2001/REC-SVG-20010904/DTD/svg10.dtd\">\n\
+<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+xmlns:krita=\"http://krita.org/namespaces/svg/krita\" \
+width=\"{w}pt\" height=\"{h}pt\" viewBox=\"0 0 {w} {h}\">\n{body}</svg>\n",
+        w = width,
+        h = height,
+        body = body
+    )
+}
+
+fn frame_svg(settings: &KraTemplateSettings, origin: (u32, u32)) -> String {
+    format!(
+        "<rect id=\"frame\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#000000\" stroke-width=\"3\"/>\n",
+        origin.0, origin.1, settings.panel_width, settings.panel_height
+    )
+}
+
+fn caption_svg(
+    settings: &KraTemplateSettings,
+    origin: (u32, u32),
+    label: &str,
+    text: &[String],
+) -> String {
+    let line_height = settings.font_size + settings.font_size / 4;
+    let x = origin.0;
+    let top = origin.1 + settings.panel_height + line_height;
+    let max_lines = (settings.caption_height / line_height).saturating_sub(1) as usize;
+    // monospaced screenplay text is about 0.6em wide
+    let max_chars = (settings.panel_width as f64 / (settings.font_size as f64 * 0.6)) as usize;
+
+    let mut lines = wrap_lines(text, max_chars.max(1));
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push_str(" \u{2026}");
+        }
+    }
+
+    let mut svg = format!(
+        "<text id=\"label\" x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-weight=\"bold\" font-size=\"{}\" fill=\"#000000\">{}</text>\n",
+        x,
+        top,
+        settings.font_size,
+        escape_xml(label)
+    );
+    svg.push_str(&format!(
+        "<text id=\"text\" font-family=\"Courier Prime, Courier New, monospace\" font-size=\"{}\" fill=\"#000000\">",
+        settings.font_size
+    ));
+    for (index, line) in lines.iter().enumerate() {
+        svg.push_str(&format!(
+            "<tspan x=\"{}\" y=\"{}\">{}</tspan>",
+            x,
+            top + (index as u32 + 1) * line_height,
+            escape_xml(line)
+        ));
+    }
+    svg.push_str("</text>\n");
+    svg
+}
+
+/// An empty tiled paint layer, in Krita's "VERSION 2" tile format.
+fn empty_paint_layer_data() -> &'static [u8] {
+    b"VERSION 2\nTILEWIDTH 64\nTILEHEIGHT 64\nPIXELSIZE 4\nDATA 0\n"
+}
+
+struct LayerFiles<'a> {
+    zip: &'a mut ZipWriter,
+    next_layer: u32,
+}
+impl LayerFiles<'_> {
+    fn next_filename(&mut self) -> String {
+        self.next_layer += 1;
+        format!("layer{}", self.next_layer)
+    }
+    fn add(&mut self, path: String, contents: &[u8]) -> Result<(), io::Error> {
+        self.zip
+            .add_file(&format!("{}/layers/{}", IMAGE_NAME, path), contents)
+    }
+}
+
+fn layer_attributes(name: &str, filename: &str, nodetype: &str) -> String {
+    format!(
+        "name=\"{}\" filename=\"{}\" nodetype=\"{}\" uuid=\"{{{}}}\" visible=\"1\" locked=\"0\" \
+opacity=\"255\" compositeop=\"normal\" x=\"0\" y=\"0\" collapsed=\"0\" colorlabel=\"0\" intimeline=\"0\"",
+        escape_xml(name),
+        filename,
+        nodetype,
+        Uuid::new_v4()
+    )
+}
+
+fn shape_layer(files: &mut LayerFiles, name: &str, svg: String) -> Result<String, io::Error> {
+    let filename = files.next_filename();
+    files.add(format!("{}.shapelayer/content.svg", filename), svg.as_bytes())?;
+    Ok(format!("<layer {}/>\n", layer_attributes(name, &filename, "shapelayer")))
+}
+
+fn paint_layer(
+    files: &mut LayerFiles,
+    name: &str,
+    default_pixel: Option<[u8; 4]>,
+) -> Result<String, io::Error> {
+    let filename = files.next_filename();
+    files.add(filename.clone(), empty_paint_layer_data())?;
+    if let Some(pixel) = default_pixel {
+        files.add(format!("{}.defaultpixel", filename), &pixel)?;
+    }
+    Ok(format!(
+        "<layer {} colorspacename=\"RGBA\" channelflags=\"\"/>\n",
+        layer_attributes(name, &filename, "paintlayer")
+    ))
+}
+
+/// Builds a `.kra` storyboard template: one layer group per shot, in shot list order.
+///
+/// Each group holds a caption (scene/shot label and the lined text), an empty "Drawing" layer, and a panel frame.
+///
+/// Fails if there are too many shots for a zip archive to hold.
+pub fn storyboard_template(
+    screenplay: &ScreenplayDocument,
+    shotlist: &ShotList,
+    settings: &KraTemplateSettings,
+) -> Result<Vec<u8>, io::Error> {
+    let (width, height) = settings.canvas_size(shotlist.entries.len());
+    let mut zip = ZipWriter::new();
+    zip.add_file("mimetype", KRA_MIMETYPE.as_bytes())?;
+
+    let mut files = LayerFiles {
+        zip: &mut zip,
+        next_layer: 0,
+    };
+    let mut layers = String::new();
+    for (index, entry) in shotlist.entries.iter().enumerate() {
+        let origin = settings.panel_origin(index);
+        let label = panel_label(screenplay, entry);
+        let text = panel_text(screenplay, entry);
+
+        let group_filename = files.next_filename();
+        let caption = shape_layer(
+            &mut files,
+            "Caption",
+            svg_document(width, height, &caption_svg(settings, origin, &label, &text)),
+        )?;
+        let drawing = paint_layer(&mut files, "Drawing", None)?;
+        let frame = shape_layer(
+            &mut files,
+            "Frame",
+            svg_document(width, height, &frame_svg(settings, origin)),
+        )?;
+        layers.push_str(&format!(
+            "<layer {} passthrough=\"0\">\n<layers>\n{}{}{}</layers>\n</layer>\n",
+            layer_attributes(&label, &group_filename, "grouplayer"),
+            caption,
+            drawing,
+            frame
+        ));
+    }
+    layers.push_str(&paint_layer(
+        &mut files,
+        "Background",
+        Some([255, 255, 255, 255]),
+    )?);
+
+    let maindoc = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<!DOCTYPE DOC PUBLIC '-//KDE//DTD krita 2.0//EN' 'http://www.calligra.org/DTD/krita-2.0.dtd'>\n\
+<DOC xmlns=\"http://www.calligra.org/DTD/krita\" syntaxVersion=\"2.0\" editor=\"Krita\" kritaVersion=\"5.2.0\">\n\
+<IMAGE name=\"{}\" mime=\"application/x-kra\" width=\"{}\" height=\"{}\" colorspacename=\"RGBA\" \
+profile=\"sRGB-elle-V2-srgbtrc.icc\" x-res=\"72\" y-res=\"72\" description=\"\">\n\
+<layers>\n{}</layers>\n</IMAGE>\n</DOC>\n",
+        IMAGE_NAME, width, height, layers
+    );
+    let documentinfo = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<!DOCTYPE document-info PUBLIC '-//KDE//DTD document-info 1.1//EN' 'http://www.calligra.org/DTD/document-info-1.1.dtd'>\n\
+<document-info xmlns=\"http://www.calligra.org/DTD/document-info\">\n\
+<about><title>Storyboard</title><generator>ShotLiner</generator></about>\n\
+</document-info>\n";
+
+    zip.add_file("maindoc.xml", maindoc.as_bytes())?;
+    zip.add_file("documentinfo.xml", documentinfo.as_bytes())?;
+    zip.finish()
+}
+
+pub fn save_to_file(
+    screenplay: &ScreenplayDocument,
+    shotlist: &ShotList,
+    settings: &KraTemplateSettings,
+    path: impl AsRef<Path>,
+) -> Result<(), std::io::Error> {
+    fs::write(path, storyboard_template(screenplay, shotlist, settings)?)
+}
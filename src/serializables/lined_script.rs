@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::{fs, path::Path};
+
+use screenplay_doc_parser_rs::screenplay_document::{
+    self, ScreenplayCoordinate, ScreenplayDocument,
+};
+
+use crate::production::ShotID;
+use crate::serializables::pdf_writer::{PdfContent, PdfFont, PdfWriter};
+use crate::shotliner_document::{ShotLine, ShotlinerDoc};
+
+/// Page geometry and shotline styling, in PDF points.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinedScriptSettings {
+    pub page_width: f64,
+    pub page_height: f64,
+    pub font_size: f64,
+    /// Where lines without a parsed position are drawn, counting down from `body_top`.
+    pub body_top: f64,
+    pub line_height: f64,
+    pub default_x: f64,
+
+    /// Shotlines are drawn in lanes, left to right, so overlapping shots never share a lane.
+    pub first_lane_x: f64,
+    pub lane_spacing: f64,
+    /// How many lanes fit at `lane_spacing`; when more are needed they're squeezed into the same width.
+    pub lane_count: usize,
+    pub shotline_width: f64,
+    /// How far a squiggle swings to either side of the shotline.
+    pub squiggle_amplitude: f64,
+}
+impl LinedScriptSettings {
+    pub fn us_letter_default() -> Self {
+        LinedScriptSettings {
+            page_width: 612.0,
+            page_height: 792.0,
+            font_size: 12.0,
+            body_top: 720.0,
+            line_height: 12.0,
+            default_x: 108.0,
+            first_lane_x: 300.0,
+            lane_spacing: 16.0,
+            lane_count: 16,
+            shotline_width: 1.2,
+            squiggle_amplitude: 3.0,
+        }
+    }
+    /// Where `lane` is drawn when `lanes_used` lanes are in use.
+    pub fn lane_x(&self, lane: usize, lanes_used: usize) -> f64 {
+        let lane_count = self.lane_count.max(1);
+        let spacing = if lanes_used > lane_count {
+            self.lane_spacing * (lane_count - 1) as f64 / (lanes_used - 1) as f64
+        } else {
+            self.lane_spacing
+        };
+        self.first_lane_x + lane as f64 * spacing
+    }
+    pub fn ascent(&self) -> f64 {
+        self.font_size * 0.75
+    }
+    pub fn descent(&self) -> f64 {
+        self.font_size * 0.25
+    }
+}
+impl Default for LinedScriptSettings {
+    fn default() -> Self {
+        LinedScriptSettings::us_letter_default()
+    }
+}
+
+/// The baseline of a line: its parsed position if it has one, otherwise counted down from the top of the body.
+pub fn line_baseline(
+    page: &screenplay_document::Page,
+    line_index: usize,
+    settings: &LinedScriptSettings,
+) -> f64 {
+    page.lines
+        .get(line_index)
+        .and_then(|line| line.text_elements.iter().find_map(|e| e.element_position))
+        .map(|position| position.y)
+        .unwrap_or(settings.body_top - line_index as f64 * settings.line_height)
+}
+
+/// Where each of a line's text elements starts and ends horizontally.
+///
+/// Elements without a parsed position follow on from the one before, a space apart.
+pub fn element_extents(
+    line: &screenplay_document::Line,
+    settings: &LinedScriptSettings,
+) -> Vec<(f64, f64)> {
+    let char_width = settings.font_size * PdfFont::Courier.average_char_width();
+    let mut x = settings.default_x;
+    let mut extents = Vec::new();
+    for element in &line.text_elements {
+        if let Some(position) = element.element_position {
+            x = position.x;
+        }
+        let width = element.text.chars().count() as f64 * char_width;
+        extents.push((x, x + width));
+        x += width + char_width;
+    }
+    extents
+}
+
+/// Redraws a page's text where the parser found it, in Courier.
+pub fn draw_screenplay_page_text(
+    content: &mut PdfContent,
+    page: &screenplay_document::Page,
+    settings: &LinedScriptSettings,
+) {
+    content.fill_color((0.0, 0.0, 0.0));
+    for (line_index, line) in page.lines.iter().enumerate() {
+        let baseline = line_baseline(page, line_index, settings);
+        let extents = element_extents(line, settings);
+        for (element, (x, _)) in line.text_elements.iter().zip(extents) {
+            content.text(
+                x,
+                baseline,
+                PdfFont::Courier,
+                settings.font_size,
+                &element.text,
+            );
+        }
+    }
+}
+
+/// Assigns every shot with a ShotLine to a lane, so shotlines that overlap are drawn side by side.
+///
+/// Shots are placed in order of where they start; each takes the leftmost lane that's free by then.
+pub fn assign_lanes(doc: &ShotlinerDoc) -> HashMap<ShotID, usize> {
+    let mut shots: Vec<(&ShotID, &ShotLine)> = doc
+        .annotation_map
+        .shotlines
+        .iter()
+        .filter_map(|(id, shot)| shot.shotline.as_ref().map(|sl| (id, sl)))
+        .collect();
+    shots.sort_by(|(a_id, a), (b_id, b)| {
+        (a.start.page, a.start.line, a.end.page, a.end.line)
+            .cmp(&(b.start.page, b.start.line, b.end.page, b.end.line))
+            .then_with(|| a_id.cmp(b_id))
+    });
+
+    let mut lane_ends: Vec<(usize, usize)> = Vec::new();
+    let mut lanes: HashMap<ShotID, usize> = HashMap::new();
+    for (id, shotline) in shots {
+        let start = (shotline.start.page, shotline.start.line);
+        let end = (shotline.end.page, shotline.end.line);
+        let lane = match lane_ends.iter().position(|lane_end| *lane_end < start) {
+            Some(lane) => {
+                lane_ends[lane] = end;
+                lane
+            }
+            None => {
+                lane_ends.push(end);
+                lane_ends.len() - 1
+            }
+        };
+        lanes.insert(id.clone(), lane);
+    }
+    lanes
+}
+
+/// A zigzag down from `top` to `bottom`, for lines that are off camera.
+fn squiggle(x: f64, top: f64, bottom: f64, amplitude: f64) -> Vec<(f64, f64)> {
+    let step = amplitude * 1.5;
+    let mut points = vec![(x, top)];
+    let mut y = top - step / 2.0;
+    let mut side = 1.0;
+    while y > bottom + step / 2.0 {
+        points.push((x + side * amplitude, y));
+        side = -side;
+        y -= step;
+    }
+    points.push((x, bottom));
+    points
+}
+
+fn draw_arrowhead_down(content: &mut PdfContent, x: f64, y: f64) {
+    content.fill_polygon(&[(x - 4.0, y + 7.0), (x + 4.0, y + 7.0), (x, y)]);
+}
+
+/// Draws the part of one shotline that falls on `page_index`.
+fn draw_shotline_segment(
+    content: &mut PdfContent,
+    page_index: usize,
+    page: &screenplay_document::Page,
+    shotline: &ShotLine,
+    label: &str,
+    x: f64,
+    settings: &LinedScriptSettings,
+) {
+    if page.lines.is_empty() || page_index < shotline.start.page || page_index > shotline.end.page {
+        return;
+    }
+    let first = if page_index == shotline.start.page {
+        shotline.start.line
+    } else {
+        0
+    };
+    let last = if page_index == shotline.end.page {
+        shotline.end.line.min(page.lines.len() - 1)
+    } else {
+        page.lines.len() - 1
+    };
+    if last < first {
+        return;
+    }
+    let continues_from_previous = page_index > shotline.start.page;
+    let continues_to_next = page_index < shotline.end.page;
+
+    // each line owns the space halfway to its neighbours, so runs of filmed and unfilmed lines meet exactly
+    let baselines: Vec<f64> = (first..=last)
+        .map(|line| line_baseline(page, line, settings))
+        .collect();
+    let top = baselines[0] + settings.ascent();
+    let bottom = baselines[baselines.len() - 1] - settings.descent();
+    let boundary = |index: usize| -> f64 {
+        if index == 0 {
+            return top;
+        }
+        if index == baselines.len() {
+            return bottom;
+        }
+        ((baselines[index - 1] - settings.descent()) + (baselines[index] + settings.ascent())) / 2.0
+    };
+
+    content.save_state();
+    content.stroke_color((0.0, 0.0, 0.0));
+    content.fill_color((0.0, 0.0, 0.0));
+    content.line_width(settings.shotline_width);
+
+    let mut run_start = 0;
+    while run_start < baselines.len() {
+        let unfilmed = |index: usize| {
+            let coord = ScreenplayCoordinate {
+                page: page_index,
+                line: first + index,
+                element: None,
+            };
+            shotline
+                .unfilmed_lines
+                .as_ref()
+                .is_some_and(|lines| lines.contains(&coord))
+        };
+        let run_unfilmed = unfilmed(run_start);
+        let mut run_end = run_start + 1;
+        while run_end < baselines.len() && unfilmed(run_end) == run_unfilmed {
+            run_end += 1;
+        }
+        let (run_top, run_bottom) = (boundary(run_start), boundary(run_end));
+        if run_unfilmed {
+            content.polyline(&squiggle(
+                x,
+                run_top,
+                run_bottom,
+                settings.squiggle_amplitude,
+            ));
+        } else {
+            content.line((x, run_top), (x, run_bottom));
+        }
+        run_start = run_end;
+    }
+
+    // the top gets the shot number, the bottom gets an arrow if the shot carries on past the page, or an end tick
+    let label = if continues_from_previous {
+        format!("({})", label)
+    } else {
+        label.to_string()
+    };
+    let label_size = 8.0;
+    let label_width =
+        label.chars().count() as f64 * label_size * PdfFont::HelveticaBold.average_char_width();
+    content.text(
+        x - label_width / 2.0,
+        top + 3.0,
+        PdfFont::HelveticaBold,
+        label_size,
+        &label,
+    );
+    if continues_to_next {
+        draw_arrowhead_down(content, x, bottom - 8.0);
+        content.line((x, bottom), (x, bottom - 2.0));
+    } else {
+        content.line((x - 4.0, bottom), (x + 4.0, bottom));
+    }
+    content.restore_state();
+}
+
+/// Renders one page of the lined script: the screenplay text, plus every shotline crossing the page.
+pub fn render_page(
+    doc: &ShotlinerDoc,
+    page_index: usize,
+    lanes: &HashMap<ShotID, usize>,
+    settings: &LinedScriptSettings,
+) -> PdfContent {
+    let mut content = PdfContent::new();
+    let Some(page) = doc.screenplay.pages.get(page_index) else {
+        return content;
+    };
+    draw_screenplay_page_text(&mut content, page, settings);
+
+    let lanes_used = lanes.values().max().map_or(0, |lane| lane + 1);
+    for (id, shot) in &doc.annotation_map.shotlines {
+        let (Some(shotline), Some(lane)) = (&shot.shotline, lanes.get(id)) else {
+            continue;
+        };
+        let label = shot
+            .shot_number
+            .as_ref()
+            .map(|number| number.0.clone())
+            .unwrap_or("?".to_string());
+        draw_shotline_segment(
+            &mut content,
+            page_index,
+            page,
+            shotline,
+            &label,
+            settings.lane_x(*lane, lanes_used),
+            settings,
+        );
+    }
+    content
+}
+
+fn pages_pdf(
+    screenplay: &ScreenplayDocument,
+    settings: &LinedScriptSettings,
+    mut render: impl FnMut(usize) -> PdfContent,
+) -> Vec<u8> {
+    let mut pdf = PdfWriter::new();
+    for page_index in 0..screenplay.pages.len() {
+        pdf.add_page(
+            settings.page_width,
+            settings.page_height,
+            &render(page_index),
+        );
+    }
+    pdf.finish()
+}
+
+/// The whole lined script, one PDF page per screenplay page.
+pub fn lined_script_pdf(doc: &ShotlinerDoc, settings: &LinedScriptSettings) -> Vec<u8> {
+    let lanes = assign_lanes(doc);
+    pages_pdf(&doc.screenplay, settings, |page_index| {
+        render_page(doc, page_index, &lanes, settings)
+    })
+}
+
+pub fn save_to_file(
+    doc: &ShotlinerDoc,
+    settings: &LinedScriptSettings,
+    path: impl AsRef<Path>,
+) -> Result<(), std::io::Error> {
+    fs::write(path, lined_script_pdf(doc, settings))
+}
@@ -0,0 +1,87 @@
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::serializables::sl_file::SlFileError;
+
+/// Upgrades a whole `.sl` document (as raw JSON) from one format version to the next.
+///
+/// Migrations only ever see the raw JSON, never the current structs,
+/// so they keep working no matter how much those structs change later.
+pub type Migration = fn(Value) -> Result<Value, SlFileError>;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` document to version `n + 2`.
+///
+/// When the format changes, append a migration here; the current format version follows from the length of this list.
+/// Adding a field with a `#[serde(default)]` isn't a format change, since older files still load.
+pub const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2];
+
+pub const fn current_format_version() -> u64 {
+    MIGRATIONS.len() as u64 + 1
+}
+
+/// Runs every migration needed to bring a document of `from_version` up to the current format version.
+pub fn migrate(mut document: Value, from_version: u64) -> Result<Value, SlFileError> {
+    if from_version == 0 || from_version > current_format_version() {
+        return Err(SlFileError::UnknownVersion(from_version));
+    }
+    for migration in &MIGRATIONS[(from_version - 1) as usize..] {
+        document = migration(document)?;
+    }
+    Ok(document)
+}
+
+fn annotations_mut(document: &mut Value) -> Result<&mut Map<String, Value>, SlFileError> {
+    document
+        .get_mut("annotations")
+        .and_then(|a| a.as_object_mut())
+        .ok_or(SlFileError::Corrupt("missing annotations".to_string()))
+}
+
+/// Version 1 stored full copies of each `Tag` inside `Group.tags`.
+/// Version 2 stores `TagID`s instead, like everything else that references tags.
+///
+/// Embedded tags are matched against the document's tags; a tag with no match is added as a new tag.
+fn migrate_v1_to_v2(mut document: Value) -> Result<Value, SlFileError> {
+    let annotations = annotations_mut(&mut document)?;
+
+    let mut tags = match annotations.remove("tags") {
+        Some(Value::Object(tags)) => tags,
+        _ => return Err(SlFileError::Corrupt("missing tags".to_string())),
+    };
+    let mut groups = match annotations.remove("groups") {
+        Some(Value::Object(groups)) => groups,
+        _ => return Err(SlFileError::Corrupt("missing groups".to_string())),
+    };
+
+    for (_, group) in groups.iter_mut() {
+        let Some(embedded_tags) = group.get_mut("tags") else {
+            return Err(SlFileError::Corrupt("group is missing tags".to_string()));
+        };
+        let Value::Array(embedded) = embedded_tags.take() else {
+            return Err(SlFileError::Corrupt("group tags aren't a list".to_string()));
+        };
+
+        let mut tag_ids: Vec<Value> = Vec::new();
+        for embedded_tag in embedded {
+            let existing_id = tags
+                .iter()
+                .find(|(_, tag)| **tag == embedded_tag)
+                .map(|(id, _)| id.clone());
+            let id = match existing_id {
+                Some(id) => id,
+                None => {
+                    let id = Uuid::new_v4().to_string();
+                    tags.insert(id.clone(), embedded_tag);
+                    id
+                }
+            };
+            tag_ids.push(Value::String(id));
+        }
+        *embedded_tags = Value::Array(tag_ids);
+    }
+
+    annotations.insert("tags".to_string(), Value::Object(tags));
+    annotations.insert("groups".to_string(), Value::Object(groups));
+    document["format_version"] = Value::from(2);
+    Ok(document)
+}
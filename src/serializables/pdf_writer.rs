@@ -0,0 +1,403 @@
+use std::collections::BTreeSet;
+
+/// The standard PDF fonts every reader has, so nothing needs embedding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PdfFont {
+    Helvetica,
+    HelveticaBold,
+    Courier,
+    CourierBold,
+}
+impl PdfFont {
+    const ALL: [PdfFont; 4] = [
+        PdfFont::Helvetica,
+        PdfFont::HelveticaBold,
+        PdfFont::Courier,
+        PdfFont::CourierBold,
+    ];
+    fn base_font(&self) -> &'static str {
+        match self {
+            PdfFont::Helvetica => "Helvetica",
+            PdfFont::HelveticaBold => "Helvetica-Bold",
+            PdfFont::Courier => "Courier",
+            PdfFont::CourierBold => "Courier-Bold",
+        }
+    }
+    fn resource_name(&self) -> &'static str {
+        match self {
+            PdfFont::Helvetica => "F1",
+            PdfFont::HelveticaBold => "F2",
+            PdfFont::Courier => "F3",
+            PdfFont::CourierBold => "F4",
+        }
+    }
+    /// A rough average glyph width, as a fraction of the font size. Courier's is exact.
+    pub fn average_char_width(&self) -> f64 {
+        match self {
+            PdfFont::Helvetica => 0.5,
+            PdfFont::HelveticaBold => 0.55,
+            PdfFont::Courier | PdfFont::CourierBold => 0.6,
+        }
+    }
+}
+
+/// An image ready to be embedded as an image XObject.
+///
+/// JPEGs are embedded as they are. PNGs are embedded by passing their compressed data straight through,
+/// which only works for 8-bit, non-interlaced grayscale or RGB images without transparency.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PdfImage {
+    pub width: u32,
+    pub height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    decode_parms: Option<String>,
+    data: Vec<u8>,
+}
+impl PdfImage {
+    pub fn from_jpeg(bytes: &[u8]) -> Option<Self> {
+        if bytes.get(0..2) != Some(&[0xff, 0xd8]) {
+            return None;
+        }
+        let mut index = 2;
+        while index + 9 < bytes.len() {
+            if bytes[index] != 0xff {
+                return None;
+            }
+            let marker = bytes[index + 1];
+            let length = u16::from_be_bytes([bytes[index + 2], bytes[index + 3]]) as usize;
+            // any SOFn marker, except DHT (c4), JPG (c8) and DAC (cc)
+            if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+                let height = u16::from_be_bytes([bytes[index + 5], bytes[index + 6]]) as u32;
+                let width = u16::from_be_bytes([bytes[index + 7], bytes[index + 8]]) as u32;
+                let color_space = match bytes[index + 9] {
+                    1 => "/DeviceGray",
+                    4 => "/DeviceCMYK",
+                    _ => "/DeviceRGB",
+                };
+                return Some(PdfImage {
+                    width: width,
+                    height: height,
+                    color_space: color_space,
+                    filter: "/DCTDecode",
+                    decode_parms: None,
+                    data: bytes.to_vec(),
+                });
+            }
+            index += 2 + length;
+        }
+        None
+    }
+
+    pub fn from_png(bytes: &[u8]) -> Option<Self> {
+        if bytes.get(0..8) != Some(b"\x89PNG\r\n\x1a\n") {
+            return None;
+        }
+        let mut index = 8;
+        let mut header: Option<(u32, u32, u8, u8, u8)> = None;
+        let mut data: Vec<u8> = Vec::new();
+        while index + 8 <= bytes.len() {
+            let length = u32::from_be_bytes(bytes[index..index + 4].try_into().ok()?) as usize;
+            let chunk_type = &bytes[index + 4..index + 8];
+            let chunk = bytes.get(index + 8..index + 8 + length)?;
+            match chunk_type {
+                b"IHDR" if length >= 13 => {
+                    let width = u32::from_be_bytes(chunk[0..4].try_into().ok()?);
+                    let height = u32::from_be_bytes(chunk[4..8].try_into().ok()?);
+                    header = Some((width, height, chunk[8], chunk[9], chunk[12]));
+                }
+                b"IDAT" => data.extend_from_slice(chunk),
+                b"IEND" => break,
+                _ => {}
+            }
+            index += 12 + length;
+        }
+
+        let (width, height, bit_depth, color_type, interlace) = header?;
+        let (color_space, colors) = match color_type {
+            0 => ("/DeviceGray", 1),
+            2 => ("/DeviceRGB", 3),
+            _ => return None,
+        };
+        if bit_depth != 8 || interlace != 0 || data.is_empty() {
+            return None;
+        }
+        Some(PdfImage {
+            width: width,
+            height: height,
+            color_space: color_space,
+            filter: "/FlateDecode",
+            decode_parms: Some(format!(
+                "<< /Predictor 15 /Colors {} /BitsPerComponent 8 /Columns {} >>",
+                colors, width
+            )),
+            data: data,
+        })
+    }
+
+    /// Tries every supported format.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        PdfImage::from_jpeg(bytes).or_else(|| PdfImage::from_png(bytes))
+    }
+}
+
+/// Identifies an image added with `PdfWriter::add_image`, for drawing it on pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PdfImageID(u32);
+
+/// Escapes text for a PDF literal string, in the standard fonts' WinAnsi encoding.
+///
+/// Characters WinAnsi doesn't have become '?'.
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut encoded: Vec<u8> = Vec::new();
+    for c in text.chars() {
+        let byte = match c {
+            '(' | ')' | '\\' => {
+                encoded.push(b'\\');
+                c as u8
+            }
+            '\u{2026}' => 0x85,
+            '\u{2018}' => 0x91,
+            '\u{2019}' => 0x92,
+            '\u{201c}' => 0x93,
+            '\u{201d}' => 0x94,
+            '\u{2022}' => 0x95,
+            '\u{2013}' => 0x96,
+            '\u{2014}' => 0x97,
+            c if (c as u32) >= 0x20 && (c as u32) < 0x7f => c as u8,
+            c if (c as u32) >= 0xa0 && (c as u32) <= 0xff => c as u32 as u8,
+            _ => b'?',
+        };
+        encoded.push(byte);
+    }
+    encoded
+}
+
+/// The drawing operators of one page, in PDF coordinates: points, with the origin at the bottom left.
+#[derive(Clone, Debug, Default)]
+pub struct PdfContent {
+    ops: Vec<u8>,
+    images: BTreeSet<PdfImageID>,
+}
+impl PdfContent {
+    pub fn new() -> Self {
+        PdfContent::default()
+    }
+
+    fn op(&mut self, op: String) {
+        self.ops.extend_from_slice(op.as_bytes());
+        self.ops.push(b'\n');
+    }
+
+    pub fn save_state(&mut self) {
+        self.op("q".to_string());
+    }
+    pub fn restore_state(&mut self) {
+        self.op("Q".to_string());
+    }
+    pub fn stroke_color(&mut self, rgb: (f64, f64, f64)) {
+        self.op(format!("{:.3} {:.3} {:.3} RG", rgb.0, rgb.1, rgb.2));
+    }
+    pub fn fill_color(&mut self, rgb: (f64, f64, f64)) {
+        self.op(format!("{:.3} {:.3} {:.3} rg", rgb.0, rgb.1, rgb.2));
+    }
+    pub fn line_width(&mut self, width: f64) {
+        self.op(format!("{:.2} w", width));
+    }
+    /// Sets a dash pattern; an empty pattern draws solid lines again.
+    pub fn dash(&mut self, pattern: &[f64]) {
+        let pattern: Vec<String> = pattern.iter().map(|n| format!("{:.2}", n)).collect();
+        self.op(format!("[{}] 0 d", pattern.join(" ")));
+    }
+    /// Sets the opacity of strokes and fills, through one of the writer's shared graphics states.
+    pub fn opacity(&mut self, alpha: f64) {
+        let step = (alpha.clamp(0.0, 1.0) * 10.0).round() as u32;
+        self.op(format!("/GS{} gs", step));
+    }
+
+    pub fn line(&mut self, from: (f64, f64), to: (f64, f64)) {
+        self.polyline(&[from, to]);
+    }
+    pub fn polyline(&mut self, points: &[(f64, f64)]) {
+        let Some((first, rest)) = points.split_first() else {
+            return;
+        };
+        let mut op = format!("{:.2} {:.2} m", first.0, first.1);
+        for point in rest {
+            op.push_str(&format!(" {:.2} {:.2} l", point.0, point.1));
+        }
+        op.push_str(" S");
+        self.op(op);
+    }
+    /// A filled polygon, e.g. an arrowhead.
+    pub fn fill_polygon(&mut self, points: &[(f64, f64)]) {
+        let Some((first, rest)) = points.split_first() else {
+            return;
+        };
+        let mut op = format!("{:.2} {:.2} m", first.0, first.1);
+        for point in rest {
+            op.push_str(&format!(" {:.2} {:.2} l", point.0, point.1));
+        }
+        op.push_str(" h f");
+        self.op(op);
+    }
+    pub fn stroke_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.op(format!("{:.2} {:.2} {:.2} {:.2} re S", x, y, width, height));
+    }
+    pub fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.op(format!("{:.2} {:.2} {:.2} {:.2} re f", x, y, width, height));
+    }
+
+    /// Draws a single line of text with its baseline starting at (x, y).
+    pub fn text(&mut self, x: f64, y: f64, font: PdfFont, size: f64, text: &str) {
+        self.ops.extend_from_slice(
+            format!(
+                "BT /{} {:.2} Tf {:.2} {:.2} Td (",
+                font.resource_name(),
+                size,
+                x,
+                y
+            )
+            .as_bytes(),
+        );
+        self.ops.extend_from_slice(&encode_text(text));
+        self.ops.extend_from_slice(b") Tj ET\n");
+    }
+
+    /// Draws an image stretched over the given rectangle.
+    pub fn image(&mut self, image: PdfImageID, x: f64, y: f64, width: f64, height: f64) {
+        self.images.insert(image);
+        self.op(format!(
+            "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q",
+            width, height, x, y, image.0
+        ));
+    }
+}
+
+/// Builds a PDF document page by page.
+///
+/// Only what our exports need: the standard fonts, vector drawing, opacity and JPEG/PNG images.
+pub struct PdfWriter {
+    /// Object `n` is stored at `objects[n - 1]`; the catalog and page tree are filled in by `finish`.
+    objects: Vec<Vec<u8>>,
+    pages: Vec<u32>,
+}
+
+const CATALOG_ID: u32 = 1;
+const PAGE_TREE_ID: u32 = 2;
+
+impl PdfWriter {
+    pub fn new() -> Self {
+        PdfWriter {
+            objects: vec![Vec::new(), Vec::new()],
+            pages: Vec::new(),
+        }
+    }
+
+    fn add_object(&mut self, object: Vec<u8>) -> u32 {
+        self.objects.push(object);
+        self.objects.len() as u32
+    }
+
+    fn stream_object(dictionary: &str, data: &[u8]) -> Vec<u8> {
+        let mut object = format!("<< {} /Length {} >>\nstream\n", dictionary, data.len()).into_bytes();
+        object.extend_from_slice(data);
+        object.extend_from_slice(b"\nendstream");
+        object
+    }
+
+    pub fn add_image(&mut self, image: &PdfImage) -> PdfImageID {
+        let mut dictionary = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8 /Filter {}",
+            image.width, image.height, image.color_space, image.filter
+        );
+        if let Some(decode_parms) = &image.decode_parms {
+            dictionary.push_str(&format!(" /DecodeParms {}", decode_parms));
+        }
+        PdfImageID(self.add_object(PdfWriter::stream_object(&dictionary, &image.data)))
+    }
+
+    pub fn add_page(&mut self, width: f64, height: f64, content: &PdfContent) {
+        let content_id = self.add_object(PdfWriter::stream_object("", &content.ops));
+
+        let fonts: Vec<String> = PdfFont::ALL
+            .iter()
+            .map(|font| {
+                format!(
+                    "/{} << /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                    font.resource_name(),
+                    font.base_font()
+                )
+            })
+            .collect();
+        let graphics_states: Vec<String> = (0..=10)
+            .map(|step| format!("/GS{} << /CA {:.1} /ca {:.1} >>", step, step as f64 / 10.0, step as f64 / 10.0))
+            .collect();
+        let images: Vec<String> = content
+            .images
+            .iter()
+            .map(|image| format!("/Im{} {} 0 R", image.0, image.0))
+            .collect();
+
+        let page = format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] /Contents {} 0 R \
+/Resources << /Font << {} >> /ExtGState << {} >> /XObject << {} >> >> >>",
+            PAGE_TREE_ID,
+            width,
+            height,
+            content_id,
+            fonts.join(" "),
+            graphics_states.join(" "),
+            images.join(" ")
+        );
+        let page_id = self.add_object(page.into_bytes());
+        self.pages.push(page_id);
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.objects[(CATALOG_ID - 1) as usize] =
+            format!("<< /Type /Catalog /Pages {} 0 R >>", PAGE_TREE_ID).into_bytes();
+        let kids: Vec<String> = self.pages.iter().map(|id| format!("{} 0 R", id)).collect();
+        self.objects[(PAGE_TREE_ID - 1) as usize] = format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            self.pages.len()
+        )
+        .into_bytes();
+
+        let mut pdf: Vec<u8> = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets: Vec<usize> = Vec::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref_offset = pdf.len();
+        pdf.extend_from_slice(format!("xref\n0 {}\n", self.objects.len() + 1).as_bytes());
+        pdf.extend_from_slice(b"0000000000 65535 f \n");
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+                self.objects.len() + 1,
+                CATALOG_ID,
+                xref_offset
+            )
+            .as_bytes(),
+        );
+        pdf
+    }
+}
+impl Default for PdfWriter {
+    fn default() -> Self {
+        PdfWriter::new()
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayCoordinate;
+
+/// Use with `#[serde(with = "...::serde_helpers::Coordinate")]` on a `ScreenplayCoordinate` field.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ScreenplayCoordinate")]
+pub struct Coordinate {
+    pub page: usize,
+    pub line: usize,
+    pub element: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CoordinateWrapper(#[serde(with = "Coordinate")] ScreenplayCoordinate);
+
+fn sorted<T: PartialOrd>(mut items: Vec<T>) -> Vec<T> {
+    items.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    items
+}
+
+/// `Shot.sub_compositions` is keyed by `ScreenplayCoordinate`, which can't be a JSON object key,
+/// so it is stored as a list of coordinate/composition pairs instead.
+pub mod sub_compositions {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{sorted, Coordinate};
+    use crate::production::ShotComposition;
+    use screenplay_doc_parser_rs::screenplay_document::ScreenplayCoordinate;
+
+    #[derive(Serialize)]
+    struct SubCompositionRef<'a> {
+        #[serde(with = "Coordinate")]
+        coordinate: ScreenplayCoordinate,
+        composition: &'a ShotComposition,
+    }
+    #[derive(Deserialize)]
+    struct SubComposition {
+        #[serde(with = "Coordinate")]
+        coordinate: ScreenplayCoordinate,
+        composition: ShotComposition,
+    }
+
+    pub fn serialize<S: Serializer>(
+        sub_compositions: &Option<HashMap<ScreenplayCoordinate, ShotComposition>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let Some(map) = sub_compositions else {
+            return serializer.serialize_none();
+        };
+        let coordinates = sorted(map.keys().cloned().collect());
+        let entries: Vec<SubCompositionRef> = coordinates
+            .into_iter()
+            .map(|coordinate| SubCompositionRef {
+                composition: &map[&coordinate],
+                coordinate: coordinate,
+            })
+            .collect();
+        serializer.serialize_some(&entries)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<HashMap<ScreenplayCoordinate, ShotComposition>>, D::Error> {
+        let entries: Option<Vec<SubComposition>> = Option::deserialize(deserializer)?;
+        Ok(entries.map(|entries| {
+            entries
+                .into_iter()
+                .map(|entry| (entry.coordinate, entry.composition))
+                .collect()
+        }))
+    }
+}
+
+/// `TaggedElement.ocurrances`: a set of (start, end) coordinate ranges.
+pub mod coordinate_ranges {
+    use std::collections::HashSet;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{sorted, CoordinateWrapper};
+    use screenplay_doc_parser_rs::screenplay_document::ScreenplayCoordinate;
+
+    pub fn serialize<S: Serializer>(
+        ranges: &HashSet<(ScreenplayCoordinate, ScreenplayCoordinate)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let ranges: Vec<(CoordinateWrapper, CoordinateWrapper)> = sorted(ranges.iter().cloned().collect())
+            .into_iter()
+            .map(|(start, end)| (CoordinateWrapper(start), CoordinateWrapper(end)))
+            .collect();
+        ranges.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashSet<(ScreenplayCoordinate, ScreenplayCoordinate)>, D::Error> {
+        let ranges: Vec<(CoordinateWrapper, CoordinateWrapper)> = Vec::deserialize(deserializer)?;
+        Ok(ranges.into_iter().map(|(start, end)| (start.0, end.0)).collect())
+    }
+}
+
+/// `ShotLine.unfilmed_lines`: an optional set of coordinates.
+pub mod unfilmed_lines {
+    use std::collections::HashSet;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{sorted, CoordinateWrapper};
+    use screenplay_doc_parser_rs::screenplay_document::ScreenplayCoordinate;
+
+    pub fn serialize<S: Serializer>(
+        lines: &Option<HashSet<ScreenplayCoordinate>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let lines: Option<Vec<CoordinateWrapper>> = lines.as_ref().map(|lines| {
+            sorted(lines.iter().cloned().collect())
+                .into_iter()
+                .map(CoordinateWrapper)
+                .collect()
+        });
+        lines.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<HashSet<ScreenplayCoordinate>>, D::Error> {
+        let lines: Option<Vec<CoordinateWrapper>> = Option::deserialize(deserializer)?;
+        Ok(lines.map(|lines| lines.into_iter().map(|c| c.0).collect()))
+    }
+}
@@ -0,0 +1,210 @@
+use std::{fs, path::Path};
+
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayDocument;
+
+use crate::production::Department;
+use crate::reports::{self, ShotList, ShotListEntry};
+use crate::revisions;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShotListColumn {
+    SceneNumber,
+    SceneHeading,
+    ShotNumber,
+    ShotType,
+    SubType,
+    Setup,
+    Lens,
+    /// Every tag on the shot.
+    Tags,
+    /// Only the tags belonging to one department.
+    DepartmentTags(Department),
+    Characters,
+    /// Production page numbers the ShotLine covers, like "3" or "3-3A".
+    PageRange,
+    /// The screenplay text the ShotLine covers.
+    Text,
+    Completed,
+}
+
+pub fn default_columns() -> Vec<ShotListColumn> {
+    vec![
+        ShotListColumn::SceneNumber,
+        ShotListColumn::ShotNumber,
+        ShotListColumn::ShotType,
+        ShotListColumn::SubType,
+        ShotListColumn::Setup,
+        ShotListColumn::Lens,
+        ShotListColumn::Tags,
+        ShotListColumn::PageRange,
+        ShotListColumn::Completed,
+    ]
+}
+
+pub fn department_name(department: &Department) -> String {
+    match department {
+        Department::Other(name) => name.clone(),
+        department => format!("{:?}", department),
+    }
+}
+
+fn column_header(column: &ShotListColumn) -> String {
+    match column {
+        ShotListColumn::SceneNumber => "Scene".to_string(),
+        ShotListColumn::SceneHeading => "Scene Heading".to_string(),
+        ShotListColumn::ShotNumber => "Shot".to_string(),
+        ShotListColumn::ShotType => "Shot Type".to_string(),
+        ShotListColumn::SubType => "Subtype".to_string(),
+        ShotListColumn::Setup => "Setup".to_string(),
+        ShotListColumn::Lens => "Lens".to_string(),
+        ShotListColumn::Tags => "Tags".to_string(),
+        ShotListColumn::DepartmentTags(department) => format!("{} Tags", department_name(department)),
+        ShotListColumn::Characters => "Characters".to_string(),
+        ShotListColumn::PageRange => "Pages".to_string(),
+        ShotListColumn::Text => "Text".to_string(),
+        ShotListColumn::Completed => "Completed".to_string(),
+    }
+}
+
+/// Quotes a field if it contains a comma, quote, line break or surrounding whitespace (RFC 4180).
+pub fn escape_field(field: &str) -> String {
+    let needs_quotes = field.contains([',', '"', '\n', '\r'])
+        || field.starts_with(char::is_whitespace)
+        || field.ends_with(char::is_whitespace);
+    if !needs_quotes {
+        return field.to_string();
+    }
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+fn line_text(screenplay: &ScreenplayDocument, page: usize, line: usize) -> Option<String> {
+    let line = screenplay.pages.get(page)?.lines.get(line)?;
+    Some(reports::get_line_text(line))
+}
+
+fn column_value(
+    screenplay: &ScreenplayDocument,
+    page_labels: &[String],
+    entry: &ShotListEntry,
+    column: &ShotListColumn,
+) -> String {
+    let composition = entry.composition;
+    match column {
+        ShotListColumn::SceneNumber => entry
+            .scene
+            .and_then(|scene| scene.number.as_ref())
+            .map(|number| number.0.clone())
+            .unwrap_or_default(),
+        ShotListColumn::SceneHeading => entry
+            .scene
+            .and_then(|scene| line_text(screenplay, scene.start.page, scene.start.line))
+            .unwrap_or_default(),
+        ShotListColumn::ShotNumber => entry
+            .shot_number
+            .map(|number| number.0.clone())
+            .unwrap_or_default(),
+        ShotListColumn::ShotType => format!("{:?}", composition.shot_type),
+        ShotListColumn::SubType => composition
+            .subtype
+            .as_ref()
+            .map(|subtype| format!("{:?}", subtype))
+            .unwrap_or_default(),
+        ShotListColumn::Setup => composition
+            .setup
+            .as_ref()
+            .map(|setup| setup.id.clone())
+            .unwrap_or_default(),
+        ShotListColumn::Lens => composition
+            .camera_metadata
+            .as_ref()
+            .map(|camera| format!("{}mm", camera.lens_mm()))
+            .unwrap_or_default(),
+        ShotListColumn::Tags => {
+            let tags: Vec<&str> = entry.tags.iter().map(|tag| tag.string.as_str()).collect();
+            tags.join(", ")
+        }
+        ShotListColumn::DepartmentTags(department) => {
+            let tags: Vec<&str> = entry
+                .tags
+                .iter()
+                .filter(|tag| tag.departments.contains(department))
+                .map(|tag| tag.string.as_str())
+                .collect();
+            tags.join(", ")
+        }
+        ShotListColumn::Characters => {
+            let names: Vec<&str> = entry.characters.iter().map(|c| c.name.as_str()).collect();
+            names.join(", ")
+        }
+        ShotListColumn::PageRange => {
+            let Some(shotline) = &entry.shot.shotline else {
+                return String::new();
+            };
+            let start = revisions::page_label_at(page_labels, shotline.start.page);
+            if shotline.start.page == shotline.end.page {
+                return start;
+            }
+            format!(
+                "{}-{}",
+                start,
+                revisions::page_label_at(page_labels, shotline.end.page)
+            )
+        }
+        ShotListColumn::Text => {
+            let Some(shotline) = &entry.shot.shotline else {
+                return String::new();
+            };
+            let lines: Vec<String> = reports::get_lines_in_range(screenplay, &shotline.start, &shotline.end)
+                .into_iter()
+                .map(|(_, line)| reports::get_line_text(line))
+                .collect();
+            lines.join("\n")
+        }
+        ShotListColumn::Completed => {
+            if entry.shot.completed {
+                "yes".to_string()
+            } else {
+                "no".to_string()
+            }
+        }
+    }
+}
+
+/// Writes the shot list as CSV, one row per entry, with a header row naming the columns.
+///
+/// Rows end in CRLF, as most spreadsheet programs expect.
+/// Page ranges go by `page_labels`, from `ShotlinerDoc::production_page_labels`.
+pub fn to_string(
+    screenplay: &ScreenplayDocument,
+    page_labels: &[String],
+    shotlist: &ShotList,
+    columns: &[ShotListColumn],
+) -> String {
+    let mut csv = String::new();
+    let header: Vec<String> = columns
+        .iter()
+        .map(|column| escape_field(&column_header(column)))
+        .collect();
+    csv.push_str(&header.join(","));
+    csv.push_str("\r\n");
+
+    for entry in &shotlist.entries {
+        let row: Vec<String> = columns
+            .iter()
+            .map(|column| escape_field(&column_value(screenplay, page_labels, entry, column)))
+            .collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+pub fn save_to_file(
+    screenplay: &ScreenplayDocument,
+    page_labels: &[String],
+    shotlist: &ShotList,
+    columns: &[ShotListColumn],
+    path: impl AsRef<Path>,
+) -> Result<(), std::io::Error> {
+    fs::write(path, to_string(screenplay, page_labels, shotlist, columns))
+}
@@ -0,0 +1,280 @@
+use std::{
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayDocument;
+
+use crate::serializables::migrations;
+use crate::revisions::Revisions;
+use crate::shot_numbering::ShotNumbering;
+use crate::smart_coordinate::text_hash;
+use crate::shotliner_document::{AnnotationMap, ShotlinerDoc};
+
+pub const SL_FILE_EXTENSION: &str = "sl";
+pub const FORMAT_NAME: &str = "shotliner";
+/// Bumped by appending to `migrations::MIGRATIONS` whenever existing saved data changes shape.
+///
+/// New fields marked `#[serde(default)]` don't need a new version: older files simply load without them.
+pub const CURRENT_FORMAT_VERSION: u64 = migrations::current_format_version();
+
+#[derive(Debug)]
+pub enum SlFileError {
+    Io(std::io::Error),
+    /// The file isn't valid JSON, or doesn't match the layout of its format version.
+    Corrupt(String),
+    /// The file is a ShotLiner document, but from a newer (or otherwise unknown) format version.
+    UnknownVersion(u64),
+    NotAShotlinerFile,
+    /// The document has no screenplay filepath to reference when saving.
+    MissingScreenplayReference,
+    /// The referenced screenplay PDF couldn't be loaded.
+    Screenplay(String),
+    /// The referenced screenplay was loaded, but it isn't the same screenplay the document was saved against.
+    ScreenplayMismatch,
+}
+impl fmt::Display for SlFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlFileError::Io(e) => write!(f, "I/O error: {}", e),
+            SlFileError::Corrupt(e) => write!(f, "corrupt ShotLiner document: {}", e),
+            SlFileError::UnknownVersion(v) => {
+                write!(f, "unknown ShotLiner document format version: {}", v)
+            }
+            SlFileError::NotAShotlinerFile => write!(f, "not a ShotLiner document"),
+            SlFileError::MissingScreenplayReference => {
+                write!(f, "document has no screenplay filepath to reference")
+            }
+            SlFileError::Screenplay(e) => write!(f, "couldn't load referenced screenplay: {}", e),
+            SlFileError::ScreenplayMismatch => {
+                write!(f, "referenced screenplay doesn't match the one this document was saved with")
+            }
+        }
+    }
+}
+impl std::error::Error for SlFileError {}
+impl From<std::io::Error> for SlFileError {
+    fn from(e: std::io::Error) -> Self {
+        SlFileError::Io(e)
+    }
+}
+
+/// The screenplay isn't embedded in a `.sl` file; it's referenced by filepath,
+/// and re-parsed on load.
+///
+/// The page count and fingerprint are used to detect when the referenced file
+/// has been replaced by a different screenplay.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScreenplayReference {
+    pub filepath: String,
+    pub page_count: usize,
+    pub fingerprint: String,
+}
+impl ScreenplayReference {
+    pub fn new(filepath: String, screenplay: &ScreenplayDocument) -> Self {
+        ScreenplayReference {
+            filepath: filepath,
+            page_count: screenplay.pages.len(),
+            fingerprint: screenplay_fingerprint(screenplay),
+        }
+    }
+
+    pub fn matches(&self, screenplay: &ScreenplayDocument) -> bool {
+        self.page_count == screenplay.pages.len()
+            && self.fingerprint == screenplay_fingerprint(screenplay)
+    }
+}
+
+#[derive(Serialize)]
+struct SlFileRef<'a> {
+    format: &'a str,
+    format_version: u64,
+    screenplay: ScreenplayReference,
+    annotations: &'a AnnotationMap,
+    revisions: &'a Revisions,
+    shot_numbering: &'a ShotNumbering,
+}
+
+#[derive(Deserialize)]
+struct SlFile {
+    screenplay: ScreenplayReference,
+    annotations: AnnotationMap,
+    #[serde(default)]
+    revisions: Revisions,
+    #[serde(default)]
+    shot_numbering: ShotNumbering,
+}
+
+/// A `text_hash` of all the text in the screenplay, page by page and line by line.
+///
+/// This is deliberately independent of element types, so re-parsing the same PDF
+/// with different indentation settings still produces the same fingerprint.
+pub fn screenplay_fingerprint(screenplay: &ScreenplayDocument) -> String {
+    let mut text = String::new();
+    for page in &screenplay.pages {
+        text.push('\x0c');
+        for line in &page.lines {
+            text.push('\n');
+            for element in &line.text_elements {
+                text.push_str(&element.text);
+                text.push(' ');
+            }
+        }
+    }
+    format!("{:016x}", text_hash(&text))
+}
+
+/// Serializes a ShotlinerDoc to the `.sl` JSON format, referencing the screenplay by its filepath as given.
+pub fn to_string(doc: &ShotlinerDoc) -> Result<String, SlFileError> {
+    let Some(filepath) = &doc.screenplay_filepath else {
+        return Err(SlFileError::MissingScreenplayReference);
+    };
+    to_string_with_filepath(doc, filepath.clone())
+}
+
+fn to_string_with_filepath(doc: &ShotlinerDoc, filepath: String) -> Result<String, SlFileError> {
+    let file = SlFileRef {
+        format: FORMAT_NAME,
+        format_version: CURRENT_FORMAT_VERSION,
+        screenplay: ScreenplayReference::new(filepath, &doc.screenplay),
+        annotations: &doc.annotation_map,
+        revisions: &doc.revisions,
+        shot_numbering: &doc.shot_numbering,
+    };
+    serde_json::to_string_pretty(&file).map_err(|e| SlFileError::Corrupt(e.to_string()))
+}
+
+/// Parses a `.sl` document into its screenplay reference, AnnotationMap, revision history
+/// and shot numbering settings, without loading the screenplay itself.
+///
+/// Documents from older format versions are migrated to the current one first.
+pub fn from_str(
+    contents: &str,
+) -> Result<(ScreenplayReference, AnnotationMap, Revisions, ShotNumbering), SlFileError> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(contents).map_err(|e| SlFileError::Corrupt(e.to_string()))?;
+
+    if value.get("format").and_then(|f| f.as_str()) != Some(FORMAT_NAME) {
+        return Err(SlFileError::NotAShotlinerFile);
+    }
+    let Some(version) = value.get("format_version").and_then(|v| v.as_u64()) else {
+        return Err(SlFileError::Corrupt("missing format_version".to_string()));
+    };
+    if version != CURRENT_FORMAT_VERSION {
+        value = migrations::migrate(value, version)?;
+    }
+
+    let file: SlFile =
+        serde_json::from_value(value).map_err(|e| SlFileError::Corrupt(e.to_string()))?;
+    Ok((
+        file.screenplay,
+        file.annotations,
+        file.revisions,
+        file.shot_numbering,
+    ))
+}
+
+/// An absolute version of `path`, with symlinks and `..` resolved if it exists.
+fn absolute_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or(path.to_path_buf())
+}
+
+/// The directory a `.sl` file is in, which relative screenplay filepaths start from.
+fn sl_file_directory(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// `filepath` relative to the directory `base`, going up with `..` where needed. Both must be absolute.
+///
+/// `None` if they don't share a root, e.g. on different Windows drives.
+fn relative_path(filepath: &Path, base: &Path) -> Option<PathBuf> {
+    let file_components: Vec<Component> = filepath.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+    let common = file_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return None;
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &file_components[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+/// Saves a ShotlinerDoc as a `.sl` file.
+///
+/// The screenplay is referenced relative to the `.sl` file, so the two can be moved together.
+pub fn save_to_file(doc: &ShotlinerDoc, path: impl AsRef<Path>) -> Result<(), SlFileError> {
+    let Some(filepath) = &doc.screenplay_filepath else {
+        return Err(SlFileError::MissingScreenplayReference);
+    };
+    let screenplay_path = absolute_path(Path::new(filepath));
+    let directory = absolute_path(&sl_file_directory(path.as_ref()));
+    let stored = relative_path(&screenplay_path, &directory).unwrap_or(screenplay_path);
+    let contents = to_string_with_filepath(doc, stored.to_string_lossy().into_owned())?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Where the screenplay a `.sl` file references actually is: relative filepaths start from the `.sl` file's directory.
+///
+/// Files saved before that was the case stored the path as given, relative to the working directory;
+/// that's used if nothing is found next to the `.sl` file.
+fn resolve_screenplay_filepath(sl_path: &Path, filepath: &str) -> String {
+    if Path::new(filepath).is_absolute() {
+        return filepath.to_string();
+    }
+    let beside = sl_file_directory(sl_path).join(filepath);
+    if !beside.exists() && Path::new(filepath).exists() {
+        return filepath.to_string();
+    }
+    absolute_path(&beside).to_string_lossy().into_owned()
+}
+
+/// Loads a `.sl` document, re-parsing the referenced screenplay PDF with the default settings.
+pub fn load_from_file(path: impl AsRef<Path>) -> Result<ShotlinerDoc, SlFileError> {
+    load_from_file_with(path, |filepath| {
+        ShotlinerDoc::from_pdf(filepath)
+            .map(|doc| doc.screenplay)
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Loads a `.sl` document, using `load_screenplay` to turn the referenced filepath into a ScreenplayDocument.
+/// Relative filepaths are resolved against the `.sl` file's directory first.
+///
+/// Fails with `SlFileError::ScreenplayMismatch` if the loaded screenplay isn't the one the document was saved with.
+pub fn load_from_file_with(
+    path: impl AsRef<Path>,
+    load_screenplay: impl FnOnce(&str) -> Result<ScreenplayDocument, String>,
+) -> Result<ShotlinerDoc, SlFileError> {
+    let contents = fs::read_to_string(&path)?;
+    let (reference, annotation_map, revisions, shot_numbering) = from_str(&contents)?;
+
+    let filepath = resolve_screenplay_filepath(path.as_ref(), &reference.filepath);
+    let screenplay = load_screenplay(&filepath).map_err(SlFileError::Screenplay)?;
+    if !reference.matches(&screenplay) {
+        return Err(SlFileError::ScreenplayMismatch);
+    }
+
+    let mut doc = ShotlinerDoc::new(screenplay);
+    doc.screenplay_filepath = Some(filepath);
+    doc.revisions = revisions;
+    doc.shot_numbering = shot_numbering;
+    doc.set_annotation_map(annotation_map);
+    Ok(doc)
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayDocument;
+
+use crate::multimedia::MediaType;
+use crate::reports::{ShotList, ShotListEntry};
+use crate::serializables::kra_storyboard::{self, escape_xml};
+use crate::serializables::pdf_writer::{PdfContent, PdfFont, PdfImage, PdfImageID, PdfWriter};
+use crate::serializables::wrap_lines;
+
+/// Page and grid layout of the storyboard sheets, in points.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SheetSettings {
+    pub page_width: f64,
+    pub page_height: f64,
+    pub margin: f64,
+    pub gutter: f64,
+    pub columns: usize,
+    pub rows: usize,
+    /// Width over height of each frame.
+    pub frame_aspect: f64,
+    pub label_font_size: f64,
+    pub excerpt_font_size: f64,
+}
+impl SheetSettings {
+    /// US Letter, landscape, 3x2 frames of 16:9.
+    pub fn us_letter_default() -> Self {
+        SheetSettings {
+            page_width: 792.0,
+            page_height: 612.0,
+            margin: 36.0,
+            gutter: 18.0,
+            columns: 3,
+            rows: 2,
+            frame_aspect: 16.0 / 9.0,
+            label_font_size: 9.0,
+            excerpt_font_size: 7.0,
+        }
+    }
+    fn frames_per_page(&self) -> usize {
+        (self.columns * self.rows).max(1)
+    }
+}
+impl Default for SheetSettings {
+    fn default() -> Self {
+        SheetSettings::us_letter_default()
+    }
+}
+
+/// Something drawn on a sheet. Coordinates are in points from the top left of the page,
+/// and text is positioned by its baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SheetItem {
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        stroke_width: f64,
+    },
+    Text {
+        x: f64,
+        y: f64,
+        font: PdfFont,
+        size: f64,
+        text: String,
+    },
+    /// Scaled to fit inside the rectangle, keeping its aspect ratio.
+    Image {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        filepath: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SheetPage {
+    pub width: f64,
+    pub height: f64,
+    pub items: Vec<SheetItem>,
+}
+
+const HEADER_HEIGHT: f64 = 20.0;
+
+/// "50mm, Setup A"
+fn camera_line(entry: &ShotListEntry) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    if let Some(camera) = &entry.composition.camera_metadata {
+        parts.push(format!("{}mm", camera.lens_mm()));
+    }
+    if let Some(setup) = &entry.composition.setup {
+        parts.push(format!("Setup {}", setup.id));
+    }
+    parts.join(", ")
+}
+
+fn layout_frame(
+    screenplay: &ScreenplayDocument,
+    entry: &ShotListEntry,
+    settings: &SheetSettings,
+    cell: (f64, f64, f64, f64),
+    items: &mut Vec<SheetItem>,
+) {
+    let (x, y, width, height) = cell;
+    let frame_height = (width / settings.frame_aspect).min(height * 0.6);
+    items.push(SheetItem::Rect {
+        x: x,
+        y: y,
+        width: width,
+        height: frame_height,
+        stroke_width: 1.5,
+    });
+    let image = entry
+        .composition
+        .media
+        .iter()
+        .find(|media| *media.media_type() == MediaType::Image);
+    if let Some(image) = image {
+        items.push(SheetItem::Image {
+            x: x + 1.0,
+            y: y + 1.0,
+            width: width - 2.0,
+            height: frame_height - 2.0,
+            filepath: image.filepath().to_string(),
+        });
+    }
+
+    let label_size = settings.label_font_size;
+    let mut baseline = y + frame_height + label_size * 1.4;
+    items.push(SheetItem::Text {
+        x: x,
+        y: baseline,
+        font: PdfFont::HelveticaBold,
+        size: label_size,
+        text: kra_storyboard::panel_label(screenplay, entry),
+    });
+    let camera = camera_line(entry);
+    if !camera.is_empty() {
+        baseline += label_size * 1.2;
+        items.push(SheetItem::Text {
+            x: x,
+            y: baseline,
+            font: PdfFont::Helvetica,
+            size: label_size,
+            text: camera,
+        });
+    }
+
+    let excerpt_size = settings.excerpt_font_size;
+    let line_height = excerpt_size * 1.2;
+    let max_chars = (width / (excerpt_size * PdfFont::Courier.average_char_width())) as usize;
+    let max_lines = ((y + height - baseline) / line_height).floor().max(0.0) as usize;
+    let mut lines = wrap_lines(&kra_storyboard::panel_text(screenplay, entry), max_chars.max(1));
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push_str(" \u{2026}");
+        }
+    }
+    baseline += label_size * 0.4;
+    for line in lines {
+        baseline += line_height;
+        items.push(SheetItem::Text {
+            x: x,
+            y: baseline,
+            font: PdfFont::Courier,
+            size: excerpt_size,
+            text: line,
+        });
+    }
+}
+
+/// Lays out one frame per shot, in shot list order, `columns` x `rows` frames to a page.
+pub fn layout_sheets(
+    screenplay: &ScreenplayDocument,
+    shotlist: &ShotList,
+    settings: &SheetSettings,
+) -> Vec<SheetPage> {
+    let per_page = settings.frames_per_page();
+    let page_count = shotlist.entries.len().div_ceil(per_page);
+    let columns = settings.columns.max(1) as f64;
+    let rows = settings.rows.max(1) as f64;
+    let cell_width =
+        (settings.page_width - 2.0 * settings.margin - (columns - 1.0) * settings.gutter) / columns;
+    let cell_height = (settings.page_height - 2.0 * settings.margin - HEADER_HEIGHT - (rows - 1.0) * settings.gutter)
+        / rows;
+
+    let mut pages: Vec<SheetPage> = Vec::new();
+    for (page_index, entries) in shotlist.entries.chunks(per_page).enumerate() {
+        let mut items: Vec<SheetItem> = vec![SheetItem::Text {
+            x: settings.margin,
+            y: settings.margin + settings.label_font_size,
+            font: PdfFont::HelveticaBold,
+            size: settings.label_font_size,
+            text: format!("Storyboard - page {} of {}", page_index + 1, page_count),
+        }];
+        for (index, entry) in entries.iter().enumerate() {
+            let column = (index % settings.columns.max(1)) as f64;
+            let row = (index / settings.columns.max(1)) as f64;
+            let cell = (
+                settings.margin + column * (cell_width + settings.gutter),
+                settings.margin + HEADER_HEIGHT + row * (cell_height + settings.gutter),
+                cell_width,
+                cell_height,
+            );
+            layout_frame(screenplay, entry, settings, cell, &mut items);
+        }
+        pages.push(SheetPage {
+            width: settings.page_width,
+            height: settings.page_height,
+            items: items,
+        });
+    }
+    pages
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn image_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if bytes.len() > 12 && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn svg_font_attributes(font: PdfFont) -> &'static str {
+    match font {
+        PdfFont::Helvetica => "font-family=\"Helvetica, Arial, sans-serif\"",
+        PdfFont::HelveticaBold => "font-family=\"Helvetica, Arial, sans-serif\" font-weight=\"bold\"",
+        PdfFont::Courier => "font-family=\"Courier Prime, Courier New, monospace\"",
+        PdfFont::CourierBold => "font-family=\"Courier Prime, Courier New, monospace\" font-weight=\"bold\"",
+    }
+}
+
+/// Renders one sheet as a standalone SVG. Images are embedded as data URIs;
+/// images that can't be read are left out.
+pub fn page_to_svg(page: &SheetPage) -> String {
+    let mut svg = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}pt\" height=\"{h}pt\" viewBox=\"0 0 {w} {h}\">\n\
+<rect width=\"{w}\" height=\"{h}\" fill=\"#ffffff\"/>\n",
+        w = page.width,
+        h = page.height
+    );
+    for item in &page.items {
+        match item {
+            SheetItem::Rect { x, y, width, height, stroke_width } => svg.push_str(&format!(
+                "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"none\" stroke=\"#000000\" stroke-width=\"{}\"/>\n",
+                x, y, width, height, stroke_width
+            )),
+            SheetItem::Text { x, y, font, size, text } => svg.push_str(&format!(
+                "<text x=\"{:.2}\" y=\"{:.2}\" {} font-size=\"{}\" xml:space=\"preserve\">{}</text>\n",
+                x,
+                y,
+                svg_font_attributes(*font),
+                size,
+                escape_xml(text)
+            )),
+            SheetItem::Image { x, y, width, height, filepath } => {
+                let Ok(bytes) = fs::read(filepath) else {
+                    continue;
+                };
+                let Some(mime_type) = image_mime_type(&bytes) else {
+                    continue;
+                };
+                svg.push_str(&format!(
+                    "<image x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" preserveAspectRatio=\"xMidYMid meet\" href=\"data:{};base64,{}\"/>\n",
+                    x,
+                    y,
+                    width,
+                    height,
+                    mime_type,
+                    base64(&bytes)
+                ));
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Renders every sheet into one PDF. Each image file is embedded once, however many frames use it;
+/// images the PDF writer can't embed are left out.
+pub fn sheets_to_pdf(pages: &[SheetPage]) -> Vec<u8> {
+    let mut pdf = PdfWriter::new();
+    let mut images: HashMap<String, Option<(PdfImageID, u32, u32)>> = HashMap::new();
+
+    for page in pages {
+        let mut content = PdfContent::new();
+        content.stroke_color((0.0, 0.0, 0.0));
+        content.fill_color((0.0, 0.0, 0.0));
+        for item in &page.items {
+            match item {
+                SheetItem::Rect { x, y, width, height, stroke_width } => {
+                    content.line_width(*stroke_width);
+                    content.stroke_rect(*x, page.height - y - height, *width, *height);
+                }
+                SheetItem::Text { x, y, font, size, text } => {
+                    content.text(*x, page.height - y, *font, *size, text);
+                }
+                SheetItem::Image { x, y, width, height, filepath } => {
+                    let image = images.entry(filepath.clone()).or_insert_with(|| {
+                        let image = PdfImage::from_bytes(&fs::read(filepath).ok()?)?;
+                        Some((pdf.add_image(&image), image.width, image.height))
+                    });
+                    let Some((id, image_width, image_height)) = *image else {
+                        continue;
+                    };
+                    let scale = (width / image_width as f64).min(height / image_height as f64);
+                    let (drawn_width, drawn_height) =
+                        (image_width as f64 * scale, image_height as f64 * scale);
+                    content.image(
+                        id,
+                        x + (width - drawn_width) / 2.0,
+                        page.height - y - height + (height - drawn_height) / 2.0,
+                        drawn_width,
+                        drawn_height,
+                    );
+                }
+            }
+        }
+        pdf.add_page(page.width, page.height, &content);
+    }
+    pdf.finish()
+}
+
+/// Writes one SVG per sheet next to `path`, numbered like "storyboard-01.svg", and returns their paths.
+pub fn save_svgs(pages: &[SheetPage], path: impl AsRef<Path>) -> Result<Vec<PathBuf>, std::io::Error> {
+    let path = path.as_ref();
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or("storyboard".to_string());
+    let mut written: Vec<PathBuf> = Vec::new();
+    for (index, page) in pages.iter().enumerate() {
+        let page_path = path.with_file_name(format!("{}-{:02}.svg", stem, index + 1));
+        fs::write(&page_path, page_to_svg(page))?;
+        written.push(page_path);
+    }
+    Ok(written)
+}
+
+pub fn save_pdf(pages: &[SheetPage], path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+    fs::write(path, sheets_to_pdf(pages))
+}
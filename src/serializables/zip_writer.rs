@@ -0,0 +1,122 @@
+use std::io;
+
+/// A minimal writer for uncompressed ("stored") zip archives.
+///
+/// This is all the container formats we export need; `.kra` files even require
+/// their first entry, `mimetype`, to be stored uncompressed.
+///
+/// ZIP64 isn't supported, so archives are limited to 65535 entries and 4 GiB;
+/// going past either is an error rather than a corrupt archive.
+pub struct ZipWriter {
+    buffer: Vec<u8>,
+    central_directory: Vec<u8>,
+    entry_count: u16,
+}
+
+fn too_large(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("zip archives without ZIP64 can't hold {}", what),
+    )
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffffffff;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    !crc
+}
+
+// 1980-01-01 00:00, the earliest MS-DOS timestamp; keeps exports reproducible.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        ZipWriter {
+            buffer: Vec::new(),
+            central_directory: Vec::new(),
+            entry_count: 0,
+        }
+    }
+
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) -> Result<(), io::Error> {
+        let Some(entry_count) = self.entry_count.checked_add(1) else {
+            return Err(too_large("more than 65535 entries"));
+        };
+        let crc = crc32(contents);
+        let size = u32::try_from(contents.len()).map_err(|_| too_large("an entry over 4 GiB"))?;
+        let offset =
+            u32::try_from(self.buffer.len()).map_err(|_| too_large("an archive over 4 GiB"))?;
+        let name = name.as_bytes();
+        let name_length =
+            u16::try_from(name.len()).map_err(|_| too_large("an entry name over 64 KiB"))?;
+
+        // local file header
+        self.buffer.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        self.buffer.extend_from_slice(&10u16.to_le_bytes()); // version needed to extract
+        self.buffer.extend_from_slice(&0u16.to_le_bytes()); // flags
+        self.buffer.extend_from_slice(&0u16.to_le_bytes()); // compression: stored
+        self.buffer.extend_from_slice(&DOS_TIME.to_le_bytes());
+        self.buffer.extend_from_slice(&DOS_DATE.to_le_bytes());
+        self.buffer.extend_from_slice(&crc.to_le_bytes());
+        self.buffer.extend_from_slice(&size.to_le_bytes()); // compressed size
+        self.buffer.extend_from_slice(&size.to_le_bytes()); // uncompressed size
+        self.buffer.extend_from_slice(&name_length.to_le_bytes());
+        self.buffer.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        self.buffer.extend_from_slice(name);
+        self.buffer.extend_from_slice(contents);
+
+        // central directory header
+        let cd = &mut self.central_directory;
+        cd.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        cd.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        cd.extend_from_slice(&10u16.to_le_bytes()); // version needed to extract
+        cd.extend_from_slice(&0u16.to_le_bytes()); // flags
+        cd.extend_from_slice(&0u16.to_le_bytes()); // compression: stored
+        cd.extend_from_slice(&DOS_TIME.to_le_bytes());
+        cd.extend_from_slice(&DOS_DATE.to_le_bytes());
+        cd.extend_from_slice(&crc.to_le_bytes());
+        cd.extend_from_slice(&size.to_le_bytes());
+        cd.extend_from_slice(&size.to_le_bytes());
+        cd.extend_from_slice(&name_length.to_le_bytes());
+        cd.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        cd.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        cd.extend_from_slice(&0u16.to_le_bytes()); // disk number start
+        cd.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        cd.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        cd.extend_from_slice(&offset.to_le_bytes());
+        cd.extend_from_slice(name);
+
+        self.entry_count = entry_count;
+        Ok(())
+    }
+
+    /// Appends the central directory and returns the finished archive.
+    pub fn finish(mut self) -> Result<Vec<u8>, io::Error> {
+        let too_big = |_| too_large("an archive over 4 GiB");
+        let cd_offset = u32::try_from(self.buffer.len()).map_err(too_big)?;
+        let cd_size = u32::try_from(self.central_directory.len()).map_err(too_big)?;
+        self.buffer.extend_from_slice(&self.central_directory);
+
+        self.buffer.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        self.buffer.extend_from_slice(&0u16.to_le_bytes()); // this disk
+        self.buffer.extend_from_slice(&0u16.to_le_bytes()); // disk with the central directory
+        self.buffer.extend_from_slice(&self.entry_count.to_le_bytes());
+        self.buffer.extend_from_slice(&self.entry_count.to_le_bytes());
+        self.buffer.extend_from_slice(&cd_size.to_le_bytes());
+        self.buffer.extend_from_slice(&cd_offset.to_le_bytes());
+        self.buffer.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        Ok(self.buffer)
+    }
+}
+impl Default for ZipWriter {
+    fn default() -> Self {
+        ZipWriter::new()
+    }
+}
@@ -0,0 +1,655 @@
+use std::fmt;
+
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayCoordinate;
+
+use crate::commands::{Command, CommandHistoryStatus};
+use crate::production::{self, Department, ShotSubType, ShotType};
+use crate::reports;
+use crate::serializables::{breakdown_script, kra_storyboard, lined_script, shot_list_csv, storyboard_sheets};
+use crate::shotliner_document::{ShotLine, ShotlinerDoc, Tag, TagID};
+
+pub const HELP_TEXT: &str = "\
+Coordinates are raw page and line indices, as shown by `go page`.
+
+  add shotline <page> <line> <page> <line>
+  remove shotline <n>
+  list shotlines
+  modify shotline <n> type <extremewide|wide|medium|closeup|extremecloseup|insert|other>
+  modify shotline <n> subtype <trucking|moving|dolly|whippan|panning|other|none>
+  modify shotline <n> number <shot number>
+  modify shotline <n> tag <add|remove> <tag n>
+  add tag \"<text>\" <department>...
+  remove tag <n>
+  list tags
+  go page <page>
+  save doc <path.sl>
+  export <csv|kra|sheets|lined|breakdown> <path>
+  undo
+  redo
+  help
+  quit";
+
+#[derive(Debug, PartialEq)]
+pub enum ShellError {
+    /// The line couldn't be parsed as a command.
+    Parse(String),
+    /// The command was understood, but couldn't be applied to the document.
+    Failed(String),
+}
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShellError::Parse(e) => write!(f, "parse error: {}", e),
+            ShellError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for ShellError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Kra,
+    /// Storyboard sheets; a PDF, or one SVG per page, depending on the filepath's extension.
+    Sheets,
+    /// The lined script PDF.
+    Lined,
+    /// The breakdown-colored script PDF.
+    Breakdown,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShotlineChange {
+    ShotType(ShotType),
+    SubType(Option<ShotSubType>),
+    Number(String),
+    AddTag(usize),
+    RemoveTag(usize),
+}
+
+/// One parsed line of the command language.
+///
+/// Shotlines and tags are referred to by their index in `list shotlines` and `list tags`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShellCommand {
+    AddShotline(ScreenplayCoordinate, ScreenplayCoordinate),
+    RemoveShotline(usize),
+    ListShotlines,
+    ModifyShotline(usize, ShotlineChange),
+    AddTag(String, Vec<Department>),
+    RemoveTag(usize),
+    ListTags,
+    GoPage(usize),
+    SaveDoc(String),
+    Export(ExportFormat, String),
+    Undo,
+    Redo,
+    Help,
+    Quit,
+}
+
+/// Splits a line into whitespace-separated words, keeping "double quoted" text together.
+/// Anything after an unquoted `#` is a comment.
+fn tokenize(line: &str) -> Result<Vec<String>, ShellError> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_token = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_token = true;
+            }
+            '#' if !in_quotes => break,
+            c if c.is_whitespace() && !in_quotes => {
+                if has_token {
+                    tokens.push(std::mem::take(&mut current));
+                    has_token = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_token = true;
+            }
+        }
+    }
+    if in_quotes {
+        return Err(ShellError::Parse("unterminated quote".to_string()));
+    }
+    if has_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_index(token: Option<&String>, what: &str) -> Result<usize, ShellError> {
+    let Some(token) = token else {
+        return Err(ShellError::Parse(format!("expected {}", what)));
+    };
+    token
+        .parse::<usize>()
+        .map_err(|_| ShellError::Parse(format!("expected {}, found '{}'", what, token)))
+}
+
+fn parse_shot_type(token: &str) -> Option<ShotType> {
+    match token.to_lowercase().as_str() {
+        "extremewide" | "ews" | "ew" => Some(ShotType::ExtremeWide),
+        "wide" | "ws" => Some(ShotType::Wide),
+        "medium" | "ms" => Some(ShotType::Medium),
+        "closeup" | "cu" => Some(ShotType::CloseUp),
+        "extremecloseup" | "ecu" => Some(ShotType::ExtremeCloseUp),
+        "insert" | "ins" => Some(ShotType::Insert),
+        "other" => Some(ShotType::Other),
+        _ => None,
+    }
+}
+
+fn parse_shot_subtype(token: &str) -> Option<Option<ShotSubType>> {
+    match token.to_lowercase().as_str() {
+        "trucking" => Some(Some(ShotSubType::Trucking)),
+        "moving" => Some(Some(ShotSubType::Moving)),
+        "dolly" => Some(Some(ShotSubType::Dolly)),
+        "whippan" => Some(Some(ShotSubType::WhipPan)),
+        "panning" | "pan" => Some(Some(ShotSubType::Panning)),
+        "other" => Some(Some(ShotSubType::Other)),
+        "none" => Some(None),
+        _ => None,
+    }
+}
+
+/// Unrecognized department names become `Department::Other`.
+fn parse_department(token: &str) -> Department {
+    match token.to_lowercase().as_str() {
+        "production" => Department::Production,
+        "art" => Department::Art,
+        "wardrobe" => Department::Wardrobe,
+        "hairmakeup" => Department::HairMakeup,
+        "camera" => Department::Camera,
+        "sound" => Department::Sound,
+        "electric" => Department::Electric,
+        "lightinggrip" => Department::LightingGrip,
+        "props" => Department::Props,
+        "practicalfx" => Department::PracticalFX,
+        "visualfx" => Department::VisualFX,
+        "stunts" => Department::Stunts,
+        "animals" => Department::Animals,
+        "vehicles" => Department::Vehicles,
+        "dance" => Department::Dance,
+        "choreography" => Department::Choreography,
+        "pyrotechnics" => Department::Pyrotechnics,
+        "craftservices" => Department::CraftServices,
+        "transportation" => Department::Transportation,
+        "miscellaneous" => Department::Miscellaneous,
+        _ => Department::Other(token.to_string()),
+    }
+}
+
+/// Parses one line of the command language.
+///
+/// Returns `Ok(None)` for blank lines and comments.
+pub fn parse_line(line: &str) -> Result<Option<ShellCommand>, ShellError> {
+    let tokens = tokenize(line)?;
+    let Some(verb) = tokens.first() else {
+        return Ok(None);
+    };
+    let noun = tokens.get(1).map(|s| s.as_str()).unwrap_or("");
+
+    let cmd = match (verb.as_str(), noun) {
+        ("add", "shotline") => {
+            let start = ScreenplayCoordinate {
+                page: parse_index(tokens.get(2), "start page")?,
+                line: parse_index(tokens.get(3), "start line")?,
+                element: None,
+            };
+            let end = ScreenplayCoordinate {
+                page: parse_index(tokens.get(4), "end page")?,
+                line: parse_index(tokens.get(5), "end line")?,
+                element: None,
+            };
+            ShellCommand::AddShotline(start, end)
+        }
+        ("remove", "shotline") => {
+            ShellCommand::RemoveShotline(parse_index(tokens.get(2), "shotline number")?)
+        }
+        ("list", "shotlines") => ShellCommand::ListShotlines,
+        ("modify", "shotline") => {
+            let index = parse_index(tokens.get(2), "shotline number")?;
+            let field = tokens.get(3).map(|s| s.as_str()).unwrap_or("");
+            let Some(value) = tokens.get(4) else {
+                return Err(ShellError::Parse(format!("expected a value for '{}'", field)));
+            };
+            let change = match field {
+                "type" => ShotlineChange::ShotType(
+                    parse_shot_type(value)
+                        .ok_or(ShellError::Parse(format!("unknown shot type '{}'", value)))?,
+                ),
+                "subtype" => ShotlineChange::SubType(
+                    parse_shot_subtype(value)
+                        .ok_or(ShellError::Parse(format!("unknown shot subtype '{}'", value)))?,
+                ),
+                "number" => ShotlineChange::Number(value.clone()),
+                "tag" => {
+                    let tag_index = parse_index(tokens.get(5), "tag number")?;
+                    match value.as_str() {
+                        "add" => ShotlineChange::AddTag(tag_index),
+                        "remove" => ShotlineChange::RemoveTag(tag_index),
+                        _ => {
+                            return Err(ShellError::Parse(format!(
+                                "expected 'add' or 'remove', found '{}'",
+                                value
+                            )));
+                        }
+                    }
+                }
+                _ => {
+                    return Err(ShellError::Parse(format!(
+                        "unknown shotline field '{}'",
+                        field
+                    )));
+                }
+            };
+            ShellCommand::ModifyShotline(index, change)
+        }
+        ("add", "tag") => {
+            let Some(string) = tokens.get(2) else {
+                return Err(ShellError::Parse("expected tag text".to_string()));
+            };
+            let departments = tokens[3..].iter().map(|d| parse_department(d)).collect();
+            ShellCommand::AddTag(string.clone(), departments)
+        }
+        ("remove", "tag") => ShellCommand::RemoveTag(parse_index(tokens.get(2), "tag number")?),
+        ("list", "tags") => ShellCommand::ListTags,
+        ("go", "page") => ShellCommand::GoPage(parse_index(tokens.get(2), "page")?),
+        ("save", "doc") => {
+            let Some(path) = tokens.get(2) else {
+                return Err(ShellError::Parse("expected a filepath".to_string()));
+            };
+            ShellCommand::SaveDoc(path.clone())
+        }
+        ("export", _) => {
+            let format = match noun {
+                "csv" => ExportFormat::Csv,
+                "kra" => ExportFormat::Kra,
+                "sheets" => ExportFormat::Sheets,
+                "lined" => ExportFormat::Lined,
+                "breakdown" => ExportFormat::Breakdown,
+                _ => return Err(ShellError::Parse(format!("unknown export format '{}'", noun))),
+            };
+            let Some(path) = tokens.get(2) else {
+                return Err(ShellError::Parse("expected a filepath".to_string()));
+            };
+            ShellCommand::Export(format, path.clone())
+        }
+        ("undo", _) => ShellCommand::Undo,
+        ("redo", _) => ShellCommand::Redo,
+        ("help", _) => ShellCommand::Help,
+        ("quit", _) | ("exit", _) => ShellCommand::Quit,
+        _ => {
+            return Err(ShellError::Parse(format!("unknown command '{}'", tokens.join(" "))));
+        }
+    };
+    Ok(Some(cmd))
+}
+
+/// A ShotlinerDoc being edited through the command language.
+pub struct Shell {
+    pub doc: ShotlinerDoc,
+    pub current_page: usize,
+}
+impl Shell {
+    pub fn new(doc: ShotlinerDoc) -> Self {
+        Shell {
+            doc: doc,
+            current_page: 0,
+        }
+    }
+
+    /// Shots in the order `list shotlines` shows them: by shotline start, with unlined shots last.
+    pub fn ordered_shot_ids(&self) -> Vec<production::ShotID> {
+        let mut shots: Vec<(&production::ShotID, &production::Shot)> =
+            self.doc.annotation_map.shotlines.iter().collect();
+        shots.sort_by(|(a_id, a), (b_id, b)| {
+            let a_start = a.shotline.as_ref().map(|sl| (sl.start.page, sl.start.line));
+            let b_start = b.shotline.as_ref().map(|sl| (sl.start.page, sl.start.line));
+            match (a_start, b_start) {
+                (Some(a_start), Some(b_start)) => a_start.cmp(&b_start),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            }
+            .then_with(|| a_id.cmp(b_id))
+        });
+        shots.into_iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    /// Tags in the order `list tags` shows them: alphabetically.
+    pub fn ordered_tag_ids(&self) -> Vec<TagID> {
+        let mut tags: Vec<(&TagID, &Tag)> = self.doc.annotation_map.tags.iter().collect();
+        tags.sort_by(|(a_id, a), (b_id, b)| a.string.cmp(&b.string).then_with(|| a_id.cmp(b_id)));
+        tags.into_iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    fn shot_id_at(&self, index: usize) -> Result<production::ShotID, ShellError> {
+        self.ordered_shot_ids()
+            .get(index)
+            .cloned()
+            .ok_or(ShellError::Failed(format!("no shotline {}", index)))
+    }
+
+    fn tag_id_at(&self, index: usize) -> Result<TagID, ShellError> {
+        self.ordered_tag_ids()
+            .get(index)
+            .cloned()
+            .ok_or(ShellError::Failed(format!("no tag {}", index)))
+    }
+
+    fn execute(&mut self, cmd: Command, failure: &str) -> Result<(), ShellError> {
+        self.doc
+            .execute(cmd)
+            .map(|_| ())
+            .map_err(|_| ShellError::Failed(failure.to_string()))
+    }
+
+    fn check_coordinate(&self, coord: &ScreenplayCoordinate) -> Result<(), ShellError> {
+        let Some(page) = self.doc.screenplay.pages.get(coord.page) else {
+            return Err(ShellError::Failed(format!("no page {}", coord.page)));
+        };
+        if coord.line >= page.lines.len() {
+            return Err(ShellError::Failed(format!(
+                "page {} (numbered {}) has no line {}",
+                coord.page,
+                self.doc.production_page_label(coord.page),
+                coord.line
+            )));
+        }
+        Ok(())
+    }
+
+    fn format_shot(&self, index: usize, shot: &production::Shot) -> String {
+        let number = shot
+            .shot_number
+            .as_ref()
+            .map(|n| n.0.clone())
+            .unwrap_or("-".to_string());
+        let span = match &shot.shotline {
+            Some(sl) => format!(
+                "{} -> {}",
+                self.doc.production_coordinate_label(&sl.start),
+                self.doc.production_coordinate_label(&sl.end)
+            ),
+            None => "(no shotline)".to_string(),
+        };
+        let tags: Vec<String> = shot
+            .primary_composition
+            .tags
+            .iter()
+            .filter_map(|id| self.doc.annotation_map.tags.get(id))
+            .map(|tag| tag.string.clone())
+            .collect();
+        let mut line = format!(
+            "{:>3}: [{}] {} {:?}",
+            index, number, span, shot.primary_composition.shot_type
+        );
+        if let Some(subtype) = &shot.primary_composition.subtype {
+            line.push_str(&format!(" {:?}", subtype));
+        }
+        if !tags.is_empty() {
+            line.push_str(&format!(" | tags: {}", tags.join(", ")));
+        }
+        line
+    }
+
+    fn format_page(&self, page_index: usize) -> Result<String, ShellError> {
+        let Some(page) = self.doc.screenplay.pages.get(page_index) else {
+            return Err(ShellError::Failed(format!("no page {}", page_index)));
+        };
+        let mut out = format!(
+            "--- page {} of {} (numbered {}) ---",
+            page_index,
+            self.doc.screenplay.pages.len() - 1,
+            self.doc.production_page_label(page_index)
+        );
+        for (line_index, line) in page.lines.iter().enumerate() {
+            let coord = (page_index, line_index);
+            let lined = self.doc.annotation_map.shotlines.values().any(|shot| {
+                shot.shotline.as_ref().is_some_and(|sl| {
+                    (sl.start.page, sl.start.line) <= coord && coord <= (sl.end.page, sl.end.line)
+                })
+            });
+            let text: Vec<&str> = line.text_elements.iter().map(|e| e.text.as_str()).collect();
+            out.push_str(&format!(
+                "\n{:>3} {} {}",
+                line_index,
+                if lined { "|" } else { " " },
+                text.join(" ")
+            ));
+        }
+        Ok(out)
+    }
+
+    /// Runs one command against the document, returning the text to show the user.
+    pub fn run_command(&mut self, cmd: ShellCommand) -> Result<String, ShellError> {
+        match cmd {
+            ShellCommand::AddShotline(start, end) => {
+                self.check_coordinate(&start)?;
+                self.check_coordinate(&end)?;
+                if (end.page, end.line) < (start.page, start.line) {
+                    return Err(ShellError::Failed("shotline ends before it starts".to_string()));
+                }
+                let mut shot = production::Shot::new();
+                shot.shotline = Some(ShotLine::new(start, end));
+                let id = production::ShotID::new();
+                self.execute(Command::AddShotline(id.clone(), shot), "couldn't add shotline")?;
+                let index = self.ordered_shot_ids().iter().position(|i| *i == id).unwrap_or(0);
+                Ok(format!("added shotline {}", index))
+            }
+            ShellCommand::RemoveShotline(index) => {
+                let id = self.shot_id_at(index)?;
+                self.execute(Command::RemoveShotline(id, None), "couldn't remove shotline")?;
+                Ok(format!("removed shotline {}", index))
+            }
+            ShellCommand::ListShotlines => {
+                let lines: Vec<String> = self
+                    .ordered_shot_ids()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| self.format_shot(i, &self.doc.annotation_map.shotlines[id]))
+                    .collect();
+                if lines.is_empty() {
+                    return Ok("no shotlines".to_string());
+                }
+                Ok(lines.join("\n"))
+            }
+            ShellCommand::ModifyShotline(index, change) => {
+                let id = self.shot_id_at(index)?;
+                let mut shot = self.doc.annotation_map.shotlines[&id].clone();
+                match change {
+                    ShotlineChange::ShotType(shot_type) => {
+                        shot.primary_composition.shot_type(shot_type)
+                    }
+                    ShotlineChange::SubType(subtype) => shot.primary_composition.subtype(subtype),
+                    ShotlineChange::Number(number) => {
+                        shot.shot_number = Some(production::ShotNumber(number))
+                    }
+                    ShotlineChange::AddTag(tag_index) => {
+                        let tag_id = self.tag_id_at(tag_index)?;
+                        shot.primary_composition.add_tag(&tag_id).map_err(|_| {
+                            ShellError::Failed(format!("shotline {} already has tag {}", index, tag_index))
+                        })?;
+                    }
+                    ShotlineChange::RemoveTag(tag_index) => {
+                        let tag_id = self.tag_id_at(tag_index)?;
+                        shot.primary_composition.remove_tag(&tag_id).map_err(|_| {
+                            ShellError::Failed(format!("shotline {} doesn't have tag {}", index, tag_index))
+                        })?;
+                    }
+                }
+                let summary = self.format_shot(index, &shot);
+                self.execute(Command::ModifyShotline(id, Some(shot)), "couldn't modify shotline")?;
+                Ok(summary)
+            }
+            ShellCommand::AddTag(string, departments) => {
+                let tag = Tag {
+                    string: string,
+                    departments: departments,
+                };
+                let id = TagID::new();
+                self.execute(Command::AddTag(id.clone(), tag), "couldn't add tag")?;
+                let index = self.ordered_tag_ids().iter().position(|i| *i == id).unwrap_or(0);
+                Ok(format!("added tag {}", index))
+            }
+            ShellCommand::RemoveTag(index) => {
+                let id = self.tag_id_at(index)?;
+                self.doc
+                    .delete_tag(&id)
+                    .map_err(|_| ShellError::Failed("couldn't remove tag".to_string()))?;
+                Ok(format!("removed tag {}", index))
+            }
+            ShellCommand::ListTags => {
+                let lines: Vec<String> = self
+                    .ordered_tag_ids()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| {
+                        let tag = &self.doc.annotation_map.tags[id];
+                        format!("{:>3}: \"{}\" {:?}", i, tag.string, tag.departments)
+                    })
+                    .collect();
+                if lines.is_empty() {
+                    return Ok("no tags".to_string());
+                }
+                Ok(lines.join("\n"))
+            }
+            ShellCommand::GoPage(page) => {
+                let text = self.format_page(page)?;
+                self.current_page = page;
+                Ok(text)
+            }
+            ShellCommand::SaveDoc(path) => {
+                self.doc
+                    .save(&path)
+                    .map_err(|e| ShellError::Failed(e.to_string()))?;
+                Ok(format!("saved {}", path))
+            }
+            ShellCommand::Export(ExportFormat::Csv, path) => {
+                let Some(shotlist) = reports::get_shotlist(&self.doc) else {
+                    return Err(ShellError::Failed("no shots to export".to_string()));
+                };
+                shot_list_csv::save_to_file(
+                    &self.doc.screenplay,
+                    &self.doc.production_page_labels(),
+                    &shotlist,
+                    &shot_list_csv::default_columns(),
+                    &path,
+                )
+                .map_err(|e| ShellError::Failed(e.to_string()))?;
+                Ok(format!("exported {} shots to {}", shotlist.entries.len(), path))
+            }
+            ShellCommand::Export(ExportFormat::Kra, path) => {
+                let Some(shotlist) = reports::get_shotlist(&self.doc) else {
+                    return Err(ShellError::Failed("no shots to export".to_string()));
+                };
+                kra_storyboard::save_to_file(
+                    &self.doc.screenplay,
+                    &shotlist,
+                    &kra_storyboard::KraTemplateSettings::default(),
+                    &path,
+                )
+                .map_err(|e| ShellError::Failed(e.to_string()))?;
+                Ok(format!("exported {} panels to {}", shotlist.entries.len(), path))
+            }
+            ShellCommand::Export(ExportFormat::Sheets, path) => {
+                let Some(shotlist) = reports::get_shotlist(&self.doc) else {
+                    return Err(ShellError::Failed("no shots to export".to_string()));
+                };
+                let pages = storyboard_sheets::layout_sheets(
+                    &self.doc.screenplay,
+                    &shotlist,
+                    &storyboard_sheets::SheetSettings::default(),
+                );
+                if path.ends_with(".svg") {
+                    let written = storyboard_sheets::save_svgs(&pages, &path)
+                        .map_err(|e| ShellError::Failed(e.to_string()))?;
+                    return Ok(format!("exported {} sheets", written.len()));
+                }
+                storyboard_sheets::save_pdf(&pages, &path)
+                    .map_err(|e| ShellError::Failed(e.to_string()))?;
+                Ok(format!("exported {} sheets to {}", pages.len(), path))
+            }
+            ShellCommand::Export(ExportFormat::Lined, path) => {
+                lined_script::save_to_file(
+                    &self.doc,
+                    &lined_script::LinedScriptSettings::default(),
+                    &path,
+                )
+                .map_err(|e| ShellError::Failed(e.to_string()))?;
+                Ok(format!(
+                    "exported {} lined pages to {}",
+                    self.doc.screenplay.pages.len(),
+                    path
+                ))
+            }
+            ShellCommand::Export(ExportFormat::Breakdown, path) => {
+                breakdown_script::save_to_file(
+                    &self.doc,
+                    &breakdown_script::BreakdownScriptSettings::default(),
+                    &path,
+                )
+                .map_err(|e| ShellError::Failed(e.to_string()))?;
+                Ok(format!(
+                    "exported {} breakdown pages to {}",
+                    self.doc.screenplay.pages.len(),
+                    path
+                ))
+            }
+            ShellCommand::Undo => match self.doc.undo() {
+                Ok(CommandHistoryStatus::UndoLimitReached) => Ok("nothing to undo".to_string()),
+                Ok(_) => Ok("undone".to_string()),
+                Err(_) => Err(ShellError::Failed("couldn't undo".to_string())),
+            },
+            ShellCommand::Redo => match self.doc.redo() {
+                Ok(CommandHistoryStatus::RedoLimitReached) => Ok("nothing to redo".to_string()),
+                Ok(_) => Ok("redone".to_string()),
+                Err(_) => Err(ShellError::Failed("couldn't redo".to_string())),
+            },
+            ShellCommand::Help => Ok(HELP_TEXT.to_string()),
+            ShellCommand::Quit => Ok(String::new()),
+        }
+    }
+
+    /// Parses and runs one line of the command language.
+    pub fn run_line(&mut self, line: &str) -> Result<Option<String>, ShellError> {
+        let Some(cmd) = parse_line(line)? else {
+            return Ok(None);
+        };
+        self.run_command(cmd).map(Some)
+    }
+
+    /// Runs a whole script, one command per line, handing each command's output to `on_output`.
+    ///
+    /// Stops at the first failing command, returning its (1-based) line number along with the error.
+    /// A `quit` line ends the script early.
+    pub fn run_script(
+        &mut self,
+        script: &str,
+        mut on_output: impl FnMut(&str),
+    ) -> Result<(), (usize, ShellError)> {
+        for (line_index, line) in script.lines().enumerate() {
+            let cmd = match parse_line(line) {
+                Ok(Some(cmd)) => cmd,
+                Ok(None) => continue,
+                Err(e) => return Err((line_index + 1, e)),
+            };
+            if cmd == ShellCommand::Quit {
+                break;
+            }
+            match self.run_command(cmd) {
+                Ok(output) => on_output(&output),
+                Err(e) => return Err((line_index + 1, e)),
+            }
+        }
+        Ok(())
+    }
+}
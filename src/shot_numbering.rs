@@ -0,0 +1,448 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use screenplay_doc_parser_rs::screenplay_document::ScreenplayDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::Command;
+use crate::production::{Shot, ShotID, ShotNumber};
+use crate::reports;
+use crate::revisions::{letter_suffix, suffix_index};
+use crate::shotliner_document::AnnotationMap;
+
+/// How shot numbers are made up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NumberingScheme {
+    /// The scene number, then a letter for each shot in the scene: 12A, 12B... skipping I and O.
+    #[default]
+    SceneLetter,
+    /// 1, 2, 3... through the whole script.
+    Sequential,
+    /// The scene number, then a letter for each camera setup in the scene;
+    /// further shots from the same setup are counted after it: 12A, 12A2, 12B...
+    /// Shots without a setup get a letter of their own.
+    SetupBased,
+}
+
+/// What happens to the shots already numbered when numbering.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NumberingMode {
+    /// Every shot is renumbered in script order, so a shot inserted between two others shifts the ones after it.
+    RenumberOnInsert,
+    /// Numbered shots keep their numbers. A new shot gets the number it would have had if that's free
+    /// and still in order; otherwise it's numbered after the shot before it (12A1 after 12A, 5A after 5).
+    #[default]
+    KeepStable,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ShotNumbering {
+    pub scheme: NumberingScheme,
+    pub mode: NumberingMode,
+}
+
+/// One run of digits or letters in a shot number; see `number_key`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NumberSegment {
+    Number(u64),
+    /// Ordered as `letter_suffix` counts (Z, then AA), with anything containing I or O after the rest.
+    Letters(usize, String),
+}
+
+/// Splits a shot number into the runs of digits and letters it's ordered by: "12A2" is 12, A, 2.
+/// Anything else, like dashes or periods, only separates runs.
+pub fn number_key(number: &str) -> Vec<NumberSegment> {
+    let mut segments: Vec<NumberSegment> = Vec::new();
+    let mut run = String::new();
+    let mut flush = |run: &mut String| {
+        if run.is_empty() {
+            return;
+        }
+        let segment = if run.starts_with(|c: char| c.is_ascii_digit()) {
+            NumberSegment::Number(run.parse().unwrap_or(u64::MAX))
+        } else {
+            let letters = run.to_ascii_uppercase();
+            NumberSegment::Letters(suffix_index(&letters).unwrap_or(usize::MAX), letters)
+        };
+        segments.push(segment);
+        run.clear();
+    };
+    for c in number.trim().chars() {
+        let continues = run
+            .chars()
+            .last()
+            .is_none_or(|last| last.is_ascii_digit() == c.is_ascii_digit());
+        if !c.is_ascii_alphanumeric() || !continues {
+            flush(&mut run);
+        }
+        if c.is_ascii_alphanumeric() {
+            run.push(c);
+        }
+    }
+    flush(&mut run);
+    segments
+}
+
+/// Shot numbers are compared without surrounding whitespace, ignoring case.
+fn normalize_number(number: &ShotNumber) -> String {
+    number.0.trim().to_ascii_uppercase()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NumberingProblem {
+    /// More than one shot has this number. The shots are in script order.
+    Duplicate { number: String, shots: Vec<ShotID> },
+    /// A shot's number doesn't come after the number of the shot before it.
+    OutOfOrder {
+        shot_id: ShotID,
+        number: String,
+        previous: String,
+    },
+}
+impl fmt::Display for NumberingProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberingProblem::Duplicate { number, shots } => {
+                write!(f, "shot number {} is used by {} shots", number, shots.len())
+            }
+            NumberingProblem::OutOfOrder {
+                number, previous, ..
+            } => write!(f, "shot {} comes after shot {}", number, previous),
+        }
+    }
+}
+
+/// A shot with a shotline, and where it goes in the numbering.
+struct PlacedShot<'a> {
+    id: &'a ShotID,
+    shot: &'a Shot,
+    /// Index into `get_scene_extents`; `None` before the first scene.
+    scene: Option<usize>,
+}
+
+/// Which shots a shot's number has to be in order with: under `NumberingScheme::SetupBased`,
+/// the ones from the same setup (shots without a setup are one group), otherwise all of them.
+fn order_group(shot: &Shot, scheme: NumberingScheme) -> Option<&str> {
+    match scheme {
+        NumberingScheme::SetupBased => shot
+            .primary_composition
+            .setup
+            .as_ref()
+            .map(|setup| setup.id.as_str()),
+        NumberingScheme::SceneLetter | NumberingScheme::Sequential => None,
+    }
+}
+
+/// Every shot with a shotline, in script order.
+fn placed_shots<'a>(
+    screenplay: &ScreenplayDocument,
+    shots: &[(&'a ShotID, &'a Shot)],
+) -> Vec<PlacedShot<'a>> {
+    let extents = reports::get_scene_extents(screenplay);
+    let mut placed: Vec<PlacedShot> = shots
+        .iter()
+        .filter_map(|(id, shot)| {
+            let start = &shot.shotline.as_ref()?.start;
+            let scene = extents.iter().rposition(|(_, scene, _)| {
+                (scene.start.page, scene.start.line) <= (start.page, start.line)
+            });
+            Some(PlacedShot {
+                id: id,
+                shot: shot,
+                scene: scene,
+            })
+        })
+        .collect();
+    placed.sort_by_key(|placed| {
+        let shotline = placed.shot.shotline.as_ref();
+        (
+            shotline.map(|sl| (sl.start.page, sl.start.line, sl.end.page, sl.end.line)),
+            **placed.id,
+        )
+    });
+    placed
+}
+
+/// What goes in front of the letters for shots in each scene: the scene number (or its position
+/// in the script if it isn't numbered), with a dash if it already ends in a letter, e.g. "12" or "3A-".
+fn scene_prefixes(screenplay: &ScreenplayDocument) -> Vec<String> {
+    reports::get_scene_extents(screenplay)
+        .iter()
+        .enumerate()
+        .map(|(index, (_, scene, _))| {
+            let number = scene
+                .number
+                .as_ref()
+                .map(|number| number.0.trim().trim_end_matches('*').to_string())
+                .unwrap_or((index + 1).to_string());
+            if number.ends_with(|c: char| c.is_ascii_alphabetic()) {
+                format!("{}-", number)
+            } else {
+                number
+            }
+        })
+        .collect()
+}
+
+/// The number every placed shot would have if they were all numbered from scratch.
+fn scheme_numbers(
+    placed: &[PlacedShot],
+    prefixes: &[String],
+    scheme: NumberingScheme,
+) -> Vec<String> {
+    let prefix = |scene: Option<usize>| scene.map(|index| prefixes[index].as_str()).unwrap_or("");
+    let mut numbers: Vec<String> = Vec::new();
+    let mut shots_in_scene = 0;
+    let mut setups: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (index, shot) in placed.iter().enumerate() {
+        if index == 0 || placed[index - 1].scene != shot.scene {
+            shots_in_scene = 0;
+            setups.clear();
+        }
+        let number = match scheme {
+            NumberingScheme::Sequential => (index + 1).to_string(),
+            NumberingScheme::SceneLetter => {
+                shots_in_scene += 1;
+                format!("{}{}", prefix(shot.scene), letter_suffix(shots_in_scene))
+            }
+            NumberingScheme::SetupBased => {
+                // shots without a setup are counted in `shots_in_scene`, each taking a letter of its own
+                let letters = setups.len() + shots_in_scene;
+                let (letter, count) = match &shot.shot.primary_composition.setup {
+                    Some(setup) => {
+                        let entry = setups.entry(setup.id.as_str()).or_insert((letters + 1, 0));
+                        entry.1 += 1;
+                        *entry
+                    }
+                    None => {
+                        shots_in_scene += 1;
+                        (letters + 1, 1)
+                    }
+                };
+                let count = if count > 1 {
+                    count.to_string()
+                } else {
+                    String::new()
+                };
+                format!("{}{}{}", prefix(shot.scene), letter_suffix(letter), count)
+            }
+        };
+        numbers.push(number);
+    }
+    numbers
+}
+
+/// New shot numbers for `shots` under `numbering`, keyed by ShotID. Shots that would keep their number are left out.
+///
+/// Only shots with a shotline are numbered, in script order; the rest keep whatever number they have.
+pub fn assign_numbers(
+    screenplay: &ScreenplayDocument,
+    shots: &[(&ShotID, &Shot)],
+    numbering: &ShotNumbering,
+) -> HashMap<ShotID, ShotNumber> {
+    let placed = placed_shots(screenplay, shots);
+    let prefixes = scene_prefixes(screenplay);
+    let ideal = scheme_numbers(&placed, &prefixes, numbering.scheme);
+
+    let numbers: Vec<String> = match numbering.mode {
+        NumberingMode::RenumberOnInsert => ideal,
+        NumberingMode::KeepStable => {
+            let mut used: HashSet<String> = shots
+                .iter()
+                .filter_map(|(_, shot)| shot.shot_number.as_ref().map(normalize_number))
+                .collect();
+            let mut numbers: Vec<Option<String>> = placed
+                .iter()
+                .map(|placed| placed.shot.shot_number.as_ref().map(normalize_number))
+                .collect();
+            for index in 0..placed.len() {
+                if numbers[index].is_some() {
+                    continue;
+                }
+                let number = stable_number(
+                    &placed,
+                    &prefixes,
+                    &numbers,
+                    &used,
+                    index,
+                    &ideal[index],
+                    numbering.scheme,
+                );
+                used.insert(number.clone());
+                numbers[index] = Some(number);
+            }
+            numbers
+                .into_iter()
+                .map(|number| number.unwrap_or_default())
+                .collect()
+        }
+    };
+
+    placed
+        .iter()
+        .zip(numbers)
+        .filter(|(placed, number)| {
+            placed.shot.shot_number.as_ref().map(normalize_number) != Some(number.clone())
+        })
+        .map(|(placed, number)| (placed.id.clone(), ShotNumber(number)))
+        .collect()
+}
+
+/// How many letters (or digits) are tried after the previous shot's number before giving up on keeping a new shot in order.
+const SUFFIX_ATTEMPTS: usize = 576;
+
+/// A number for the unnumbered shot at `index` that doesn't disturb the shots around it
+/// (from its setup, under `NumberingScheme::SetupBased`; see `order_group`). See `NumberingMode::KeepStable`.
+fn stable_number(
+    placed: &[PlacedShot],
+    prefixes: &[String],
+    numbers: &[Option<String>],
+    used: &HashSet<String>,
+    index: usize,
+    ideal: &str,
+    scheme: NumberingScheme,
+) -> String {
+    let group = order_group(placed[index].shot, scheme);
+    let in_group = |other: &usize| order_group(placed[*other].shot, scheme) == group;
+    let previous = (0..index)
+        .rev()
+        .filter(in_group)
+        .find_map(|before| numbers[before].as_ref());
+    let next = (index + 1..placed.len())
+        .filter(in_group)
+        .find_map(|after| numbers[after].as_ref());
+    let fits = |candidate: &str| {
+        !used.contains(candidate)
+            && previous.is_none_or(|previous| number_key(candidate) > number_key(previous))
+            && next.is_none_or(|next| number_key(candidate) < number_key(next))
+    };
+    if fits(ideal) {
+        return ideal.to_string();
+    }
+
+    // numbered after the shot before it, as long as that's in the same scene (for the scene schemes)
+    let scene = placed[index].scene;
+    let prefix = scene
+        .map(|index| prefixes[index].clone())
+        .unwrap_or_default();
+    let base = match scheme {
+        NumberingScheme::Sequential => previous.cloned(),
+        NumberingScheme::SceneLetter | NumberingScheme::SetupBased => (0..index)
+            .rev()
+            .take_while(|before| placed[*before].scene == scene)
+            .filter(in_group)
+            .find_map(|before| numbers[before].clone())
+            .or(Some(prefix.clone())),
+    };
+    if let Some(base) = base {
+        let mut inserted = (1..=SUFFIX_ATTEMPTS).map(|count| {
+            if base.ends_with(|c: char| c.is_ascii_alphabetic()) {
+                format!("{}{}", base, count)
+            } else {
+                format!("{}{}", base, letter_suffix(count))
+            }
+        });
+        if let Some(number) = inserted.find(|candidate| fits(candidate)) {
+            return number;
+        }
+    }
+
+    // nothing fits in order, so it gets the first free number, out of order
+    (1..)
+        .map(|count| match scheme {
+            NumberingScheme::Sequential => count.to_string(),
+            NumberingScheme::SceneLetter | NumberingScheme::SetupBased => {
+                format!("{}{}", prefix, letter_suffix(count))
+            }
+        })
+        .find(|candidate| !used.contains(candidate))
+        .unwrap_or_default()
+}
+
+/// The commands that number the shots in `annotation_map` under `numbering`. See `assign_numbers`.
+pub fn numbering_commands(
+    screenplay: &ScreenplayDocument,
+    annotation_map: &AnnotationMap,
+    numbering: &ShotNumbering,
+) -> Vec<Command> {
+    let shots: Vec<(&ShotID, &Shot)> = annotation_map.shotlines.iter().collect();
+    let mut numbers: Vec<(ShotID, ShotNumber)> = assign_numbers(screenplay, &shots, numbering)
+        .into_iter()
+        .collect();
+    numbers.sort_by_key(|(id, _)| **id);
+    numbers
+        .into_iter()
+        .filter_map(|(id, number)| {
+            let mut shot = annotation_map.shotlines.get(&id)?.clone();
+            shot.shot_number = Some(number);
+            Some(Command::ModifyShotline(id, Some(shot)))
+        })
+        .collect()
+}
+
+/// Shot numbers used more than once, then shots numbered lower than the numbered shot before them, in script order.
+///
+/// Under `NumberingScheme::SetupBased` a setup's shots are spread through its scene,
+/// so each shot is only checked against the one before it from the same setup.
+pub fn find_problems(
+    screenplay: &ScreenplayDocument,
+    annotation_map: &AnnotationMap,
+    scheme: NumberingScheme,
+) -> Vec<NumberingProblem> {
+    let shots: Vec<(&ShotID, &Shot)> = annotation_map.shotlines.iter().collect();
+    let placed = placed_shots(screenplay, &shots);
+    let mut problems: Vec<NumberingProblem> = Vec::new();
+
+    // shots with shotlines in script order, then the rest by ID
+    let mut ordered: Vec<(&ShotID, &Shot)> = placed
+        .iter()
+        .map(|placed| (placed.id, placed.shot))
+        .collect();
+    let mut unplaced: Vec<(&ShotID, &Shot)> = shots
+        .iter()
+        .filter(|(_, shot)| shot.shotline.is_none())
+        .cloned()
+        .collect();
+    unplaced.sort_by_key(|(id, _)| ***id);
+    ordered.extend(unplaced);
+
+    let mut by_number: HashMap<String, Vec<ShotID>> = HashMap::new();
+    for (id, shot) in &ordered {
+        if let Some(number) = &shot.shot_number {
+            by_number
+                .entry(normalize_number(number))
+                .or_default()
+                .push((*id).clone());
+        }
+    }
+    let mut duplicates: Vec<(String, Vec<ShotID>)> = by_number
+        .into_iter()
+        .filter(|(_, shots)| shots.len() > 1)
+        .collect();
+    duplicates.sort_by(|(a, _), (b, _)| number_key(a).cmp(&number_key(b)).then_with(|| a.cmp(b)));
+    for (number, shots) in duplicates {
+        problems.push(NumberingProblem::Duplicate {
+            number: number,
+            shots: shots,
+        });
+    }
+
+    let mut previous: HashMap<Option<&str>, String> = HashMap::new();
+    for placed in &placed {
+        let Some(number) = placed.shot.shot_number.as_ref().map(normalize_number) else {
+            continue;
+        };
+        let setup = order_group(placed.shot, scheme);
+        if let Some(previous) = previous.get(&setup)
+            && number_key(&number) < number_key(previous)
+        {
+            problems.push(NumberingProblem::OutOfOrder {
+                shot_id: placed.id.clone(),
+                number: number.clone(),
+                previous: previous.clone(),
+            });
+        }
+        previous.insert(setup, number);
+    }
+    problems
+}
@@ -1,28 +1,36 @@
 use std::collections::HashSet;
 use std::ops::{Deref, DerefMut};
+use std::path::Path;
 use std::{collections::HashMap, fmt::Error, hash::Hash};
 use uuid::Uuid;
 
+use screenplay_doc_parser_rs::pdf_document::ElementIndentationsInches;
 use screenplay_doc_parser_rs::screenplay_document::{self, ScreenplayDocument};
+use serde::{Deserialize, Serialize};
 
+use crate::annotation_index::{
+    AnnotationIndex, AnnotationKey, AnnotationQueryResult, TagUsage, TagUsageIndex,
+};
 use crate::production::{self, ShotComposition};
-use crate::{commands, shotliner_document};
+use crate::revisions::{ProductionPageNumber, RevisionSet, Revisions};
+use crate::shot_numbering::{self, NumberingProblem, ShotNumbering};
+use crate::reports;
+use crate::serializables::sl_file::{self, SlFileError};
+use crate::smart_coordinate::{AnchorContext, SmartRange};
+use crate::{commands, merge_forward, shotliner_document, validation};
 
-//TODO: this will be used later, when we implement merge-forward for new drafts of the screenplay
-#[derive(Clone)]
-pub struct SmartScreenplayCoordinate {}
+pub use crate::smart_coordinate::SmartScreenplayCoordinate;
 
 /// A Tag is a finite Screenplay Element or range of Elements, which correspond to one or more Departments.    
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Tag {
     pub string: String,
-    // FIXME: TODO: We need to be able to search tags by ID as well as by production and/or screenplay element.
-    // fuck.
+    // Tags are found by position through `ShotlinerDoc::annotations_in_range` (and `_on_page`, `_in_scene`).
     pub departments: Vec<production::Department>,
     //pub other_metadata: idk
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TagID(Uuid);
 impl Deref for TagID {
     type Target = Uuid;
@@ -36,19 +44,25 @@ impl TagID {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TaggedElement {
+    #[serde(with = "crate::serializables::serde_helpers::coordinate_ranges")]
     pub ocurrances: HashSet<(
         screenplay_document::ScreenplayCoordinate,
         screenplay_document::ScreenplayCoordinate,
     )>, //list of RANGES that correspond to this thing...
+    #[serde(with = "crate::serializables::serde_helpers::Coordinate")]
     pub origin: screenplay_document::ScreenplayCoordinate,
+    #[serde(with = "crate::serializables::serde_helpers::Coordinate")]
     pub endpoint: screenplay_document::ScreenplayCoordinate, //inclusive
     pub tags: Vec<TagID>, // tags are found / stored lazily; find tags by referencing the Annotation Map; Don't duplicate tag structs, just IDs
                           // NOTE: if a UUID doesn't exist when invoking a tag search, DELETE it from the TaggedElement Vec
+    /// `origin` and `endpoint` by content, to find them again after the screenplay reflows. See `ShotlinerDoc::resolve_anchors`.
+    #[serde(default)]
+    pub anchors: Option<SmartRange>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TaggedElementID(Uuid);
 impl Deref for TaggedElementID {
     type Target = Uuid;
@@ -62,14 +76,22 @@ impl TaggedElementID {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Group {
-    string: String,
-    tags: HashSet<Tag>
+    pub string: String,
+    pub tags: HashSet<TagID>
 
 }
+impl Group {
+    pub fn new(string: String) -> Self {
+        Group {
+            string: string,
+            tags: HashSet::new(),
+        }
+    }
+}
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct GroupID(Uuid);
 impl GroupID {
     pub fn new() -> Self{
@@ -83,11 +105,17 @@ impl Deref for GroupID {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ShotLine {
+    #[serde(with = "crate::serializables::serde_helpers::Coordinate")]
     pub start: screenplay_document::ScreenplayCoordinate,
+    #[serde(with = "crate::serializables::serde_helpers::Coordinate")]
     pub end: screenplay_document::ScreenplayCoordinate,
-    pub unfilmed_lines: Option<HashSet<screenplay_document::ScreenplayDocument>>,
+    #[serde(with = "crate::serializables::serde_helpers::unfilmed_lines")]
+    pub unfilmed_lines: Option<HashSet<screenplay_document::ScreenplayCoordinate>>,
+    /// `start` and `end` by content, to find them again after the screenplay reflows. See `ShotlinerDoc::resolve_anchors`.
+    #[serde(default)]
+    pub anchors: Option<SmartRange>,
 }
 impl ShotLine {
     pub fn new(
@@ -98,11 +126,12 @@ impl ShotLine {
             start: start,
             end: end,
             unfilmed_lines: None,
+            anchors: None,
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AnnotationMap {
     pub shotlines: HashMap<production::ShotID, production::Shot>,
     pub tags: HashMap<TagID, Tag>,
@@ -125,8 +154,16 @@ impl AnnotationMap {
 //#[derive(Clone)]
 pub struct ShotlinerDoc {
     pub screenplay: ScreenplayDocument,
+    pub screenplay_filepath: Option<String>, // the PDF the screenplay was parsed from; saved documents reference it
     pub command_history: Option<crate::commands::CommandHistory>,
     pub annotation_map: AnnotationMap,
+    /// Finds annotations by position, and `tag_usage_index` finds everything referencing a tag.
+    /// The add/modify/remove methods keep both in sync; call `rebuild_indexes` after changing `annotation_map` any other way.
+    pub annotation_index: AnnotationIndex,
+    pub tag_usage_index: TagUsageIndex,
+    pub revisions: Revisions,
+    /// How `number_shots` and `add_numbered_shotline` number shots.
+    pub shot_numbering: ShotNumbering,
 }
 impl ShotlinerDoc {
     pub fn new(
@@ -134,39 +171,759 @@ impl ShotlinerDoc {
     ) -> Self {
         ShotlinerDoc {
             screenplay: screenplay,
+            screenplay_filepath: None,
             command_history: None,
             annotation_map: AnnotationMap::new(),
+            annotation_index: AnnotationIndex::new(),
+            tag_usage_index: TagUsageIndex::new(),
+            revisions: Revisions::default(),
+            shot_numbering: ShotNumbering::default(),
+        }
+    }
+
+    /// Replaces the whole AnnotationMap, e.g. with one loaded from disk, and re-indexes it.
+    pub fn set_annotation_map(&mut self, annotation_map: AnnotationMap) {
+        self.annotation_map = annotation_map;
+        self.rebuild_indexes();
+    }
+
+    pub fn rebuild_indexes(&mut self) {
+        self.annotation_index = AnnotationIndex::from_annotation_map(&self.annotation_map);
+        self.tag_usage_index = TagUsageIndex::from_annotation_map(&self.annotation_map);
+    }
+
+    /// Every TaggedElement, shot composition and Group that references the tag.
+    pub fn tag_usages(&self, id: &TagID) -> Vec<&TagUsage> {
+        self.tag_usage_index.usages(id)
+    }
+
+    /// Where a tag shows up in the screenplay: the ranges of its TaggedElements and the ShotLines of
+    /// the shots using it, in script order and without repeats. Groups aren't anywhere, so they're left out.
+    pub fn tag_ocurrances(
+        &self,
+        id: &TagID,
+    ) -> Vec<(
+        screenplay_document::ScreenplayCoordinate,
+        screenplay_document::ScreenplayCoordinate,
+    )> {
+        let mut ranges = Vec::new();
+        for usage in self.tag_usages(id) {
+            match usage {
+                TagUsage::TaggedElement(te_id) => {
+                    if let Some(te) = self.annotation_map.tagged_elements.get(te_id) {
+                        ranges.extend(reports::get_tagged_element_ranges(te));
+                    }
+                }
+                TagUsage::Shot(shot_id) | TagUsage::SubComposition(shot_id, _) => {
+                    if let Some(shotline) = self
+                        .annotation_map
+                        .shotlines
+                        .get(shot_id)
+                        .and_then(|shot| shot.shotline.as_ref())
+                    {
+                        ranges.push((shotline.start.clone(), shotline.end.clone()));
+                    }
+                }
+                TagUsage::Group(_) => {}
+            }
+        }
+        ranges.sort_by_key(|(start, end)| {
+            (start.page, start.line, start.element, end.page, end.line, end.element)
+        });
+        ranges.dedup();
+        ranges
+    }
+
+    /// Every shot, tagged element and tag overlapping the lines from `start` to `end` (inclusive).
+    pub fn annotations_in_range(
+        &self,
+        start: &screenplay_document::ScreenplayCoordinate,
+        end: &screenplay_document::ScreenplayCoordinate,
+    ) -> AnnotationQueryResult<'_> {
+        AnnotationQueryResult::from_keys(
+            &self.annotation_map,
+            self.annotation_index.query(start, end),
+        )
+    }
+
+    pub fn annotations_on_page(&self, page: usize) -> AnnotationQueryResult<'_> {
+        let last_line = self
+            .screenplay
+            .pages
+            .get(page)
+            .map(|p| p.lines.len().saturating_sub(1))
+            .unwrap_or(0);
+        self.annotations_in_range(
+            &screenplay_document::ScreenplayCoordinate {
+                page: page,
+                line: 0,
+                element: None,
+            },
+            &screenplay_document::ScreenplayCoordinate {
+                page: page,
+                line: last_line,
+                element: None,
+            },
+        )
+    }
+
+    /// Returns `None` if the scene isn't in the screenplay.
+    pub fn annotations_in_scene(
+        &self,
+        scene_id: &screenplay_document::SceneID,
+    ) -> Option<AnnotationQueryResult<'_>> {
+        let (_, scene, end) = reports::get_scene_extents(&self.screenplay)
+            .into_iter()
+            .find(|(id, _, _)| *id == scene_id)?;
+        Some(self.annotations_in_range(&scene.start, &end))
+    }
+
+    /// Parses a screenplay PDF with the default US-Letter indentations.
+    fn parse_pdf(filepath: &str) -> Result<ScreenplayDocument, Box<dyn std::error::Error>> {
+        Ok(
+            screenplay_doc_parser_rs::mupdf_basic_parser::get_screenplay_doc_from_filepath(
+                filepath.to_string(),
+                Some(ElementIndentationsInches::us_letter_default()),
+                None,
+                None,
+                None,
+            )?,
+        )
+    }
+
+    /// Parses a screenplay PDF with the default US-Letter indentations, and starts a new, empty document for it.
+    pub fn from_pdf(filepath: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let screenplay = ShotlinerDoc::parse_pdf(filepath)?;
+        let mut doc = ShotlinerDoc::new(screenplay);
+        doc.screenplay_filepath = Some(filepath.to_string());
+        Ok(doc)
+    }
+
+    /// Swaps in a new draft of the screenplay, moving every annotation to wherever its text ended up.
+    /// See `merge_forward::merge_forward`.
+    ///
+    /// Revision marks and locked page numbers are carried over too (see `Revisions::carry_forward`).
+    ///
+    /// The command history is cleared, since its commands point at lines of the old draft.
+    pub fn merge_forward(&mut self, screenplay: ScreenplayDocument) -> merge_forward::MergeReport {
+        let revisions = self
+            .revisions
+            .carry_forward(&self.screenplay, &screenplay);
+        self.swap_draft(screenplay, revisions)
+    }
+
+    fn swap_draft(
+        &mut self,
+        screenplay: ScreenplayDocument,
+        revisions: Revisions,
+    ) -> merge_forward::MergeReport {
+        let (annotation_map, mut report) =
+            merge_forward::merge_forward(&self.screenplay, &screenplay, &self.annotation_map);
+        report.old_page_labels = self.production_page_labels();
+        report.new_page_labels = revisions.page_labels(&screenplay);
+        self.screenplay = screenplay;
+        self.revisions = revisions;
+        self.set_annotation_map(annotation_map);
+        self.command_history = None;
+        report
+    }
+
+    /// Parses a new draft of the screenplay's PDF and merges forward onto it.
+    /// Saving the document afterwards references the new PDF.
+    pub fn merge_forward_from_pdf(
+        &mut self,
+        filepath: &str,
+    ) -> Result<merge_forward::MergeReport, Box<dyn std::error::Error>> {
+        let screenplay = ShotlinerDoc::parse_pdf(filepath)?;
+        let report = self.merge_forward(screenplay);
+        self.screenplay_filepath = Some(filepath.to_string());
+        Ok(report)
+    }
+
+    /// Freezes the page numbers as they are now, so revisions add A-pages instead of renumbering.
+    /// Does nothing if the pages are already locked.
+    pub fn lock_pages(&mut self) {
+        if !self.revisions.is_locked() {
+            self.revisions.locked_pages = self.revisions.all_page_numbers(&self.screenplay);
+        }
+    }
+
+    /// Goes back to the page numbers printed on the pages. Revision marks are kept.
+    pub fn unlock_pages(&mut self) {
+        self.revisions.locked_pages.clear();
+    }
+
+    /// The number production knows a page by. See `Revisions::page_number`.
+    pub fn production_page_number(&self, page_index: usize) -> Option<ProductionPageNumber> {
+        self.revisions.page_number(&self.screenplay, page_index)
+    }
+
+    /// See `Revisions::page_label`.
+    pub fn production_page_label(&self, page_index: usize) -> String {
+        self.revisions.page_label(&self.screenplay, page_index)
+    }
+
+    /// Every page's production label, by page index; for reports that label many pages.
+    pub fn production_page_labels(&self) -> Vec<String> {
+        self.revisions.page_labels(&self.screenplay)
+    }
+
+    /// A coordinate by production page number, e.g. "12A:5".
+    pub fn production_coordinate_label(
+        &self,
+        coord: &screenplay_document::ScreenplayCoordinate,
+    ) -> String {
+        self.revisions.coordinate_label(&self.screenplay, coord)
+    }
+
+    /// The revision that last changed a line, if any did.
+    pub fn revision_mark(&self, page: usize, line: usize) -> Option<&RevisionSet> {
+        self.revisions.mark(page, line)
+    }
+
+    /// Issues a new draft of the screenplay as the revision `set`:
+    /// changed lines are marked, locked pages get A-page numbers as needed (see `Revisions::issue`),
+    /// and the annotations are merged forward onto it.
+    pub fn issue_revision(
+        &mut self,
+        screenplay: ScreenplayDocument,
+        set: RevisionSet,
+    ) -> merge_forward::MergeReport {
+        let revisions = self.revisions.issue(&self.screenplay, &screenplay, set);
+        self.swap_draft(screenplay, revisions)
+    }
+
+    /// Parses a revised draft's PDF and issues it as the revision `set`.
+    pub fn issue_revision_from_pdf(
+        &mut self,
+        filepath: &str,
+        set: RevisionSet,
+    ) -> Result<merge_forward::MergeReport, Box<dyn std::error::Error>> {
+        let screenplay = ShotlinerDoc::parse_pdf(filepath)?;
+        let report = self.issue_revision(screenplay, set);
+        self.screenplay_filepath = Some(filepath.to_string());
+        Ok(report)
+    }
+
+    /// Saves this document as a `.sl` file. See `serializables::sl_file`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SlFileError> {
+        sl_file::save_to_file(self, path)
+    }
+
+    /// Loads a `.sl` file, re-parsing the screenplay PDF it references. See `serializables::sl_file`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SlFileError> {
+        sl_file::load_from_file(path)
+    }
+
+    /// Executes a `Command` against the `AnnotationMap` and pushes it onto the command history.
+    ///
+    /// Executing a new command discards anything that could have been redone.
+    pub fn execute(
+        &mut self,
+        mut cmd: commands::Command,
+    ) -> Result<commands::CommandHistoryStatus, Error> {
+        self.command_exec(&mut cmd)?;
+        self.command_history
+            .get_or_insert_with(commands::CommandHistory::default)
+            .push(cmd);
+        Ok(commands::CommandHistoryStatus::ExecuteSuccess)
+    }
+
+    /// Reverts the most recently executed (or redone) command.
+    pub fn undo(&mut self) -> Result<commands::CommandHistoryStatus, Error> {
+        let mut history = self.command_history.take().unwrap_or_default();
+        let Some(mut cmd) = history.take_undo() else {
+            self.command_history = Some(history);
+            return Ok(commands::CommandHistoryStatus::UndoLimitReached);
+        };
+        let result = self.command_undo(&mut cmd);
+        match result {
+            Ok(_) => history.return_undone(cmd),
+            Err(_) => history.return_failed_undo(cmd),
+        }
+        self.command_history = Some(history);
+        result.map(|_| commands::CommandHistoryStatus::UndoSuccess)
+    }
+
+    /// Re-applies the most recently undone command.
+    pub fn redo(&mut self) -> Result<commands::CommandHistoryStatus, Error> {
+        let mut history = self.command_history.take().unwrap_or_default();
+        let Some(mut cmd) = history.take_redo() else {
+            self.command_history = Some(history);
+            return Ok(commands::CommandHistoryStatus::RedoLimitReached);
+        };
+        let result = self.command_exec(&mut cmd);
+        match result {
+            Ok(_) => history.return_redone(cmd),
+            Err(_) => history.return_failed_redo(cmd),
+        }
+        self.command_history = Some(history);
+        result.map(|_| commands::CommandHistoryStatus::RedoSuccess)
+    }
+
+    /// Executes several commands as one atomic, single-entry-undoable edit.
+    pub fn execute_batch(
+        &mut self,
+        cmds: Vec<commands::Command>,
+    ) -> Result<commands::CommandHistoryStatus, Error> {
+        self.execute(commands::Command::Batch(cmds))
+    }
+
+    /// Deletes a Tag, and strips its `TagID` from every TaggedElement, Shot and Group that references it,
+    /// as one undoable edit.
+    pub fn delete_tag(&mut self, id: &TagID) -> Result<commands::CommandHistoryStatus, Error> {
+        if !self.annotation_map.tags.contains_key(id) {
+            return Err(Error);
         }
+        let mut cmds: Vec<commands::Command> = Vec::new();
+        let mut stripped_shots: Vec<&production::ShotID> = Vec::new();
+
+        for usage in self.tag_usages(id) {
+            match usage {
+                TagUsage::TaggedElement(te_id) => {
+                    let Some(te) = self.annotation_map.tagged_elements.get(te_id) else {
+                        continue;
+                    };
+                    let mut new_te = te.clone();
+                    new_te.tags.retain(|t| t != id);
+                    cmds.push(commands::Command::ModifyTaggedElement(te_id.clone(), Some(new_te)));
+                }
+                // a shot can use the tag in several compositions, but it's only modified once
+                TagUsage::Shot(shot_id) | TagUsage::SubComposition(shot_id, _) => {
+                    if stripped_shots.contains(&shot_id) {
+                        continue;
+                    }
+                    stripped_shots.push(shot_id);
+                    let Some(shot) = self.annotation_map.shotlines.get(shot_id) else {
+                        continue;
+                    };
+                    let mut new_shot = shot.clone();
+                    let _ = new_shot.primary_composition.remove_tag(id);
+                    if let Some(sub_compositions) = &mut new_shot.sub_compositions {
+                        for (_, composition) in sub_compositions.iter_mut() {
+                            let _ = composition.remove_tag(id);
+                        }
+                    }
+                    cmds.push(commands::Command::ModifyShotline(shot_id.clone(), Some(new_shot)));
+                }
+                TagUsage::Group(group_id) => {
+                    let Some(group) = self.annotation_map.groups.get(group_id) else {
+                        continue;
+                    };
+                    let mut new_group = group.clone();
+                    new_group.tags.remove(id);
+                    cmds.push(commands::Command::ModifyGroup(group_id.clone(), Some(new_group)));
+                }
+            }
+        }
+
+        cmds.push(commands::Command::RemoveTag(id.clone(), None));
+        self.execute_batch(cmds)
+    }
+
+    /// Everything wrong with the AnnotationMap, as far as can be told from the screenplay and the map itself.
+    pub fn validate(&self) -> Vec<validation::AnnotationProblem> {
+        validation::validate(&self.screenplay, &self.annotation_map)
     }
 
-    fn command_exec(&mut self, cmd: &commands::Command) -> Result<(), Error> {
+    /// Fixes every problem `validate` finds that `AnnotationProblem::is_repairable`, as one undoable edit.
+    ///
+    /// Returns the problems that were fixed. Nothing is pushed onto the command history if there's nothing to fix.
+    pub fn repair(&mut self) -> Result<Vec<validation::AnnotationProblem>, Error> {
+        let problems = self.validate();
+        let (cmds, repaired) = validation::repair_commands(&self.annotation_map, &problems);
+        if !cmds.is_empty() {
+            self.execute_batch(cmds)?;
+        }
+        Ok(repaired)
+    }
+
+    /// Numbers the shots under `shot_numbering`, as one undoable edit. See `shot_numbering::assign_numbers`.
+    ///
+    /// Returns how many shots got a new number; if none did, nothing is pushed onto the command history.
+    pub fn number_shots(&mut self) -> Result<usize, Error> {
+        let cmds = shot_numbering::numbering_commands(
+            &self.screenplay,
+            &self.annotation_map,
+            &self.shot_numbering,
+        );
+        let count = cmds.len();
+        if count > 0 {
+            self.execute_batch(cmds)?;
+        }
+        Ok(count)
+    }
+
+    /// Adds a shot and numbers it under `shot_numbering`, along with any shots
+    /// `NumberingMode::RenumberOnInsert` renumbers, as one undoable edit.
+    /// Under `NumberingMode::KeepStable` no other shot is touched; unnumbered ones are left for `number_shots`.
+    pub fn add_numbered_shotline(
+        &mut self,
+        id: production::ShotID,
+        mut shot: production::Shot,
+    ) -> Result<commands::CommandHistoryStatus, Error> {
+        if self.annotation_map.shotlines.contains_key(&id) {
+            return Err(Error);
+        }
+        let mut shots: Vec<(&production::ShotID, &production::Shot)> =
+            self.annotation_map.shotlines.iter().collect();
+        shots.push((&id, &shot));
+        let mut numbers =
+            shot_numbering::assign_numbers(&self.screenplay, &shots, &self.shot_numbering);
+
+        if let Some(number) = numbers.remove(&id) {
+            shot.shot_number = Some(number);
+        }
+        if self.shot_numbering.mode == shot_numbering::NumberingMode::KeepStable {
+            numbers.clear();
+        }
+        let mut renumbered: Vec<(production::ShotID, production::ShotNumber)> =
+            numbers.into_iter().collect();
+        renumbered.sort_by_key(|(id, _)| **id);
+        let mut cmds = vec![commands::Command::AddShotline(id, shot)];
+        for (id, number) in renumbered {
+            let Some(old_shot) = self.annotation_map.shotlines.get(&id) else {
+                continue;
+            };
+            let mut new_shot = old_shot.clone();
+            new_shot.shot_number = Some(number);
+            cmds.push(commands::Command::ModifyShotline(id, Some(new_shot)));
+        }
+        self.execute_batch(cmds)
+    }
+
+    /// Shot numbers that are used more than once or out of script order. See `shot_numbering::find_problems`.
+    pub fn shot_numbering_problems(&self) -> Vec<NumberingProblem> {
+        shot_numbering::find_problems(
+            &self.screenplay,
+            &self.annotation_map,
+            self.shot_numbering.scheme,
+        )
+    }
+
+    /// Anchors every ShotLine and TaggedElement where it is now, as one undoable edit,
+    /// so they can be found again with `resolve_anchors` once the screenplay reflows.
+    pub fn anchor_annotations(&mut self) -> Result<(), Error> {
+        let context = AnchorContext::new(&self.screenplay);
+        let mut cmds: Vec<commands::Command> = Vec::new();
+        for (id, shot) in &self.annotation_map.shotlines {
+            let Some(shotline) = &shot.shotline else {
+                continue;
+            };
+            let anchors = SmartRange::anchor(&context, &shotline.start, &shotline.end);
+            if anchors.is_some() && anchors != shotline.anchors {
+                let mut new_shot = shot.clone();
+                if let Some(new_shotline) = &mut new_shot.shotline {
+                    new_shotline.anchors = anchors;
+                }
+                cmds.push(commands::Command::ModifyShotline(id.clone(), Some(new_shot)));
+            }
+        }
+        for (id, te) in &self.annotation_map.tagged_elements {
+            let anchors = SmartRange::anchor(&context, &te.origin, &te.endpoint);
+            if anchors.is_some() && anchors != te.anchors {
+                let mut new_te = te.clone();
+                new_te.anchors = anchors;
+                cmds.push(commands::Command::ModifyTaggedElement(id.clone(), Some(new_te)));
+            }
+        }
+        if !cmds.is_empty() {
+            self.execute_batch(cmds)?;
+        }
+        Ok(())
+    }
+
+    /// Moves every anchored ShotLine and TaggedElement to wherever its anchors are in the current screenplay,
+    /// as one undoable edit.
+    ///
+    /// Only the ends are anchored: unfilmed lines, sub-compositions and other ocurrances are moved by as many lines
+    /// as the start (or origin) was, so `merge_forward` is the better choice while the old draft is still at hand.
+    /// Returns the annotations whose anchors couldn't be found; those are left alone.
+    pub fn resolve_anchors(&mut self) -> Result<Vec<AnnotationKey>, Error> {
+        let context = AnchorContext::new(&self.screenplay);
+        let mut cmds: Vec<commands::Command> = Vec::new();
+        let mut unresolved: Vec<AnnotationKey> = Vec::new();
+        for (id, shot) in &self.annotation_map.shotlines {
+            let Some(shotline) = &shot.shotline else {
+                continue;
+            };
+            let Some(anchors) = &shotline.anchors else {
+                continue;
+            };
+            match anchors.resolve(&context) {
+                Some((start, end)) => {
+                    if start != shotline.start || end != shotline.end {
+                        let shift = |coord: &screenplay_document::ScreenplayCoordinate| {
+                            context.shift(coord, &shotline.start, &start)
+                        };
+                        let mut new_shot = shot.clone();
+                        if let Some(sub_compositions) = &mut new_shot.sub_compositions {
+                            *sub_compositions = sub_compositions
+                                .drain()
+                                .map(|(coord, composition)| (shift(&coord), composition))
+                                .collect();
+                        }
+                        if let Some(new_shotline) = &mut new_shot.shotline {
+                            if let Some(unfilmed_lines) = &mut new_shotline.unfilmed_lines {
+                                *unfilmed_lines = unfilmed_lines.iter().map(shift).collect();
+                            }
+                            new_shotline.start = start;
+                            new_shotline.end = end;
+                        }
+                        cmds.push(commands::Command::ModifyShotline(id.clone(), Some(new_shot)));
+                    }
+                }
+                None => unresolved.push(AnnotationKey::Shot(id.clone())),
+            }
+        }
+        for (id, te) in &self.annotation_map.tagged_elements {
+            let Some(anchors) = &te.anchors else {
+                continue;
+            };
+            match anchors.resolve(&context) {
+                Some((origin, endpoint)) => {
+                    if origin != te.origin || endpoint != te.endpoint {
+                        let mut new_te = te.clone();
+                        new_te.ocurrances = te
+                            .ocurrances
+                            .iter()
+                            .map(|(start, end)| {
+                                if (start, end) == (&te.origin, &te.endpoint) {
+                                    (origin.clone(), endpoint.clone())
+                                } else {
+                                    (
+                                        context.shift(start, &te.origin, &origin),
+                                        context.shift(end, &te.origin, &origin),
+                                    )
+                                }
+                            })
+                            .collect();
+                        new_te.origin = origin;
+                        new_te.endpoint = endpoint;
+                        cmds.push(commands::Command::ModifyTaggedElement(id.clone(), Some(new_te)));
+                    }
+                }
+                None => unresolved.push(AnnotationKey::TaggedElement(id.clone())),
+            }
+        }
+        if !cmds.is_empty() {
+            self.execute_batch(cmds)?;
+        }
+        unresolved.sort_by_key(|key| match key {
+            AnnotationKey::Shot(id) => (0, **id),
+            AnnotationKey::TaggedElement(id) => (1, **id),
+        });
+        Ok(unresolved)
+    }
+
+    /// Applies each command of a batch in order.
+    /// If one fails, the already-applied commands are undone in reverse order.
+    fn batch_exec(&mut self, cmds: &mut [commands::Command]) -> Result<(), Error> {
+        for idx in 0..cmds.len() {
+            if let Err(e) = self.command_exec(&mut cmds[idx]) {
+                for applied in cmds[..idx].iter_mut().rev() {
+                    let _ = self.command_undo(applied);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes each command of a batch in reverse order.
+    /// If one fails, the already-undone commands are re-applied.
+    fn batch_undo(&mut self, cmds: &mut [commands::Command]) -> Result<(), Error> {
+        for idx in (0..cmds.len()).rev() {
+            if let Err(e) = self.command_undo(&mut cmds[idx]) {
+                for undone in cmds[idx + 1..].iter_mut() {
+                    let _ = self.command_exec(undone);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a command, storing whatever value it replaced or removed inside the command,
+    /// so that `command_undo` can put it back.
+    fn command_exec(&mut self, cmd: &mut commands::Command) -> Result<(), Error> {
         use commands::Command::*;
         match cmd {
             AddShotline(id, sl) => {
                 return self.add_shotline(sl.clone(), id.clone());
             }
             ModifyShotline(id, sl_opt) => {
+                let Some(new_sl) = sl_opt.clone() else {
+                    return Err(Error);
+                };
+                let Some(old_sl) = self.annotation_map.shotlines.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.modify_shotline(id, new_sl)?;
+                *sl_opt = Some(old_sl);
                 return Ok(());
             }
-            _ => {
-                return Err(Error);
+            RemoveShotline(id, sl_opt) => {
+                let Some(old_sl) = self.annotation_map.shotlines.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.remove_shotline(id)?;
+                *sl_opt = Some(old_sl);
+                return Ok(());
+            }
+            AddTag(id, tag) => {
+                return self.add_tag(tag.clone(), id.clone());
+            }
+            ModifyTag(id, tag_opt) => {
+                let Some(new_tag) = tag_opt.clone() else {
+                    return Err(Error);
+                };
+                let Some(old_tag) = self.annotation_map.tags.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.modify_tag(new_tag, id.clone())?;
+                *tag_opt = Some(old_tag);
+                return Ok(());
+            }
+            RemoveTag(id, tag_opt) => {
+                let Some(old_tag) = self.annotation_map.tags.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.remove_tag(id.clone())?;
+                *tag_opt = Some(old_tag);
+                return Ok(());
+            }
+            AddTaggedElement(id, te) => {
+                return self.add_tagged_element(id.clone(), te.clone());
+            }
+            ModifyTaggedElement(id, te_opt) => {
+                let Some(new_te) = te_opt.clone() else {
+                    return Err(Error);
+                };
+                let Some(old_te) = self.annotation_map.tagged_elements.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.modify_tagged_element(id.clone(), new_te)?;
+                *te_opt = Some(old_te);
+                return Ok(());
+            }
+            RemoveTaggedElement(id, te_opt) => {
+                let Some(old_te) = self.annotation_map.tagged_elements.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.remove_tagged_element(id.clone())?;
+                *te_opt = Some(old_te);
+                return Ok(());
+            }
+            AddGroup(id, group) => {
+                return self.add_group(id.clone(), group.clone());
+            }
+            ModifyGroup(id, group_opt) => {
+                let Some(new_group) = group_opt.clone() else {
+                    return Err(Error);
+                };
+                let Some(old_group) = self.annotation_map.groups.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.modify_group(id.clone(), new_group)?;
+                *group_opt = Some(old_group);
+                return Ok(());
+            }
+            RemoveGroup(id, group_opt) => {
+                let Some(old_group) = self.annotation_map.groups.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.remove_group(id.clone())?;
+                *group_opt = Some(old_group);
+                return Ok(());
+            }
+            AddShotSetup(id, setup) => {
+                return self.add_shot_setup(id.clone(), setup.clone());
+            }
+            ModifyShotSetup(id, setup_opt) => {
+                let Some(new_setup) = setup_opt.clone() else {
+                    return Err(Error);
+                };
+                let Some(old_setup) = self.annotation_map.shot_setups.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.modify_shot_setup(id.clone(), new_setup)?;
+                *setup_opt = Some(old_setup);
+                return Ok(());
+            }
+            RemoveShotSetup(id, setup_opt) => {
+                let Some(old_setup) = self.annotation_map.shot_setups.get(id).cloned() else {
+                    return Err(Error);
+                };
+                self.remove_shot_setup(id.clone())?;
+                *setup_opt = Some(old_setup);
+                return Ok(());
+            }
+            Batch(cmds) => {
+                return self.batch_exec(cmds);
             }
         }
-
-        Err(Error)
     }
 
-    fn command_undo(&mut self, cmd: &commands::Command) -> Result<(), Error> {
+    /// Inverts a command previously applied by `command_exec`.
+    fn command_undo(&mut self, cmd: &mut commands::Command) -> Result<(), Error> {
+        use commands::Command::*;
         match cmd {
-            commands::Command::AddShotline(id, sl) => {
-                let other_id = id.clone();
-                if let Ok(_) = self.remove_shotline((&other_id)) {
-                    return Ok(());
-                }
-                return Err(Error);
+            AddShotline(id, _) => {
+                return self.remove_shotline(id);
+            }
+            // Modifying is a swap, so undoing it is the same operation as executing it
+            ModifyShotline(_, _)
+            | ModifyTag(_, _)
+            | ModifyTaggedElement(_, _)
+            | ModifyGroup(_, _)
+            | ModifyShotSetup(_, _) => {
+                return self.command_exec(cmd);
+            }
+            RemoveShotline(id, sl_opt) => {
+                let Some(old_sl) = sl_opt.clone() else {
+                    return Err(Error);
+                };
+                return self.add_shotline(old_sl, id.clone());
             }
-            _ => {
-                return Err(Error);
+            AddTag(id, _) => {
+                return self.remove_tag(id.clone());
+            }
+            RemoveTag(id, tag_opt) => {
+                let Some(old_tag) = tag_opt.clone() else {
+                    return Err(Error);
+                };
+                return self.add_tag(old_tag, id.clone());
+            }
+            AddTaggedElement(id, _) => {
+                return self.remove_tagged_element(id.clone());
+            }
+            RemoveTaggedElement(id, te_opt) => {
+                let Some(old_te) = te_opt.clone() else {
+                    return Err(Error);
+                };
+                return self.add_tagged_element(id.clone(), old_te);
+            }
+            AddGroup(id, _) => {
+                return self.remove_group(id.clone());
+            }
+            RemoveGroup(id, group_opt) => {
+                let Some(old_group) = group_opt.clone() else {
+                    return Err(Error);
+                };
+                return self.add_group(id.clone(), old_group);
+            }
+            AddShotSetup(id, _) => {
+                return self.remove_shot_setup(id.clone());
+            }
+            RemoveShotSetup(id, setup_opt) => {
+                let Some(old_setup) = setup_opt.clone() else {
+                    return Err(Error);
+                };
+                return self.add_shot_setup(id.clone(), old_setup);
+            }
+            Batch(cmds) => {
+                return self.batch_undo(cmds);
             }
         }
     }
@@ -176,42 +933,92 @@ impl ShotlinerDoc {
         id: TaggedElementID,
         new_tagged_element: TaggedElement,
     ) -> Result<(), Error> {
-        if let None = self
-            .annotation_map
-            .tagged_elements
-            .insert(id, new_tagged_element)
-        {
-            return Ok(());
+        if self.annotation_map.tagged_elements.contains_key(&id) {
+            return Err(Error);
         }
-        Err(Error)
+        self.annotation_index.insert_tagged_element(&id, &new_tagged_element);
+        self.tag_usage_index.insert_tagged_element(&id, &new_tagged_element);
+        self.annotation_map.tagged_elements.insert(id, new_tagged_element);
+        Ok(())
     }
     pub fn modify_tagged_element(
         &mut self,
         id: TaggedElementID,
         new_tagged_element: TaggedElement,
     ) -> Result<(), Error> {
-        if let Some(_) = self
-            .annotation_map
-            .tagged_elements
-            .insert(id, new_tagged_element)
-        {
+        if self.annotation_map.tagged_elements.contains_key(&id) {
+            self.annotation_index.insert_tagged_element(&id, &new_tagged_element);
+            self.tag_usage_index.insert_tagged_element(&id, &new_tagged_element);
+            self.annotation_map.tagged_elements.insert(id, new_tagged_element);
             return Ok(());
         }
         Err(Error)
     }
     pub fn remove_tagged_element(&mut self, id: TaggedElementID) -> Result<(), Error> {
         if let Some(_) = self.annotation_map.tagged_elements.remove(&id) {
+            self.tag_usage_index.remove_tagged_element(&id);
+            self.annotation_index.remove(&AnnotationKey::TaggedElement(id));
             return Ok(());
         }
         Err(Error)
     }
 
-    pub fn add_tag(&mut self, tag: Tag, id: TagID) -> Result<(), Error> {
-        if let None = self.annotation_map.tags.insert(id, tag) {
+    pub fn add_group(&mut self, id: GroupID, group: Group) -> Result<(), Error> {
+        if self.annotation_map.groups.contains_key(&id) {
+            return Err(Error);
+        }
+        self.tag_usage_index.insert_group(&id, &group);
+        self.annotation_map.groups.insert(id, group);
+        Ok(())
+    }
+    pub fn modify_group(&mut self, id: GroupID, new_group: Group) -> Result<(), Error> {
+        if self.annotation_map.groups.contains_key(&id) {
+            self.tag_usage_index.insert_group(&id, &new_group);
+            self.annotation_map.groups.insert(id, new_group);
+            return Ok(());
+        }
+        Err(Error)
+    }
+    pub fn remove_group(&mut self, id: GroupID) -> Result<(), Error> {
+        if let Some(_) = self.annotation_map.groups.remove(&id) {
+            self.tag_usage_index.remove_group(&id);
+            return Ok(());
+        }
+        Err(Error)
+    }
+
+    pub fn add_shot_setup(&mut self, id: Uuid, setup: production::ShotSetup) -> Result<(), Error> {
+        if self.annotation_map.shot_setups.contains_key(&id) {
+            return Err(Error);
+        }
+        self.annotation_map.shot_setups.insert(id, setup);
+        Ok(())
+    }
+    pub fn modify_shot_setup(
+        &mut self,
+        id: Uuid,
+        new_setup: production::ShotSetup,
+    ) -> Result<(), Error> {
+        if self.annotation_map.shot_setups.contains_key(&id) {
+            self.annotation_map.shot_setups.insert(id, new_setup);
+            return Ok(());
+        }
+        Err(Error)
+    }
+    pub fn remove_shot_setup(&mut self, id: Uuid) -> Result<(), Error> {
+        if let Some(_) = self.annotation_map.shot_setups.remove(&id) {
             return Ok(());
         }
         Err(Error)
     }
+
+    pub fn add_tag(&mut self, tag: Tag, id: TagID) -> Result<(), Error> {
+        if self.annotation_map.tags.contains_key(&id) {
+            return Err(Error);
+        }
+        self.annotation_map.tags.insert(id, tag);
+        Ok(())
+    }
     pub fn modify_tag(&mut self, new_tag: Tag, id: TagID) -> Result<(), Error> {
         if self.annotation_map.tags.contains_key(&id) {
             self.annotation_map.tags.insert(id, new_tag);
@@ -250,10 +1057,13 @@ impl ShotlinerDoc {
         shotline: production::Shot,
         id: production::ShotID,
     ) -> Result<(), Error> {
-        if let None = self.annotation_map.shotlines.insert(id, shotline) {
-            return Ok(());
+        if self.annotation_map.shotlines.contains_key(&id) {
+            return Err(Error); // Tried to add a shotline that already has the UUID in the map!
         }
-        Err(Error) // Tried to add a shotline that already has the UUID in the map!
+        self.annotation_index.insert_shot(&id, &shotline);
+        self.tag_usage_index.insert_shot(&id, &shotline);
+        self.annotation_map.shotlines.insert(id, shotline);
+        Ok(())
     }
     pub fn modify_shotline(
         &mut self,
@@ -261,6 +1071,8 @@ impl ShotlinerDoc {
         new_shotline: production::Shot,
     ) -> Result<(), Error> {
         if let Some(_) = self.annotation_map.shotlines.get(id) {
+            self.annotation_index.insert_shot(id, &new_shotline);
+            self.tag_usage_index.insert_shot(id, &new_shotline);
             if let Some(_) = self
                 .annotation_map
                 .shotlines
@@ -274,6 +1086,8 @@ impl ShotlinerDoc {
     }
     pub fn remove_shotline(&mut self, id: &production::ShotID) -> Result<(), Error> {
         if let Some(_) = self.annotation_map.shotlines.remove(id) {
+            self.annotation_index.remove(&AnnotationKey::Shot(id.clone()));
+            self.tag_usage_index.remove_shot(id);
             return Ok(());
         }
 
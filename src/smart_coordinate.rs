@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use screenplay_doc_parser_rs::screenplay_document::{
+    Line, ScreenplayCoordinate, ScreenplayDocument,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::merge_forward::{SIMILARITY_THRESHOLD, normalize_text, text_similarity};
+use crate::reports;
+
+/// A 64-bit FNV-1a hash, which (unlike `std`'s hasher) stays the same between builds, so it can be saved.
+pub fn text_hash(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Which scene a SmartScreenplayCoordinate is in.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SceneAnchor {
+    /// The printed scene number, if there is one.
+    pub number: Option<String>,
+    /// The heading's normalized text.
+    pub heading: String,
+    /// How many scenes come before it.
+    pub index: usize,
+}
+
+/// A position in the screenplay, remembered by its content instead of by page and line,
+/// so it can be found again after the script reflows or is revised.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SmartScreenplayCoordinate {
+    /// `None` for lines before the first scene, e.g. on the title page.
+    pub scene: Option<SceneAnchor>,
+    /// How many lines into the scene (or the script, before the first scene), counting across page breaks.
+    pub line_in_scene: usize,
+    /// `text_hash` of the line's normalized text.
+    pub text_hash: u64,
+    /// The normalized text of the nearest non-blank lines before and after it.
+    pub previous_text: Option<String>,
+    pub next_text: Option<String>,
+    /// Where the text element starts, in characters into the normalized line. `None` for the whole line.
+    pub char_offset: Option<usize>,
+}
+impl SmartScreenplayCoordinate {
+    /// See `AnchorContext::anchor`.
+    pub fn anchor(screenplay: &ScreenplayDocument, coord: &ScreenplayCoordinate) -> Option<Self> {
+        AnchorContext::new(screenplay).anchor(coord)
+    }
+
+    /// See `AnchorContext::resolve`.
+    pub fn resolve(&self, screenplay: &ScreenplayDocument) -> Option<ScreenplayCoordinate> {
+        AnchorContext::new(screenplay).resolve(self)
+    }
+}
+
+/// Both ends of a range, as SmartScreenplayCoordinates.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SmartRange {
+    pub start: SmartScreenplayCoordinate,
+    pub end: SmartScreenplayCoordinate,
+}
+impl SmartRange {
+    pub fn anchor(
+        context: &AnchorContext,
+        start: &ScreenplayCoordinate,
+        end: &ScreenplayCoordinate,
+    ) -> Option<Self> {
+        Some(SmartRange {
+            start: context.anchor(start)?,
+            end: context.anchor(end)?,
+        })
+    }
+
+    /// `None` if either end can't be found, or the end now comes before the start.
+    pub fn resolve(
+        &self,
+        context: &AnchorContext,
+    ) -> Option<(ScreenplayCoordinate, ScreenplayCoordinate)> {
+        let start = context.resolve(&self.start)?;
+        let end = context.resolve(&self.end)?;
+        if (end.page, end.line) < (start.page, start.line) {
+            return None;
+        }
+        Some((start, end))
+    }
+}
+
+struct ContextLine {
+    position: (usize, usize),
+    text: String,
+    hash: u64,
+    /// Index into `AnchorContext::scenes`.
+    scene: Option<usize>,
+    line_in_scene: usize,
+    previous_text: Option<String>,
+    next_text: Option<String>,
+}
+
+/// Everything about one draft of the screenplay that anchoring and resolving needs, worked out once,
+/// for when there are many coordinates to go through.
+pub struct AnchorContext {
+    lines: Vec<ContextLine>,
+    by_position: HashMap<(usize, usize), usize>,
+    by_hash: HashMap<u64, Vec<usize>>,
+    scenes: Vec<SceneAnchor>,
+    /// Index into `lines` of each page's first line.
+    page_starts: Vec<usize>,
+    /// The start of each text element in the normalized line text, by line; `None` for elements with no text.
+    element_offsets: HashMap<(usize, usize), Vec<Option<usize>>>,
+}
+impl AnchorContext {
+    pub fn new(screenplay: &ScreenplayDocument) -> Self {
+        let extents = reports::get_scene_extents(screenplay);
+        let mut scene_starts: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut scenes: Vec<SceneAnchor> = Vec::new();
+        for (index, (_, scene, _)) in extents.iter().enumerate() {
+            scene_starts.insert((scene.start.page, scene.start.line), index);
+            let heading = screenplay
+                .pages
+                .get(scene.start.page)
+                .and_then(|page| page.lines.get(scene.start.line))
+                .map(|line| normalize_text(&reports::get_line_text(line)))
+                .unwrap_or_default();
+            scenes.push(SceneAnchor {
+                number: scene.number.as_ref().map(|number| number.0.clone()),
+                heading: heading,
+                index: index,
+            });
+        }
+
+        let mut lines: Vec<ContextLine> = Vec::new();
+        let mut element_offsets: HashMap<(usize, usize), Vec<Option<usize>>> = HashMap::new();
+        let mut scene: Option<usize> = None;
+        let mut line_in_scene = 0;
+        let mut page_starts: Vec<usize> = Vec::new();
+        for (page_index, page) in screenplay.pages.iter().enumerate() {
+            page_starts.push(lines.len());
+            for (line_index, line) in page.lines.iter().enumerate() {
+                let position = (page_index, line_index);
+                if let Some(index) = scene_starts.get(&position) {
+                    scene = Some(*index);
+                    line_in_scene = 0;
+                }
+                let text = normalize_text(&reports::get_line_text(line));
+                element_offsets.insert(position, get_element_offsets(line));
+                lines.push(ContextLine {
+                    position: position,
+                    hash: text_hash(&text),
+                    text: text,
+                    scene: scene,
+                    line_in_scene: line_in_scene,
+                    previous_text: None,
+                    next_text: None,
+                });
+                line_in_scene += 1;
+            }
+        }
+
+        let mut previous: Option<String> = None;
+        for line in lines.iter_mut() {
+            line.previous_text = previous.clone();
+            if !line.text.is_empty() {
+                previous = Some(line.text.clone());
+            }
+        }
+        let mut next: Option<String> = None;
+        for line in lines.iter_mut().rev() {
+            line.next_text = next.clone();
+            if !line.text.is_empty() {
+                next = Some(line.text.clone());
+            }
+        }
+
+        let mut by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, line) in lines.iter().enumerate() {
+            by_hash.entry(line.hash).or_default().push(index);
+        }
+        AnchorContext {
+            by_position: lines
+                .iter()
+                .enumerate()
+                .map(|(index, line)| (line.position, index))
+                .collect(),
+            by_hash: by_hash,
+            lines: lines,
+            scenes: scenes,
+            page_starts: page_starts,
+            element_offsets: element_offsets,
+        }
+    }
+
+    /// `coord` moved by as many lines as it takes to get from `from` to `to`, counting across page breaks.
+    ///
+    /// For carrying along the coordinates inside an annotation once its ends have been resolved.
+    /// Anything moved off either end of the screenplay is kept at that end.
+    pub fn shift(
+        &self,
+        coord: &ScreenplayCoordinate,
+        from: &ScreenplayCoordinate,
+        to: &ScreenplayCoordinate,
+    ) -> ScreenplayCoordinate {
+        let Some(last) = self.lines.len().checked_sub(1) else {
+            return coord.clone();
+        };
+        let index = |coord: &ScreenplayCoordinate| {
+            let page_start = self.page_starts.get(coord.page).copied().unwrap_or(self.lines.len());
+            (page_start + coord.line) as i64
+        };
+        let shifted = index(coord) + index(to) - index(from);
+        let (page, line) = self.lines[shifted.clamp(0, last as i64) as usize].position;
+        ScreenplayCoordinate {
+            page: page,
+            line: line,
+            element: coord.element,
+        }
+    }
+
+    /// A SmartScreenplayCoordinate for `coord`, or `None` if the screenplay doesn't have that line.
+    pub fn anchor(&self, coord: &ScreenplayCoordinate) -> Option<SmartScreenplayCoordinate> {
+        let line = &self.lines[*self.by_position.get(&(coord.page, coord.line))?];
+        let char_offset = coord.element.and_then(|element| {
+            let offsets = &self.element_offsets[&line.position];
+            // an element without any text starts where the next one with text does
+            offsets
+                .iter()
+                .skip(element as usize)
+                .find_map(|offset| *offset)
+                .or(Some(line.text.chars().count()))
+        });
+        Some(SmartScreenplayCoordinate {
+            scene: line.scene.map(|index| self.scenes[index].clone()),
+            line_in_scene: line.line_in_scene,
+            text_hash: line.hash,
+            previous_text: line.previous_text.clone(),
+            next_text: line.next_text.clone(),
+            char_offset: char_offset,
+        })
+    }
+
+    /// How well a line of this draft fits a SmartScreenplayCoordinate; higher is better.
+    fn score(&self, smart: &SmartScreenplayCoordinate, line: &ContextLine) -> (u8, u8, i64, i64) {
+        let scene = line.scene.map(|index| &self.scenes[index]);
+        let scene_match = match (&smart.scene, scene) {
+            (None, None) => 2,
+            (Some(a), Some(b)) if a.number.is_some() && a.number == b.number => 2,
+            (Some(a), Some(b)) if a.heading == b.heading => 2,
+            (Some(a), Some(b))
+                if text_similarity(&a.heading, &b.heading) >= SIMILARITY_THRESHOLD =>
+            {
+                1
+            }
+            _ => 0,
+        };
+        let neighbors = (smart.previous_text == line.previous_text) as u8
+            + (smart.next_text == line.next_text) as u8;
+        let scene_distance = match (&smart.scene, scene) {
+            (Some(a), Some(b)) => a.index.abs_diff(b.index) as i64,
+            _ => 0,
+        };
+        (
+            scene_match,
+            neighbors,
+            -(smart.line_in_scene.abs_diff(line.line_in_scene) as i64),
+            -scene_distance,
+        )
+    }
+
+    /// The line in this draft that best fits `smart`: one with the same text if there is any,
+    /// preferring the same scene, then the same neighbors, then the same place in the scene.
+    /// If the line's text changed, a line with the same text on both sides of it is taken instead.
+    ///
+    /// `None` if there's no line it could be.
+    pub fn resolve(&self, smart: &SmartScreenplayCoordinate) -> Option<ScreenplayCoordinate> {
+        let candidates: Vec<&ContextLine> = match self.by_hash.get(&smart.text_hash) {
+            Some(indices) => indices.iter().map(|index| &self.lines[*index]).collect(),
+            None => self
+                .lines
+                .iter()
+                .filter(|line| {
+                    smart.previous_text.is_some()
+                        && smart.next_text.is_some()
+                        && line.previous_text == smart.previous_text
+                        && line.next_text == smart.next_text
+                })
+                .collect(),
+        };
+        // on a tie, the earliest line wins
+        let line = candidates
+            .into_iter()
+            .rev()
+            .max_by_key(|line| self.score(smart, line))?;
+
+        let element = smart.char_offset.map(|char_offset| {
+            self.element_offsets[&line.position]
+                .iter()
+                .enumerate()
+                .filter_map(|(index, offset)| Some((index, (*offset)?)))
+                .take_while(|(_, offset)| *offset <= char_offset)
+                .last()
+                .map(|(index, _)| index as u64)
+                .unwrap_or(0)
+        });
+        Some(ScreenplayCoordinate {
+            page: line.position.0,
+            line: line.position.1,
+            element: element,
+        })
+    }
+}
+
+/// Where each text element starts in the line's normalized text (see `normalize_text`).
+fn get_element_offsets(line: &Line) -> Vec<Option<usize>> {
+    let mut offsets: Vec<Option<usize>> = Vec::new();
+    let mut length = 0;
+    for element in &line.text_elements {
+        let text = normalize_text(&element.text);
+        if text.is_empty() {
+            offsets.push(None);
+            continue;
+        }
+        if length > 0 {
+            length += 1;
+        }
+        offsets.push(Some(length));
+        length += text.chars().count();
+    }
+    offsets
+}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use screenplay_doc_parser_rs::screenplay_document::{ScreenplayCoordinate, ScreenplayDocument};
+
+use crate::annotation_index::{AnnotationKey, TagUsage};
+use crate::commands::Command;
+use crate::production::{self, ShotID};
+use crate::shotliner_document::{
+    AnnotationMap, Group, GroupID, ShotLine, TagID, TaggedElement, TaggedElementID,
+};
+
+/// Which coordinate of an annotation a problem is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CoordinatePlace {
+    ShotlineStart,
+    ShotlineEnd,
+    UnfilmedLine,
+    SubComposition,
+    Origin,
+    Endpoint,
+    Ocurrance,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnnotationProblem {
+    /// `tag_id` is referenced, but isn't in the AnnotationMap.
+    DanglingTag { usage: TagUsage, tag_id: TagID },
+    /// A ShotLine whose end comes before its start.
+    ShotlineReversed(ShotID),
+    /// A TaggedElement whose endpoint comes before its origin.
+    TaggedElementReversed(TaggedElementID),
+    /// A page, line or element the screenplay doesn't have.
+    CoordinateOutOfBounds {
+        annotation: AnnotationKey,
+        place: CoordinatePlace,
+        coordinate: ScreenplayCoordinate,
+    },
+    /// An unfilmed line that isn't inside the shot's ShotLine.
+    UnfilmedLineOutsideShotline {
+        shot_id: ShotID,
+        coordinate: ScreenplayCoordinate,
+    },
+    /// A sub-composition keyed to a coordinate outside the shot's ShotLine.
+    SubCompositionOutsideShotline {
+        shot_id: ShotID,
+        coordinate: ScreenplayCoordinate,
+    },
+    /// A composition using a ShotSetup that isn't one of the AnnotationMap's `shot_setups`.
+    UnknownSetup {
+        shot_id: ShotID,
+        setup: production::ShotSetup,
+    },
+}
+impl AnnotationProblem {
+    /// Whether `repair` can fix this without a human deciding what was meant.
+    ///
+    /// Dangling tags and stray unfilmed lines or ocurrances are removed, and reversed ranges are swapped.
+    /// Anything else would mean guessing where an annotation belongs, or throwing away work.
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            AnnotationProblem::DanglingTag { .. }
+            | AnnotationProblem::ShotlineReversed(_)
+            | AnnotationProblem::TaggedElementReversed(_)
+            | AnnotationProblem::UnfilmedLineOutsideShotline { .. } => true,
+            AnnotationProblem::CoordinateOutOfBounds { place, .. } => {
+                matches!(
+                    place,
+                    CoordinatePlace::UnfilmedLine | CoordinatePlace::Ocurrance
+                )
+            }
+            AnnotationProblem::SubCompositionOutsideShotline { .. }
+            | AnnotationProblem::UnknownSetup { .. } => false,
+        }
+    }
+}
+impl fmt::Display for AnnotationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let coord = |c: &ScreenplayCoordinate| match c.element {
+            Some(element) => format!("{}:{}:{}", c.page, c.line, element),
+            None => format!("{}:{}", c.page, c.line),
+        };
+        match self {
+            AnnotationProblem::DanglingTag { usage, tag_id } => {
+                write!(f, "{:?} references missing tag {}", usage, **tag_id)
+            }
+            AnnotationProblem::ShotlineReversed(id) => {
+                write!(f, "shot {} ends before it starts", **id)
+            }
+            AnnotationProblem::TaggedElementReversed(id) => {
+                write!(f, "tagged element {} ends before it starts", **id)
+            }
+            AnnotationProblem::CoordinateOutOfBounds {
+                annotation,
+                place,
+                coordinate,
+            } => write!(
+                f,
+                "{:?} {:?} {} is outside the screenplay",
+                annotation,
+                place,
+                coord(coordinate)
+            ),
+            AnnotationProblem::UnfilmedLineOutsideShotline {
+                shot_id,
+                coordinate,
+            } => write!(
+                f,
+                "shot {} has unfilmed line {} outside its shotline",
+                **shot_id,
+                coord(coordinate)
+            ),
+            AnnotationProblem::SubCompositionOutsideShotline {
+                shot_id,
+                coordinate,
+            } => write!(
+                f,
+                "shot {} has a sub-composition at {} outside its shotline",
+                **shot_id,
+                coord(coordinate)
+            ),
+            AnnotationProblem::UnknownSetup { shot_id, setup } => write!(
+                f,
+                "shot {} uses unknown setup {} ({})",
+                **shot_id, setup.index, setup.id
+            ),
+        }
+    }
+}
+
+/// Whether the screenplay has this page, line and (if given) text element.
+pub fn coordinate_in_bounds(screenplay: &ScreenplayDocument, coord: &ScreenplayCoordinate) -> bool {
+    let Some(line) = screenplay
+        .pages
+        .get(coord.page)
+        .and_then(|page| page.lines.get(coord.line))
+    else {
+        return false;
+    };
+    match coord.element {
+        Some(element) => (element as usize) < line.text_elements.len(),
+        None => true,
+    }
+}
+
+fn position(coord: &ScreenplayCoordinate) -> (usize, usize, u64) {
+    (coord.page, coord.line, coord.element.unwrap_or(0))
+}
+
+fn in_shotline(shotline: &ShotLine, coord: &ScreenplayCoordinate) -> bool {
+    let line = (coord.page, coord.line);
+    (shotline.start.page, shotline.start.line) <= line
+        && line <= (shotline.end.page, shotline.end.line)
+}
+
+fn sorted_by_id<K: std::ops::Deref<Target = uuid::Uuid>, V>(map: &HashMap<K, V>) -> Vec<(&K, &V)> {
+    let mut entries: Vec<(&K, &V)> = map.iter().collect();
+    entries.sort_by_key(|(id, _)| ***id);
+    entries
+}
+
+fn validate_shot(
+    screenplay: &ScreenplayDocument,
+    annotation_map: &AnnotationMap,
+    id: &ShotID,
+    shot: &production::Shot,
+    problems: &mut Vec<AnnotationProblem>,
+) {
+    let mut compositions: Vec<(TagUsage, &production::ShotComposition)> =
+        vec![(TagUsage::Shot(id.clone()), &shot.primary_composition)];
+    let mut sub_compositions: Vec<(&ScreenplayCoordinate, &production::ShotComposition)> =
+        shot.sub_compositions.iter().flatten().collect();
+    sub_compositions.sort_by_key(|(coord, _)| position(coord));
+    for (coord, composition) in &sub_compositions {
+        compositions.push((
+            TagUsage::SubComposition(id.clone(), (*coord).clone()),
+            composition,
+        ));
+    }
+
+    for (usage, composition) in compositions {
+        for tag_id in &composition.tags {
+            if !annotation_map.tags.contains_key(tag_id) {
+                problems.push(AnnotationProblem::DanglingTag {
+                    usage: usage.clone(),
+                    tag_id: tag_id.clone(),
+                });
+            }
+        }
+        if let Some(setup) = &composition.setup
+            && !annotation_map
+                .shot_setups
+                .values()
+                .any(|known| known == setup)
+        {
+            problems.push(AnnotationProblem::UnknownSetup {
+                shot_id: id.clone(),
+                setup: setup.clone(),
+            });
+        }
+    }
+
+    let Some(shotline) = &shot.shotline else {
+        return;
+    };
+    let out_of_bounds = |place: CoordinatePlace, coordinate: &ScreenplayCoordinate| {
+        AnnotationProblem::CoordinateOutOfBounds {
+            annotation: AnnotationKey::Shot(id.clone()),
+            place: place,
+            coordinate: coordinate.clone(),
+        }
+    };
+    if !coordinate_in_bounds(screenplay, &shotline.start) {
+        problems.push(out_of_bounds(
+            CoordinatePlace::ShotlineStart,
+            &shotline.start,
+        ));
+    }
+    if !coordinate_in_bounds(screenplay, &shotline.end) {
+        problems.push(out_of_bounds(CoordinatePlace::ShotlineEnd, &shotline.end));
+    }
+    if position(&shotline.end) < position(&shotline.start) {
+        problems.push(AnnotationProblem::ShotlineReversed(id.clone()));
+    }
+    // a reversed shotline gets swapped by `repair`, so lines are checked against the range it will have
+    let mut range = shotline.clone();
+    if position(&range.end) < position(&range.start) {
+        std::mem::swap(&mut range.start, &mut range.end);
+    }
+
+    let mut unfilmed: Vec<&ScreenplayCoordinate> =
+        shotline.unfilmed_lines.iter().flatten().collect();
+    unfilmed.sort_by_key(|coord| position(coord));
+    for coord in unfilmed {
+        if !coordinate_in_bounds(screenplay, coord) {
+            problems.push(out_of_bounds(CoordinatePlace::UnfilmedLine, coord));
+        } else if !in_shotline(&range, coord) {
+            problems.push(AnnotationProblem::UnfilmedLineOutsideShotline {
+                shot_id: id.clone(),
+                coordinate: coord.clone(),
+            });
+        }
+    }
+    for (coord, _) in sub_compositions {
+        if !coordinate_in_bounds(screenplay, coord) {
+            problems.push(out_of_bounds(CoordinatePlace::SubComposition, coord));
+        } else if !in_shotline(&range, coord) {
+            problems.push(AnnotationProblem::SubCompositionOutsideShotline {
+                shot_id: id.clone(),
+                coordinate: coord.clone(),
+            });
+        }
+    }
+}
+
+fn validate_tagged_element(
+    screenplay: &ScreenplayDocument,
+    annotation_map: &AnnotationMap,
+    id: &TaggedElementID,
+    tagged_element: &TaggedElement,
+    problems: &mut Vec<AnnotationProblem>,
+) {
+    for tag_id in &tagged_element.tags {
+        if !annotation_map.tags.contains_key(tag_id) {
+            problems.push(AnnotationProblem::DanglingTag {
+                usage: TagUsage::TaggedElement(id.clone()),
+                tag_id: tag_id.clone(),
+            });
+        }
+    }
+    if position(&tagged_element.endpoint) < position(&tagged_element.origin) {
+        problems.push(AnnotationProblem::TaggedElementReversed(id.clone()));
+    }
+
+    let mut coordinates = vec![
+        (CoordinatePlace::Origin, &tagged_element.origin),
+        (CoordinatePlace::Endpoint, &tagged_element.endpoint),
+    ];
+    let mut ocurrances: Vec<&(ScreenplayCoordinate, ScreenplayCoordinate)> =
+        tagged_element.ocurrances.iter().collect();
+    ocurrances.sort_by_key(|(start, end)| (position(start), position(end)));
+    for (start, end) in ocurrances {
+        coordinates.push((CoordinatePlace::Ocurrance, start));
+        if end != start {
+            coordinates.push((CoordinatePlace::Ocurrance, end));
+        }
+    }
+    for (place, coordinate) in coordinates {
+        if !coordinate_in_bounds(screenplay, coordinate) {
+            problems.push(AnnotationProblem::CoordinateOutOfBounds {
+                annotation: AnnotationKey::TaggedElement(id.clone()),
+                place: place,
+                coordinate: coordinate.clone(),
+            });
+        }
+    }
+}
+
+/// Checks every shot, TaggedElement and Group against the rest of the AnnotationMap and the screenplay.
+///
+/// Problems come out shots first, then tagged elements, then groups, each sorted by ID.
+pub fn validate(
+    screenplay: &ScreenplayDocument,
+    annotation_map: &AnnotationMap,
+) -> Vec<AnnotationProblem> {
+    let mut problems: Vec<AnnotationProblem> = Vec::new();
+    for (id, shot) in sorted_by_id(&annotation_map.shotlines) {
+        validate_shot(screenplay, annotation_map, id, shot, &mut problems);
+    }
+    for (id, tagged_element) in sorted_by_id(&annotation_map.tagged_elements) {
+        validate_tagged_element(
+            screenplay,
+            annotation_map,
+            id,
+            tagged_element,
+            &mut problems,
+        );
+    }
+    for (id, group) in sorted_by_id(&annotation_map.groups) {
+        let mut tags: Vec<&TagID> = group.tags.iter().collect();
+        tags.sort_by_key(|tag_id| ***tag_id);
+        for tag_id in tags {
+            if !annotation_map.tags.contains_key(tag_id) {
+                problems.push(AnnotationProblem::DanglingTag {
+                    usage: TagUsage::Group(id.clone()),
+                    tag_id: tag_id.clone(),
+                });
+            }
+        }
+    }
+    problems
+}
+
+/// The commands that fix every repairable problem: one modify command for each annotation that needs fixing.
+///
+/// Returns the commands along with the problems they fix.
+pub fn repair_commands(
+    annotation_map: &AnnotationMap,
+    problems: &[AnnotationProblem],
+) -> (Vec<Command>, Vec<AnnotationProblem>) {
+    let mut shots: Vec<(ShotID, production::Shot)> = Vec::new();
+    let mut tagged_elements: Vec<(TaggedElementID, TaggedElement)> = Vec::new();
+    let mut groups: Vec<(GroupID, Group)> = Vec::new();
+    let mut repaired: Vec<AnnotationProblem> = Vec::new();
+
+    fn edit<'a, K: Eq + std::hash::Hash + Clone, V: Clone>(
+        edits: &'a mut Vec<(K, V)>,
+        original: &HashMap<K, V>,
+        id: &K,
+    ) -> Option<&'a mut V> {
+        let index = match edits.iter().position(|(edited, _)| edited == id) {
+            Some(index) => index,
+            None => {
+                edits.push((id.clone(), original.get(id)?.clone()));
+                edits.len() - 1
+            }
+        };
+        Some(&mut edits[index].1)
+    }
+
+    /// `Vec::retain`, returning whether anything was removed.
+    fn retain_changed<T>(items: &mut Vec<T>, keep: impl FnMut(&T) -> bool) -> bool {
+        let len = items.len();
+        items.retain(keep);
+        items.len() != len
+    }
+
+    for problem in problems.iter().filter(|problem| problem.is_repairable()) {
+        let fixed = match problem {
+            AnnotationProblem::DanglingTag { usage, tag_id } => match usage {
+                TagUsage::TaggedElement(id) => {
+                    edit(&mut tagged_elements, &annotation_map.tagged_elements, id)
+                        .is_some_and(|te| retain_changed(&mut te.tags, |t| t != tag_id))
+                }
+                TagUsage::Shot(id) => edit(&mut shots, &annotation_map.shotlines, id)
+                    .is_some_and(|shot| {
+                        retain_changed(&mut shot.primary_composition.tags, |t| t != tag_id)
+                    }),
+                TagUsage::SubComposition(id, coord) => {
+                    edit(&mut shots, &annotation_map.shotlines, id)
+                        .and_then(|shot| shot.sub_compositions.as_mut()?.get_mut(coord))
+                        .is_some_and(|composition| {
+                            retain_changed(&mut composition.tags, |t| t != tag_id)
+                        })
+                }
+                TagUsage::Group(id) => edit(&mut groups, &annotation_map.groups, id)
+                    .is_some_and(|group| group.tags.remove(tag_id)),
+            },
+            AnnotationProblem::ShotlineReversed(id) => {
+                edit(&mut shots, &annotation_map.shotlines, id)
+                    .and_then(|shot| shot.shotline.as_mut())
+                    .filter(|shotline| position(&shotline.end) < position(&shotline.start))
+                    .map(|shotline| std::mem::swap(&mut shotline.start, &mut shotline.end))
+                    .is_some()
+            }
+            AnnotationProblem::TaggedElementReversed(id) => {
+                edit(&mut tagged_elements, &annotation_map.tagged_elements, id)
+                    .filter(|te| position(&te.endpoint) < position(&te.origin))
+                    .map(|te| std::mem::swap(&mut te.origin, &mut te.endpoint))
+                    .is_some()
+            }
+            AnnotationProblem::UnfilmedLineOutsideShotline {
+                shot_id: id,
+                coordinate,
+            }
+            | AnnotationProblem::CoordinateOutOfBounds {
+                annotation: AnnotationKey::Shot(id),
+                place: CoordinatePlace::UnfilmedLine,
+                coordinate,
+            } => edit(&mut shots, &annotation_map.shotlines, id)
+                .and_then(|shot| shot.shotline.as_mut()?.unfilmed_lines.as_mut())
+                .is_some_and(|lines| lines.remove(coordinate)),
+            AnnotationProblem::CoordinateOutOfBounds {
+                annotation: AnnotationKey::TaggedElement(id),
+                place: CoordinatePlace::Ocurrance,
+                coordinate,
+            } => edit(&mut tagged_elements, &annotation_map.tagged_elements, id)
+                .is_some_and(|te| {
+                    let len = te.ocurrances.len();
+                    te.ocurrances
+                        .retain(|(a, b)| a != coordinate && b != coordinate);
+                    te.ocurrances.len() != len
+                }),
+            _ => false,
+        };
+        if fixed {
+            repaired.push(problem.clone());
+        }
+    }
+
+    // Only keep edits that ended up different from the original.
+    let mut cmds: Vec<Command> = Vec::new();
+    for (id, shot) in shots {
+        if annotation_map.shotlines.get(&id) != Some(&shot) {
+            cmds.push(Command::ModifyShotline(id, Some(shot)));
+        }
+    }
+    for (id, tagged_element) in tagged_elements {
+        if annotation_map.tagged_elements.get(&id) != Some(&tagged_element) {
+            cmds.push(Command::ModifyTaggedElement(id, Some(tagged_element)));
+        }
+    }
+    for (id, group) in groups {
+        if annotation_map.groups.get(&id) != Some(&group) {
+            cmds.push(Command::ModifyGroup(id, Some(group)));
+        }
+    }
+    (cmds, repaired)
+}